@@ -0,0 +1,133 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::{Map, Value, json};
+use yellowstone_grpc_proto::prelude::{SubscribeUpdateAccount, SubscribeUpdateAccountInfo};
+
+use crate::discriminators::hex;
+
+/// A field of an account update, selectable with `--account-fields`. The
+/// names are the JSONL keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum AccountField {
+    Pubkey,
+    Slot,
+    Lamports,
+    Owner,
+    /// The full account data, hex-encoded
+    Data,
+    /// Length of the account data in bytes
+    DataLen,
+    Executable,
+    RentEpoch,
+    WriteVersion,
+    TxnSignature,
+    IsStartup,
+}
+
+/// Logged when `--account-fields` isn't given: enough to follow balances
+/// without dumping data.
+const DEFAULT_LOG_FIELDS: [AccountField; 4] =
+    [AccountField::Pubkey, AccountField::Slot, AccountField::Lamports, AccountField::IsStartup];
+
+/// Written to JSONL when `--account-fields` isn't given, as before the
+/// option existed.
+const DEFAULT_JSON_FIELDS: [AccountField; 8] = [
+    AccountField::Slot,
+    AccountField::Pubkey,
+    AccountField::Owner,
+    AccountField::Lamports,
+    AccountField::Data,
+    AccountField::WriteVersion,
+    AccountField::TxnSignature,
+    AccountField::IsStartup,
+];
+
+/// The `--account-fields` selection; log lines follow its order.
+#[derive(Debug, Clone, Default)]
+pub struct AccountFields {
+    selected: Vec<AccountField>,
+}
+
+impl AccountFields {
+    pub fn new(selected: &[AccountField]) -> Self {
+        let mut unique = Vec::new();
+        for field in selected {
+            if !unique.contains(field) {
+                unique.push(*field);
+            }
+        }
+        Self { selected: unique }
+    }
+
+    fn for_log(&self) -> &[AccountField] {
+        if self.selected.is_empty() { &DEFAULT_LOG_FIELDS } else { &self.selected }
+    }
+
+    fn for_json(&self) -> &[AccountField] {
+        if self.selected.is_empty() { &DEFAULT_JSON_FIELDS } else { &self.selected }
+    }
+
+    /// The `key=value` list of an `Account update:` log line. A startup
+    /// update is flagged as just `startup`, and only when it is one.
+    pub fn log_line(&self, update: &SubscribeUpdateAccount, info: &SubscribeUpdateAccountInfo) -> String {
+        let mut parts = Vec::new();
+        for field in self.for_log() {
+            match field {
+                AccountField::IsStartup if update.is_startup => parts.push("startup".to_string()),
+                AccountField::IsStartup => {}
+                _ => parts.push(format!("{}={}", name(*field), display(&value(*field, update, info)))),
+            }
+        }
+        parts.join(", ")
+    }
+
+    /// The selected fields as JSON, for the JSONL record.
+    pub fn json(&self, update: &SubscribeUpdateAccount, info: &SubscribeUpdateAccountInfo) -> Map<String, Value> {
+        self.for_json()
+            .iter()
+            .map(|field| (name(*field).to_string(), value(*field, update, info)))
+            .collect()
+    }
+}
+
+fn name(field: AccountField) -> &'static str {
+    match field {
+        AccountField::Pubkey => "pubkey",
+        AccountField::Slot => "slot",
+        AccountField::Lamports => "lamports",
+        AccountField::Owner => "owner",
+        AccountField::Data => "data",
+        AccountField::DataLen => "data_len",
+        AccountField::Executable => "executable",
+        AccountField::RentEpoch => "rent_epoch",
+        AccountField::WriteVersion => "write_version",
+        AccountField::TxnSignature => "txn_signature",
+        AccountField::IsStartup => "is_startup",
+    }
+}
+
+fn value(field: AccountField, update: &SubscribeUpdateAccount, info: &SubscribeUpdateAccountInfo) -> Value {
+    match field {
+        AccountField::Pubkey => json!(bs58::encode(&info.pubkey).into_string()),
+        AccountField::Slot => json!(update.slot),
+        AccountField::Lamports => json!(info.lamports),
+        AccountField::Owner => json!(bs58::encode(&info.owner).into_string()),
+        AccountField::Data => json!(hex(&info.data)),
+        AccountField::DataLen => json!(info.data.len()),
+        AccountField::Executable => json!(info.executable),
+        AccountField::RentEpoch => json!(info.rent_epoch),
+        AccountField::WriteVersion => json!(info.write_version),
+        AccountField::TxnSignature => json!(info.txn_signature.as_ref().map(|sig| bs58::encode(sig).into_string())),
+        AccountField::IsStartup => json!(update.is_startup),
+    }
+}
+
+/// Strings without their JSON quotes.
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
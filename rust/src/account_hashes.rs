@@ -0,0 +1,25 @@
+use std::collections::HashMap;
+use xxhash_rust::xxh3::xxh3_64;
+
+/// Stable 64-bit hash of account data, identical across runs and builds.
+pub fn data_hash(data: &[u8]) -> u64 {
+    xxh3_64(data)
+}
+
+/// Remembers the data hash last seen for each account.
+#[derive(Debug, Default)]
+pub struct DataHashTracker {
+    hashes: HashMap<Vec<u8>, u64>,
+}
+
+impl DataHashTracker {
+    /// Records the account's current data and returns true if it differs
+    /// from the previous update. First-seen accounts count as changed.
+    pub fn update(&mut self, pubkey: &[u8], data: &[u8]) -> bool {
+        let hash = data_hash(data);
+        match self.hashes.insert(pubkey.to_vec(), hash) {
+            Some(previous) => previous != hash,
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{Context, Result};
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use yellowstone_grpc_proto::prelude::{CommitmentLevel, SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::discriminators::hex;
+
+/// The latest update of one account.
+struct Latest {
+    slot: u64,
+    write_version: u64,
+    owner: Vec<u8>,
+    lamports: u64,
+    executable: bool,
+    rent_epoch: u64,
+    data: Vec<u8>,
+}
+
+/// An account's latest update at each commitment level, indexed by the
+/// level.
+type States = [Option<Arc<Latest>>; 3];
+
+/// The latest known state of each subscribed account, for the HTTP
+/// `/account/<pubkey>` endpoint.
+///
+/// The set of keys is fixed when the cache is built, so memory stays bounded
+/// by the subscription. Each entry is replaced whole, and readers clone the
+/// `Arc` out of a briefly held lock, so a query never sees a half-applied
+/// update and never holds up the stream.
+///
+/// Each account has a state per commitment level. Only the levels the accounts are subscribed at are filled in; updates
+/// are told apart by the filter they matched.
+pub struct AccountState {
+    accounts: HashMap<Vec<u8>, Mutex<States>>,
+    /// Commitment of each per-level account filter
+    filters: HashMap<String, CommitmentLevel>,
+    /// For updates matching none of `filters`, and queries without a level
+    default: CommitmentLevel,
+    tracked: Vec<CommitmentLevel>,
+}
+
+/// Name of the account filter subscribing at `level` for
+/// `--account-cache-commitments`.
+pub fn filter_name(level: CommitmentLevel) -> String {
+    format!("accounts_file_{}", level.as_str_name().to_lowercase())
+}
+
+impl AccountState {
+    /// A cache of the given base58 pubkeys, all initially unknown, kept at
+    /// `default` and at each of `extra`.
+    pub fn new(pubkeys: &[String], default: CommitmentLevel, extra: &[CommitmentLevel]) -> Result<Self> {
+        let accounts = pubkeys
+            .iter()
+            .map(|pubkey| {
+                let key = bs58::decode(pubkey)
+                    .into_vec()
+                    .with_context(|| format!("invalid account pubkey {pubkey}"))?;
+                Ok((key, Mutex::new([None, None, None])))
+            })
+            .collect::<Result<_>>()?;
+        let extra: Vec<CommitmentLevel> = extra.iter().copied().filter(|level| *level != default).collect();
+        let filters = extra.iter().map(|level| (filter_name(*level), *level)).collect();
+        let mut tracked = vec![default];
+        tracked.extend(extra);
+        tracked.sort();
+        tracked.dedup();
+        Ok(Self { accounts, filters, default, tracked })
+    }
+
+    /// Clears the poison a handler panic left on the entries' locks.
+    pub fn clear_poison(&self) {
+        for entry in self.accounts.values() {
+            entry.clear_poison();
+        }
+    }
+
+    /// Whether queries at `level` can be answered.
+    pub fn tracks(&self, level: CommitmentLevel) -> bool {
+        self.tracked.contains(&level)
+    }
+
+    /// The levels queries can ask for, e.g. `processed, confirmed`.
+    pub fn tracked_names(&self) -> String {
+        self.tracked.iter().map(|level| level.as_str_name().to_lowercase()).collect::<Vec<_>>().join(", ")
+    }
+
+    /// Records an account update if it is for a cached account and not older
+    /// than what is already known at its level. It also replaces the state
+    /// at less safe levels that it isn't older than, so those are never
+    /// behind a safer one.
+    pub fn observe(&self, update: &SubscribeUpdate) {
+        let Some(UpdateOneof::Account(account_update)) = &update.update_oneof else {
+            return;
+        };
+        let Some(account) = &account_update.account else {
+            return;
+        };
+        let Some(entry) = self.accounts.get(&account.pubkey) else {
+            return;
+        };
+        let level = update.filters.iter().find_map(|name| self.filters.get(name)).copied().unwrap_or(self.default);
+        let latest = Arc::new(Latest {
+            slot: account_update.slot,
+            write_version: account.write_version,
+            owner: account.owner.clone(),
+            lamports: account.lamports,
+            executable: account.executable,
+            rent_epoch: account.rent_epoch,
+            data: account.data.clone(),
+        });
+        let mut states = entry.lock().unwrap();
+        for tracked in self.tracked.iter().filter(|tracked| **tracked <= level) {
+            let state = &mut states[*tracked as usize];
+            let newer = |current: &Arc<Latest>| {
+                (current.slot, current.write_version) > (latest.slot, latest.write_version)
+            };
+            if !state.as_ref().is_some_and(newer) {
+                *state = Some(latest.clone());
+            }
+        }
+    }
+
+    /// Rolls states from slots a fork abandoned back to the next safer
+    /// level's state, or to unknown without one. Returns how many account
+    /// states changed.
+    pub fn rollback(&self, abandoned: &[u64]) -> usize {
+        let mut rolled_back = 0;
+        for entry in self.accounts.values() {
+            let mut states = entry.lock().unwrap();
+            // Safest first, so a rolled back level falls back to a kept one
+            for index in (0..states.len()).rev() {
+                if states[index].as_ref().is_some_and(|latest| abandoned.contains(&latest.slot)) {
+                    states[index] = states[index + 1..].iter().flatten().next().cloned();
+                    rolled_back += 1;
+                }
+            }
+        }
+        rolled_back
+    }
+
+    /// The latest state of `pubkey` at `level` (by default the level the
+    /// accounts are subscribed at) as JSON, or `None` if it isn't cached or
+    /// no update for it has arrived yet.
+    pub fn get(&self, pubkey: &[u8], level: Option<CommitmentLevel>) -> Option<Value> {
+        let level = level.unwrap_or(self.default);
+        let latest = self.accounts.get(pubkey)?.lock().unwrap()[level as usize].clone()?;
+        Some(json!({
+            "pubkey": bs58::encode(pubkey).into_string(),
+            "commitment": level.as_str_name().to_lowercase(),
+            "slot": latest.slot,
+            "write_version": latest.write_version,
+            "owner": bs58::encode(&latest.owner).into_string(),
+            "lamports": latest.lamports,
+            "executable": latest.executable,
+            "rent_epoch": latest.rent_epoch,
+            "data": hex(&latest.data),
+        }))
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Slots with data this far behind the newest one are forgotten.
+const RETAINED_SLOTS: u64 = 512;
+
+/// Holds slot log lines back until the slot turns out to have matching
+/// account or transaction updates, so only slots with data are logged.
+///
+/// Data for a slot often arrives after its slot update, so lines are kept
+/// for `window` before being dropped.
+pub struct ActiveSlots {
+    window: Duration,
+    /// Slots that had at least one matching data update
+    active: BTreeSet<u64>,
+    /// Slot lines waiting for data, oldest first
+    pending: VecDeque<(u64, Instant, String)>,
+    suppressed: u64,
+}
+
+impl ActiveSlots {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            active: BTreeSet::new(),
+            pending: VecDeque::new(),
+            suppressed: 0,
+        }
+    }
+
+    /// Logs `line` now if `slot` already had data, otherwise holds it.
+    pub fn slot_update(&mut self, slot: u64, line: String) {
+        self.expire();
+        if self.active.contains(&slot) {
+            info!("{}", line);
+        } else {
+            self.pending.push_back((slot, Instant::now(), line));
+        }
+    }
+
+    /// Marks `slot` as having data and logs its held slot lines.
+    pub fn data_update(&mut self, slot: u64) {
+        self.expire();
+        if !self.active.insert(slot) {
+            return;
+        }
+        self.pending.retain(|(pending_slot, _, line)| {
+            if *pending_slot == slot {
+                info!("{}", line);
+            }
+            *pending_slot != slot
+        });
+        if let Some(&newest) = self.active.last() {
+            self.active = self.active.split_off(&newest.saturating_sub(RETAINED_SLOTS));
+        }
+    }
+
+    /// Slot lines dropped because no data arrived for them in time.
+    pub fn suppressed(&self) -> u64 {
+        self.suppressed
+    }
+
+    fn expire(&mut self) {
+        while let Some((_, held_since, _)) = self.pending.front() {
+            if held_since.elapsed() < self.window {
+                break;
+            }
+            self.pending.pop_front();
+            self.suppressed += 1;
+        }
+    }
+}
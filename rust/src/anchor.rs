@@ -0,0 +1,224 @@
+use anyhow::{Context, Result, bail};
+use serde_json::{Map, Value, json};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::discriminators::hex;
+
+/// Account types of an Anchor IDL, matched by their 8-byte discriminator.
+///
+/// Both the current IDL format (explicit `discriminator`, `pubkey`,
+/// `{"defined": {"name": ..}}`) and the legacy one (discriminator derived
+/// from `account:<Name>`, `publicKey`, `{"defined": ".."}`) are supported.
+pub struct AnchorIdl {
+    accounts: Vec<IdlAccount>,
+    types: HashMap<String, Value>,
+}
+
+struct IdlAccount {
+    name: String,
+    discriminator: [u8; 8],
+    /// The `type` object: `{"kind": "struct", "fields": [...]}` etc.
+    ty: Value,
+}
+
+impl AnchorIdl {
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("failed to read IDL {}", path.display()))?;
+        let idl: Value = serde_json::from_str(&contents)
+            .with_context(|| format!("invalid IDL {}: not JSON", path.display()))?;
+        Self::from_value(&idl).with_context(|| format!("invalid IDL {}", path.display()))
+    }
+
+    fn from_value(idl: &Value) -> Result<Self> {
+        let mut types = HashMap::new();
+        for ty in idl["types"].as_array().into_iter().flatten() {
+            let name = ty["name"].as_str().context("type without a name")?;
+            types.insert(name.to_string(), ty["type"].clone());
+        }
+
+        let mut accounts = Vec::new();
+        for account in idl["accounts"].as_array().into_iter().flatten() {
+            let name = account["name"].as_str().context("account without a name")?;
+            let discriminator = match account["discriminator"].as_array() {
+                Some(bytes) => bytes
+                    .iter()
+                    .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                    .collect::<Option<Vec<u8>>>()
+                    .and_then(|bytes| <[u8; 8]>::try_from(bytes).ok())
+                    .with_context(|| format!("account {name} has an invalid discriminator"))?,
+                None => legacy_discriminator(name),
+            };
+            // Legacy IDLs define the layout inline, current ones in `types`
+            let ty = match account.get("type") {
+                Some(ty) => ty.clone(),
+                None => types
+                    .get(name)
+                    .cloned()
+                    .with_context(|| format!("account {name} has no matching type definition"))?,
+            };
+            accounts.push(IdlAccount { name: name.to_string(), discriminator, ty });
+        }
+
+        if accounts.is_empty() {
+            bail!("no account types defined");
+        }
+        Ok(Self { accounts, types })
+    }
+
+    pub fn account_count(&self) -> usize {
+        self.accounts.len()
+    }
+
+    /// Decodes `data` as the IDL account whose discriminator it starts with.
+    /// Returns `None` if no account matches; a matching account whose data
+    /// doesn't fit its layout is an error.
+    pub fn decode(&self, data: &[u8]) -> Option<Result<(&str, Value)>> {
+        let (discriminator, body) = data.split_first_chunk::<8>()?;
+        let account = self.accounts.iter().find(|account| &account.discriminator == discriminator)?;
+        let mut reader = Reader { data: body };
+        Some(
+            self.decode_defined(&account.ty, &mut reader)
+                .map(|value| (account.name.as_str(), value))
+                .with_context(|| format!("failed to decode {} account", account.name)),
+        )
+    }
+
+    /// Decodes a field type such as `"u64"`, `{"vec": ..}` or `{"defined": ..}`.
+    fn decode_type(&self, ty: &Value, reader: &mut Reader) -> Result<Value> {
+        if let Some(name) = ty.as_str() {
+            return reader.primitive(name);
+        }
+        if let Some(inner) = ty.get("option").or_else(|| ty.get("coption")) {
+            let present = if ty.get("coption").is_some() { reader.u32()? != 0 } else { reader.u8()? != 0 };
+            return if present { self.decode_type(inner, reader) } else { Ok(Value::Null) };
+        }
+        if let Some(inner) = ty.get("vec") {
+            let len = reader.u32()?;
+            return (0..len).map(|_| self.decode_type(inner, reader)).collect();
+        }
+        if let Some(array) = ty.get("array").and_then(Value::as_array) {
+            let (Some(inner), Some(len)) = (array.first(), array.get(1).and_then(Value::as_u64)) else {
+                bail!("unsupported array type {ty}");
+            };
+            return (0..len).map(|_| self.decode_type(inner, reader)).collect();
+        }
+        if let Some(defined) = ty.get("defined") {
+            let name = defined
+                .as_str()
+                .or_else(|| defined["name"].as_str())
+                .with_context(|| format!("unsupported defined type {defined}"))?;
+            let definition = self
+                .types
+                .get(name)
+                .with_context(|| format!("type {name} is not defined in the IDL"))?;
+            return self.decode_defined(definition, reader);
+        }
+        bail!("unsupported type {ty}")
+    }
+
+    /// Decodes a struct or enum definition.
+    fn decode_defined(&self, definition: &Value, reader: &mut Reader) -> Result<Value> {
+        match definition["kind"].as_str() {
+            Some("struct") => self.decode_fields(&definition["fields"], reader),
+            Some("enum") => {
+                let variants = definition["variants"].as_array().context("enum without variants")?;
+                let index = reader.u8()? as usize;
+                let variant = variants
+                    .get(index)
+                    .with_context(|| format!("enum variant {index} out of range"))?;
+                let name = variant["name"].as_str().unwrap_or_default().to_string();
+                if variant.get("fields").is_none() {
+                    return Ok(Value::String(name));
+                }
+                Ok(json!({ name: self.decode_fields(&variant["fields"], reader)? }))
+            }
+            other => bail!("unsupported type kind {other:?}"),
+        }
+    }
+
+    /// Named fields decode to an object, tuple fields to an array.
+    fn decode_fields(&self, fields: &Value, reader: &mut Reader) -> Result<Value> {
+        let Some(fields) = fields.as_array() else {
+            return Ok(Value::Object(Map::new()));
+        };
+        if fields.iter().all(|field| field.get("name").is_some()) {
+            let mut object = Map::new();
+            for field in fields {
+                let name = field["name"].as_str().unwrap_or_default().to_string();
+                object.insert(name, self.decode_type(&field["type"], reader)?);
+            }
+            Ok(Value::Object(object))
+        } else {
+            fields.iter().map(|ty| self.decode_type(ty, reader)).collect()
+        }
+    }
+}
+
+/// `sha256("account:<Name>")[..8]`, used by IDLs that predate explicit
+/// discriminators.
+fn legacy_discriminator(name: &str) -> [u8; 8] {
+    let hash = Sha256::digest(format!("account:{name}"));
+    let mut discriminator = [0; 8];
+    discriminator.copy_from_slice(&hash[..8]);
+    discriminator
+}
+
+/// Cursor over borsh-encoded data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let Some((bytes, rest)) = self.data.split_first_chunk::<N>() else {
+            bail!("account data ended early");
+        };
+        self.data = rest;
+        Ok(*bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    fn bytes(&mut self) -> Result<&[u8]> {
+        let len = self.u32()? as usize;
+        if self.data.len() < len {
+            bail!("account data ended early");
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    /// 128-bit integers don't fit JSON numbers and are emitted as strings.
+    fn primitive(&mut self, name: &str) -> Result<Value> {
+        Ok(match name {
+            "bool" => Value::Bool(self.u8()? != 0),
+            "u8" => json!(self.u8()?),
+            "i8" => json!(i8::from_le_bytes(self.take()?)),
+            "u16" => json!(u16::from_le_bytes(self.take()?)),
+            "i16" => json!(i16::from_le_bytes(self.take()?)),
+            "u32" => json!(self.u32()?),
+            "i32" => json!(i32::from_le_bytes(self.take()?)),
+            "f32" => json!(f32::from_le_bytes(self.take()?)),
+            "u64" => json!(u64::from_le_bytes(self.take()?)),
+            "i64" => json!(i64::from_le_bytes(self.take()?)),
+            "f64" => json!(f64::from_le_bytes(self.take()?)),
+            "u128" => json!(u128::from_le_bytes(self.take()?).to_string()),
+            "i128" => json!(i128::from_le_bytes(self.take()?).to_string()),
+            "pubkey" | "publicKey" => json!(bs58::encode(self.take::<32>()?).into_string()),
+            "string" => json!(String::from_utf8_lossy(self.bytes()?)),
+            "bytes" => json!(hex(self.bytes()?)),
+            other => bail!("unsupported primitive type {other}"),
+        })
+    }
+}
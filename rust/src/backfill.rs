@@ -0,0 +1,289 @@
+use anyhow::{Result, bail};
+use serde_json::{Value, json};
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::{info, warn};
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::config::{Commitment, Config};
+use crate::handler::{self, Flow};
+use crate::rpc::{RpcClient, RpcError};
+use crate::session::Session;
+use crate::sinks::ReceivedAt;
+use crate::tx_error;
+use crate::votes;
+use crate::warmup::Warmup;
+
+/// JSON-RPC errors for slots that have no block, or no longer have one.
+const BLOCK_CLEANED_UP: i64 = -32001;
+const BLOCK_NOT_AVAILABLE: i64 = -32004;
+const SLOT_SKIPPED: i64 = -32007;
+const LONG_TERM_STORAGE_SLOT_SKIPPED: i64 = -32009;
+
+/// Rate-limited calls are retried this many times, backing off from
+/// `RATE_LIMIT_DELAY` unless the server says how long to wait.
+const RATE_LIMIT_RETRIES: u32 = 5;
+const RATE_LIMIT_DELAY: Duration = Duration::from_millis(500);
+
+/// Fills slots a resumed stream skipped over with blocks fetched from a
+/// Solana JSON-RPC endpoint, handled like streamed updates.
+pub struct RpcBackfill {
+    rpc: RpcClient,
+    url: String,
+    commitment: Commitment,
+    max_slots: u64,
+    transactions: bool,
+    include_votes: bool,
+    /// Watched signatures, by the index their filter is named after
+    signatures: Vec<String>,
+    /// Slots whose block was fetched and handled
+    pub filled: AtomicU64,
+    /// Slots RPC had no block for: skipped, or beyond its history
+    pub missing: AtomicU64,
+}
+
+impl RpcBackfill {
+    pub fn new(config: &Config) -> Result<Option<Self>> {
+        let Some(url) = &config.rpc_backfill else {
+            return Ok(None);
+        };
+        let mut commitment = config.transactions_commitment.unwrap_or(config.commitment);
+        if commitment == Commitment::Processed {
+            // getBlock only serves confirmed and finalized blocks
+            warn!("RPC backfill fetches blocks at confirmed commitment, getBlock doesn't support processed");
+            commitment = Commitment::Confirmed;
+        }
+        info!("Filling stream gaps of up to {} slots from RPC {}", config.rpc_backfill_max_slots, url);
+        Ok(Some(Self {
+            rpc: RpcClient::new(url)?,
+            url: url.clone(),
+            commitment,
+            max_slots: config.rpc_backfill_max_slots,
+            transactions: config.transactions,
+            include_votes: config.decode_votes,
+            signatures: config.tx_signatures.clone(),
+            filled: AtomicU64::new(0),
+            missing: AtomicU64::new(0),
+        }))
+    }
+
+    /// Fetches and handles every slot in `slots`, in order. Returns the
+    /// flow that ended the backfill early, if the handler asked to stop.
+    pub async fn fill(&self, session: &Session, slots: Range<u64>, warmup: &mut Warmup) -> Result<Option<Flow>> {
+        let count = slots.end - slots.start;
+        if count > self.max_slots {
+            warn!(
+                "Stream resumed {} slots after slot {}, more than RPC_BACKFILL_MAX_SLOTS={}; not backfilling",
+                count, slots.start, self.max_slots
+            );
+            return Ok(None);
+        }
+        info!("Stream resumed at slot {}, backfilling slots {}..{} from RPC", slots.end, slots.start, slots.end);
+        for slot in slots {
+            let block = match self.fetch_block(slot).await {
+                Ok(Some(block)) => block,
+                Ok(None) => {
+                    self.missing.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed to backfill slot {} from {}: {:#}", slot, self.url, e);
+                    self.missing.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+            };
+            for update in self.block_updates(slot, &block) {
+                let flow = handler::process_update(session, &update, ReceivedAt::now(), warmup)?;
+                if matches!(flow, Flow::Stop | Flow::EndOfStream) {
+                    return Ok(Some(flow));
+                }
+            }
+            self.filled.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(None)
+    }
+
+    /// The block at `slot`, or `None` if RPC has none.
+    async fn fetch_block(&self, slot: u64) -> Result<Option<Value>> {
+        let commitment = match self.commitment {
+            Commitment::Finalized => "finalized",
+            _ => "confirmed",
+        };
+        let params = json!([slot, {
+            "encoding": "json",
+            "transactionDetails": if self.transactions || !self.signatures.is_empty() { "full" } else { "none" },
+            "rewards": false,
+            "maxSupportedTransactionVersion": 0,
+            "commitment": commitment,
+        }]);
+        let mut delay = RATE_LIMIT_DELAY;
+        for _ in 0..=RATE_LIMIT_RETRIES {
+            match self.rpc.call("getBlock", params.clone()).await {
+                Ok(Value::Null) => return Ok(None),
+                Ok(block) => return Ok(Some(block)),
+                Err(RpcError::RateLimited(retry_after)) => {
+                    let wait = retry_after.unwrap_or(delay);
+                    warn!("RPC {} is rate limiting backfill requests, retrying in {:?}", self.url, wait);
+                    tokio::time::sleep(wait).await;
+                    delay *= 2;
+                }
+                Err(RpcError::Rpc {
+                    code: BLOCK_CLEANED_UP | BLOCK_NOT_AVAILABLE | SLOT_SKIPPED | LONG_TERM_STORAGE_SLOT_SKIPPED,
+                    message,
+                }) =>                 {
+                    info!("No block for slot {} from RPC: {}", slot, message);
+                    return Ok(None);
+                }
+                Err(e) => bail!("{e}"),
+            }
+        }
+        bail!("still rate limited after {RATE_LIMIT_RETRIES} retries")
+    }
+
+    /// The transactions the subscription would have matched, followed by the
+    /// slot's status update.
+    fn block_updates(&self, slot: u64, block: &Value) -> Vec<SubscribeUpdate> {
+        let mut updates = Vec::new();
+        for (index, entry) in block["transactions"].as_array().into_iter().flatten().enumerate() {
+            let Some(info) = transaction_info(index as u64, entry) else {
+                warn!("Skipping a transaction of slot {} that RPC returned in an unexpected format", slot);
+                continue;
+            };
+            let signature = bs58::encode(&info.signature).into_string();
+            // Named like the filters in the request
+            let filter = match self.signatures.iter().position(|watched| *watched == signature) {
+                Some(index) => format!("signature_{index}"),
+                None if self.transactions && (self.include_votes || !info.is_vote) => "transactions".to_string(),
+                None => continue,
+            };
+            updates.push(SubscribeUpdate {
+                filters: vec![filter],
+                update_oneof: Some(subscribe_update::UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                    transaction: Some(info),
+                    slot,
+                })),
+                created_at: None,
+            });
+        }
+        let status = match self.commitment {
+            Commitment::Finalized => SlotStatus::SlotFinalized,
+            _ => SlotStatus::SlotConfirmed,
+        };
+        updates.push(SubscribeUpdate {
+            filters: vec!["client".to_string()],
+            update_oneof: Some(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot,
+                parent: block["parentSlot"].as_u64(),
+                status: status as i32,
+                dead_error: None,
+            })),
+            created_at: None,
+        });
+        updates
+    }
+}
+
+/// Converts a `getBlock` transaction in `json` encoding to the stream's
+/// form. Inner instructions, token balances and return data are left out.
+fn transaction_info(index: u64, entry: &Value) -> Option<SubscribeUpdateTransactionInfo> {
+    let tx = &entry["transaction"];
+    let message = &tx["message"];
+    let signatures = base58_list(&tx["signatures"])?;
+    let account_keys = base58_list(&message["accountKeys"])?;
+    let instructions = message["instructions"]
+        .as_array()?
+        .iter()
+        .map(|instruction| {
+            Some(CompiledInstruction {
+                program_id_index: u32::try_from(instruction["programIdIndex"].as_u64()?).ok()?,
+                accounts: byte_list(&instruction["accounts"])?,
+                data: bs58::decode(instruction["data"].as_str()?).into_vec().ok()?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let address_table_lookups = message["addressTableLookups"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|lookup| {
+            Some(MessageAddressTableLookup {
+                account_key: bs58::decode(lookup["accountKey"].as_str()?).into_vec().ok()?,
+                writable_indexes: byte_list(&lookup["writableIndexes"])?,
+                readonly_indexes: byte_list(&lookup["readonlyIndexes"])?,
+            })
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let header = &message["header"];
+    let is_vote = instructions.iter().any(|instruction| {
+        account_keys
+            .get(instruction.program_id_index as usize)
+            .is_some_and(|key| key.as_slice() == votes::VOTE_PROGRAM_ID)
+    });
+
+    let meta = &entry["meta"];
+    let err = match &meta["err"] {
+        Value::Null => None,
+        // Unknown variants are kept as failed with undecodable bytes
+        err => Some(TransactionError { err: tx_error::encode_json(err).unwrap_or_default() }),
+    };
+    Some(SubscribeUpdateTransactionInfo {
+        signature: signatures.first()?.clone(),
+        is_vote,
+        transaction: Some(Transaction {
+            signatures,
+            message: Some(yellowstone_grpc_proto::prelude::Message {
+                header: Some(MessageHeader {
+                    num_required_signatures: header["numRequiredSignatures"].as_u64()? as u32,
+                    num_readonly_signed_accounts: header["numReadonlySignedAccounts"].as_u64()? as u32,
+                    num_readonly_unsigned_accounts: header["numReadonlyUnsignedAccounts"].as_u64()? as u32,
+                }),
+                account_keys,
+                recent_blockhash: bs58::decode(message["recentBlockhash"].as_str()?).into_vec().ok()?,
+                instructions,
+                versioned: entry["version"] != "legacy",
+                address_table_lookups,
+            }),
+        }),
+        meta: meta.is_object().then(|| TransactionStatusMeta {
+            err,
+            fee: meta["fee"].as_u64().unwrap_or_default(),
+            pre_balances: u64_list(&meta["preBalances"]),
+            post_balances: u64_list(&meta["postBalances"]),
+            inner_instructions_none: true,
+            log_messages: meta["logMessages"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|line| line.as_str().map(str::to_string))
+                .collect(),
+            log_messages_none: meta["logMessages"].is_null(),
+            loaded_writable_addresses: base58_list(&meta["loadedAddresses"]["writable"]).unwrap_or_default(),
+            loaded_readonly_addresses: base58_list(&meta["loadedAddresses"]["readonly"]).unwrap_or_default(),
+            return_data_none: true,
+            compute_units_consumed: meta["computeUnitsConsumed"].as_u64(),
+            ..Default::default()
+        }),
+        index,
+    })
+}
+
+fn base58_list(value: &Value) -> Option<Vec<Vec<u8>>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|item| bs58::decode(item.as_str()?).into_vec().ok())
+        .collect()
+}
+
+fn byte_list(value: &Value) -> Option<Vec<u8>> {
+    value
+        .as_array()?
+        .iter()
+        .map(|item| u8::try_from(item.as_u64()?).ok())
+        .collect()
+}
+
+fn u64_list(value: &Value) -> Vec<u64> {
+    value.as_array().into_iter().flatten().filter_map(Value::as_u64).collect()
+}
@@ -0,0 +1,121 @@
+use anyhow::{Context, Result, bail};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+use crate::account_hashes::data_hash;
+
+/// File header, followed by 40-byte records: pubkey (32) + data hash (8, LE).
+const MAGIC: &[u8; 8] = b"SVSBASE1";
+const RECORD_LEN: usize = 40;
+
+/// How an account update compares to the baseline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BaselineDiff {
+    Unchanged,
+    /// Not in the baseline
+    New,
+    /// Data differs from the baseline
+    Changed,
+    /// Closed (zero lamports) since the baseline
+    Removed,
+}
+
+impl BaselineDiff {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            BaselineDiff::Unchanged => "unchanged",
+            BaselineDiff::New => "new",
+            BaselineDiff::Changed => "changed",
+            BaselineDiff::Removed => "removed",
+        }
+    }
+}
+
+/// Account data hashes at a known point, compared against live updates and
+/// rolled forward as they arrive.
+pub struct Baseline {
+    path: PathBuf,
+    hashes: HashMap<[u8; 32], u64>,
+    /// Baseline accounts that received at least one update this session
+    seen: HashSet<[u8; 32]>,
+    loaded: usize,
+}
+
+impl Baseline {
+    /// Loads the baseline at `path`; a missing file starts an empty one.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut hashes = HashMap::new();
+        match fs::read(path) {
+            Ok(contents) => {
+                let Some(records) = contents.strip_prefix(MAGIC) else {
+                    bail!("{} is not an account baseline file", path.display());
+                };
+                if records.len() % RECORD_LEN != 0 {
+                    bail!("account baseline {} is truncated", path.display());
+                }
+                for record in records.chunks_exact(RECORD_LEN) {
+                    let (pubkey, hash) = record.split_at(32);
+                    hashes.insert(pubkey.try_into()?, u64::from_le_bytes(hash.try_into()?));
+                }
+                info!("Loaded baseline of {} accounts from {}", hashes.len(), path.display());
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                info!("No baseline at {} yet, every account counts as new", path.display());
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("failed to read account baseline {}", path.display()));
+            }
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            loaded: hashes.len(),
+            hashes,
+            seen: HashSet::new(),
+        })
+    }
+
+    /// Compares an account update with the baseline and records it as the
+    /// new baseline state.
+    pub fn diff(&mut self, pubkey: &[u8], lamports: u64, data: &[u8]) -> BaselineDiff {
+        let Ok(pubkey) = <[u8; 32]>::try_from(pubkey) else {
+            return BaselineDiff::New;
+        };
+        self.seen.insert(pubkey);
+        if lamports == 0 {
+            return match self.hashes.remove(&pubkey) {
+                Some(_) => BaselineDiff::Removed,
+                None => BaselineDiff::Unchanged,
+            };
+        }
+        let hash = data_hash(data);
+        match self.hashes.insert(pubkey, hash) {
+            None => BaselineDiff::New,
+            Some(previous) if previous != hash => BaselineDiff::Changed,
+            Some(_) => BaselineDiff::Unchanged,
+        }
+    }
+
+    /// Writes the rolled-forward baseline back to its file.
+    pub fn save(&self) -> Result<()> {
+        let mut contents = Vec::with_capacity(MAGIC.len() + self.hashes.len() * RECORD_LEN);
+        contents.extend_from_slice(MAGIC);
+        for (pubkey, hash) in &self.hashes {
+            contents.extend_from_slice(pubkey);
+            contents.extend_from_slice(&hash.to_le_bytes());
+        }
+        fs::write(&self.path, contents)
+            .with_context(|| format!("failed to write account baseline {}", self.path.display()))?;
+
+        let unseen = self.hashes.keys().filter(|pubkey| !self.seen.contains(*pubkey)).count();
+        info!(
+            "Saved baseline of {} accounts to {} ({} loaded, {} received no update this run)",
+            self.hashes.len(),
+            self.path.display(),
+            self.loaded,
+            unseen
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,143 @@
+use anyhow::{Result, bail};
+use clap::{Args, ValueEnum};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio_stream::StreamExt;
+use tracing::info;
+use yellowstone_grpc_proto::prelude::*;
+use yellowstone_grpc_proto::prost::Message;
+
+use crate::client::{self, ConnectOptions};
+use crate::stats::format_bytes;
+
+/// What the benchmark subscribes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BenchFilter {
+    /// All transactions, including votes
+    Transactions,
+    /// All account updates
+    Accounts,
+    /// Full blocks with transactions
+    Blocks,
+    /// Slot updates only
+    Slots,
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct BenchArgs {
+    /// How long to measure, in seconds
+    #[arg(long, default_value_t = 30)]
+    pub duration_secs: u64,
+
+    /// Subscription used for the benchmark
+    #[arg(long, value_enum, default_value_t = BenchFilter::Transactions)]
+    pub filter: BenchFilter,
+
+    /// Capacity of the queue between the stream reader and the handler
+    #[arg(long, default_value_t = 100_000)]
+    pub channel_capacity: usize,
+
+    /// How often the queue occupancy is logged, in seconds
+    #[arg(long, default_value_t = 5)]
+    pub report_interval_secs: u64,
+}
+
+fn bench_request(filter: BenchFilter) -> SubscribeRequest {
+    let mut request = SubscribeRequest {
+        commitment: Some(CommitmentLevel::Processed as i32),
+        ..Default::default()
+    };
+    let name = "bench".to_string();
+    match filter {
+        BenchFilter::Transactions => {
+            request.transactions = HashMap::from([(name, SubscribeRequestFilterTransactions::default())]);
+        }
+        BenchFilter::Accounts => {
+            request.accounts = HashMap::from([(name, SubscribeRequestFilterAccounts::default())]);
+        }
+        BenchFilter::Blocks => {
+            request.blocks = HashMap::from([(name, SubscribeRequestFilterBlocks {
+                include_transactions: Some(true),
+                ..Default::default()
+            })]);
+        }
+        BenchFilter::Slots => {
+            request.slots = HashMap::from([(name, SubscribeRequestFilterSlots::default())]);
+        }
+    }
+    request
+}
+
+/// Measures sustained throughput with a no-op handler.
+pub async fn run(options: &ConnectOptions, args: &BenchArgs) -> Result<()> {
+    let duration = Duration::from_secs(args.duration_secs);
+    info!("Benchmarking {:?} for {:?} against {}", args.filter, duration, options.endpoint);
+
+    let mut client = client::connect(options).await?;
+    let (mut subscribe_tx, mut stream) = client
+        .subscribe_with_request(Some(bench_request(args.filter)))
+        .await?;
+
+    let capacity = args.channel_capacity.max(1);
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<SubscribeUpdate>(capacity);
+    let consumer = tokio::spawn(async move {
+        // The no-op handler: only drains the queue
+        let mut handled = 0u64;
+        while rx.recv().await.is_some() {
+            handled += 1;
+        }
+        handled
+    });
+
+    let started = Instant::now();
+    let deadline = tokio::time::sleep(duration);
+    tokio::pin!(deadline);
+    let report_interval = Duration::from_secs(args.report_interval_secs.max(1));
+    let mut report = tokio::time::interval_at(tokio::time::Instant::now() + report_interval, report_interval);
+
+    let mut messages = 0u64;
+    let mut bytes = 0u64;
+    let mut max_depth = 0usize;
+    // Peak since the last report, so a past spike doesn't hide a trend
+    let mut interval_peak = 0usize;
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = report.tick() => {
+                info!(
+                    "Channel depth: current={}, interval peak={} of {}",
+                    capacity - tx.capacity(),
+                    interval_peak,
+                    capacity
+                );
+                interval_peak = 0;
+            }
+            message = stream.next() => {
+                let Some(message) = message else {
+                    bail!("stream ended after {:?}", started.elapsed());
+                };
+                let update = message?;
+                if matches!(update.update_oneof, Some(subscribe_update::UpdateOneof::Ping(_))) {
+                    client::reply_to_ping(&mut subscribe_tx).await?;
+                }
+                messages += 1;
+                bytes += update.encoded_len() as u64;
+                tx.send(update).await?;
+                let depth = capacity - tx.capacity();
+                interval_peak = interval_peak.max(depth);
+                max_depth = max_depth.max(depth);
+            }
+        }
+    }
+
+    let elapsed = started.elapsed().as_secs_f64();
+    drop(tx);
+    let handled = consumer.await?;
+
+    info!("Benchmark summary ({:?}, {:.1}s):", args.filter, elapsed);
+    info!("  messages: {} ({:.1}/s)", messages, messages as f64 / elapsed);
+    info!("  bytes: {} ({}/s)", format_bytes(bytes as f64), format_bytes(bytes as f64 / elapsed));
+    info!("  handled: {}", handled);
+    info!("  max channel depth: {} of {}", max_depth, capacity);
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+#[derive(Default)]
+struct KindWindow {
+    count: u64,
+    suppressed: u64,
+    first_slot: Option<u64>,
+    last_slot: Option<u64>,
+}
+
+/// Decides per update whether it gets its own log line. Updates of one type
+/// beyond `threshold` within a window are coalesced into a summary line
+/// logged when the window ends.
+pub struct BurstLogger {
+    window: Duration,
+    threshold: u64,
+    window_start: Instant,
+    kinds: BTreeMap<&'static str, KindWindow>,
+}
+
+impl BurstLogger {
+    pub fn new(window: Duration, threshold: u64) -> Self {
+        Self {
+            window,
+            threshold,
+            window_start: Instant::now(),
+            kinds: BTreeMap::new(),
+        }
+    }
+
+    /// Counts an update and returns true if it should be logged on its own.
+    pub fn admit(&mut self, kind: &'static str, slot: Option<u64>) -> bool {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= self.window {
+            self.summarize(elapsed);
+            self.window_start = Instant::now();
+        }
+
+        let window = self.kinds.entry(kind).or_default();
+        window.count += 1;
+        if window.count <= self.threshold {
+            return true;
+        }
+        window.suppressed += 1;
+        if let Some(slot) = slot {
+            window.first_slot = Some(window.first_slot.map_or(slot, |first| first.min(slot)));
+            window.last_slot = Some(window.last_slot.map_or(slot, |last| last.max(slot)));
+        }
+        false
+    }
+
+    fn summarize(&mut self, elapsed: Duration) {
+        for (kind, window) in std::mem::take(&mut self.kinds) {
+            if window.suppressed == 0 {
+                continue;
+            }
+            let slots = match (window.first_slot, window.last_slot) {
+                (Some(first), Some(last)) if first != last => format!(" for slots {first}-{last}"),
+                (Some(slot), _) => format!(" for slot {slot}"),
+                _ => String::new(),
+            };
+            info!(
+                "{} {} updates in {}ms{} (plus {} logged individually)",
+                window.suppressed,
+                kind,
+                elapsed.as_millis(),
+                slots,
+                window.count - window.suppressed
+            );
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use anyhow::{Result, bail};
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+use tokio_stream::StreamExt;
+use tracing::info;
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::client::{self, ConnectOptions, SharedClient, SharedConnection};
+use crate::handler::{self, Flow};
+use crate::session::Session;
+use crate::sinks::ReceivedAt;
+use crate::slot_range::{RangeCheck, SlotRange};
+use crate::stats;
+use crate::warmup::Warmup;
+
+/// Updates buffered between the connections and the handler.
+const CHANNEL_CAPACITY: usize = 10_000;
+
+enum Event {
+    Update(usize, Box<SubscribeUpdate>, ReceivedAt),
+    Done(usize, SlotRange),
+}
+
+/// Splits `from..=to` into `connections` contiguous sub-ranges.
+fn split_range(from: u64, to: u64, connections: usize) -> Vec<(u64, u64)> {
+    let total = to - from + 1;
+    let size = total.div_ceil(connections as u64).max(1);
+    (0..connections as u64)
+        .map(|index| from + index * size)
+        .take_while(|start| *start <= to)
+        .map(|start| (start, (start + size - 1).min(to)))
+        .collect()
+}
+
+/// Processes `from..=to` over several connections in parallel, each
+/// replaying its own sub-range via `from_slot`.
+///
+/// With `ordered`, updates of later sub-ranges are buffered until every
+/// earlier one is complete, so the handler sees slots in order at the cost
+/// of memory. With `share_connection`, every sub-range is streamed over a
+/// single connection. Connections are not retried; the first failure ends
+/// the run.
+pub async fn run(
+    options: &ConnectOptions,
+    requests: &[SubscribeRequest],
+    session: &Arc<Session>,
+    (from, to): (u64, u64),
+    connections: usize,
+    ordered: bool,
+    share_connection: bool,
+) -> Result<()> {
+    let ranges = split_range(from, to, connections);
+    let shared = share_connection.then(|| Arc::new(SharedConnection::default()));
+    match shared {
+        Some(_) => info!("Catching up on slots {}-{} over {} streams on one connection", from, to, ranges.len()),
+        None => info!("Catching up on slots {}-{} over {} connections", from, to, ranges.len()),
+    }
+
+    let (tx, mut rx) = mpsc::channel(CHANNEL_CAPACITY);
+    let mut workers = JoinSet::new();
+    for (index, range) in ranges.iter().copied().enumerate() {
+        info!("Connection {} handles slots {}-{}", index, range.0, range.1);
+        workers.spawn(worker(
+            index,
+            options.clone(),
+            requests.to_vec(),
+            range,
+            session.clone(),
+            shared.clone(),
+            tx.clone(),
+        ));
+    }
+    drop(tx);
+
+    let mut warmups: Vec<Warmup> = ranges.iter().map(|_| Warmup::default()).collect();
+    let mut buffers: Vec<Vec<(Box<SubscribeUpdate>, ReceivedAt)>> = vec![Vec::new(); ranges.len()];
+    let mut done = vec![false; ranges.len()];
+    let mut current = 0;
+
+    loop {
+        tokio::select! {
+            Some(result) = workers.join_next() => result??,
+            event = rx.recv() => match event {
+                Some(Event::Update(index, update, received)) if !ordered || index == current => {
+                    if handler::process_update(session, &update, received, &mut warmups[index])? == Flow::Stop {
+                        return Ok(());
+                    }
+                }
+                Some(Event::Update(index, update, received)) => buffers[index].push((update, received)),
+                Some(Event::Done(index, range)) => {
+                    info!("Connection {} finished", index);
+                    range.log_processed();
+                    done[index] = true;
+                    while current < done.len() && done[current] {
+                        current += 1;
+                        let Some(buffer) = buffers.get_mut(current) else { break };
+                        for (update, received) in std::mem::take(buffer) {
+                            if handler::process_update(session, &update, received, &mut warmups[current])? == Flow::Stop {
+                                return Ok(());
+                            }
+                        }
+                    }
+                    if done.iter().all(|done| *done) {
+                        info!("Catch-up of slots {}-{} complete", from, to);
+                        return Ok(());
+                    }
+                }
+                None => bail!("all catch-up connections ended before slot {to}"),
+            },
+        }
+    }
+}
+
+/// Streams one sub-range into `tx` until a slot after its end is announced.
+async fn worker(
+    index: usize,
+    options: ConnectOptions,
+    requests: Vec<SubscribeRequest>,
+    (from, to): (u64, u64),
+    session: Arc<Session>,
+    shared: Option<Arc<SharedConnection>>,
+    tx: mpsc::Sender<Event>,
+) -> Result<()> {
+    // Held for as long as this worker streams
+    let (mut client, _lease) = match &shared {
+        Some(shared) => {
+            let SharedClient { client, opened, lease } = shared.connect(&options).await?;
+            if opened {
+                session.connections.fetch_add(1, Ordering::Relaxed);
+            }
+            (client, Some(lease))
+        }
+        None => {
+            let client = client::connect(&options).await?;
+            session.connections.fetch_add(1, Ordering::Relaxed);
+            (client, None)
+        }
+    };
+
+    let mut subscribe_txs = Vec::with_capacity(requests.len());
+    let mut streams = Vec::with_capacity(requests.len());
+    for mut request in requests {
+        request.from_slot = Some(from);
+        let (subscribe_tx, stream) = client.subscribe_with_request(Some(request)).await?;
+        subscribe_txs.push(subscribe_tx);
+        streams.push(stream);
+    }
+    let mut stream = futures::stream::select_all(
+        streams
+            .into_iter()
+            .enumerate()
+            .map(|(index, stream)| stream.map(move |message| (index, message))),
+    );
+
+    let mut range = SlotRange::new(Some(from), Some(to));
+    while let Some((stream_index, message)) = stream.next().await {
+        let update = message?;
+        let received = ReceivedAt::now();
+        session.record_received(&update);
+
+        // Replied here since the handler runs on another task; the ping is
+        // still forwarded so it is logged and counted
+        if !session.no_ping_reply && matches!(update.update_oneof, Some(subscribe_update::UpdateOneof::Ping(_))) {
+            client::reply_to_ping(&mut subscribe_txs[stream_index]).await?;
+        }
+
+        if let Some(slot) = stats::update_slot(&update) {
+            let is_slot_update = matches!(update.update_oneof, Some(subscribe_update::UpdateOneof::Slot(_)));
+            match range.check(slot, is_slot_update) {
+                RangeCheck::Inside => {}
+                RangeCheck::Outside => {
+                    session.filtered.fetch_add(1, Ordering::Relaxed);
+                    continue;
+                }
+                RangeCheck::Done => {
+                    // A closed channel means the run already stopped
+                    let _ = tx.send(Event::Done(index, range)).await;
+                    return Ok(());
+                }
+            }
+        }
+        if tx.send(Event::Update(index, Box::new(update), received)).await.is_err() {
+            return Ok(());
+        }
+    }
+    bail!("catch-up connection {index} ended before slot {to}")
+}
@@ -0,0 +1,78 @@
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Checkpoints are also written after this long, so a slow chain can't
+/// hold back the tail of a run.
+const MAX_CHECKPOINT_AGE: Duration = Duration::from_secs(5);
+
+/// Persists the last processed slot so a restart can resume from it.
+///
+/// Writes are batched: the file is rewritten once the slot has advanced by
+/// `every_slots` or `MAX_CHECKPOINT_AGE` has passed, so a crash replays at
+/// most that many slots. `flush` writes the latest slot regardless.
+pub struct Checkpoint {
+    path: PathBuf,
+    every_slots: u64,
+    written_slot: Option<u64>,
+    written_at: Instant,
+    latest_slot: Option<u64>,
+}
+
+impl Checkpoint {
+    pub fn new(path: &Path, every_slots: u64) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            every_slots: every_slots.max(1),
+            written_slot: None,
+            written_at: Instant::now(),
+            latest_slot: None,
+        }
+    }
+
+    /// Reads a previously written checkpoint, if there is one.
+    pub fn load(path: &Path) -> Result<Option<u64>> {
+        match fs::read_to_string(path) {
+            Ok(contents) => contents
+                .trim()
+                .parse()
+                .map(Some)
+                .with_context(|| format!("invalid checkpoint file {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read checkpoint file {}", path.display())),
+        }
+    }
+
+    /// Records a processed slot, writing the checkpoint when it is due.
+    pub fn observe(&mut self, slot: u64) -> Result<()> {
+        if self.latest_slot.is_some_and(|latest| slot <= latest) {
+            return Ok(());
+        }
+        self.latest_slot = Some(slot);
+        let due = match self.written_slot {
+            Some(written) => slot >= written + self.every_slots || self.written_at.elapsed() >= MAX_CHECKPOINT_AGE,
+            None => true,
+        };
+        if due { self.flush() } else { Ok(()) }
+    }
+
+    /// Writes the latest slot, via a temporary file so a crash mid-write
+    /// never leaves a truncated checkpoint.
+    pub fn flush(&mut self) -> Result<()> {
+        let Some(slot) = self.latest_slot else {
+            return Ok(());
+        };
+        if self.written_slot == Some(slot) {
+            return Ok(());
+        }
+        let mut temp = self.path.clone().into_os_string();
+        temp.push(".tmp");
+        fs::write(&temp, format!("{slot}\n"))
+            .and_then(|()| fs::rename(&temp, &self.path))
+            .with_context(|| format!("failed to write checkpoint file {}", self.path.display()))?;
+        self.written_slot = Some(slot);
+        self.written_at = Instant::now();
+        Ok(())
+    }
+}
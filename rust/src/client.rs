@@ -0,0 +1,575 @@
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use hyper_util::rt::TokioIo;
+use serde::Serialize;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tonic::codegen::http::Uri;
+use tonic::metadata::{AsciiMetadataKey, AsciiMetadataValue};
+use tonic::service::Interceptor;
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint};
+use tonic::{Request, Status};
+use tonic_health::pb::health_client::HealthClient;
+use futures::{Sink, SinkExt};
+use tracing::{info, warn};
+use yellowstone_grpc_client::GeyserGrpcClient;
+use yellowstone_grpc_proto::prelude::geyser_client::GeyserClient;
+use yellowstone_grpc_proto::prelude::{SubscribeRequest, SubscribeRequestPing};
+use yellowstone_grpc_proto::prost_types;
+
+use crate::config;
+use crate::provider::ProviderPreset;
+
+/// How long sending a ping reply may take before the request stream is
+/// considered stuck.
+const PING_REPLY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Header names containing any of these are treated as credentials and
+/// redacted in logs.
+const SECRET_HINTS: [&str; 5] = ["token", "key", "secret", "auth", "password"];
+
+/// Headers the client sets itself, and the option that sets each. A custom
+/// header with one of these names would replace ours.
+const RESERVED_HEADERS: [(&str, &str); 6] = [
+    ("x-token", "GEYSER_ACCESS_TOKEN"),
+    ("request-id", "REQUEST_ID"),
+    ("user-agent", "GEYSER_USER_AGENT"),
+    ("x-timestamp", "HMAC_SECRET"),
+    ("x-nonce", "HMAC_SECRET"),
+    ("x-signature", "HMAC_SECRET"),
+];
+
+/// Which address family to connect over.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IpVersion {
+    /// Whatever the resolver and the OS prefer
+    #[default]
+    Auto,
+    /// IPv4 addresses only
+    V4,
+    /// IPv6 addresses only
+    V6,
+}
+
+impl IpVersion {
+    fn allows(self, addr: &SocketAddr) -> bool {
+        match self {
+            IpVersion::Auto => true,
+            IpVersion::V4 => addr.is_ipv4(),
+            IpVersion::V6 => addr.is_ipv6(),
+        }
+    }
+}
+
+/// A custom metadata header attached to every request.
+#[derive(Debug, Clone)]
+pub struct Header {
+    pub name: AsciiMetadataKey,
+    pub value: AsciiMetadataValue,
+}
+
+impl Header {
+    /// Parses a `name:value` pair, validating both halves as gRPC metadata.
+    pub fn parse(raw: &str) -> Result<Self> {
+        let Some((name, value)) = raw.split_once(':') else {
+            bail!("invalid header `{raw}`, expected `name:value`");
+        };
+        let name = name.trim();
+        let value = value.trim();
+        if name.is_empty() {
+            bail!("invalid header `{raw}`, name is empty");
+        }
+        if let Some((_, option)) = RESERVED_HEADERS.iter().find(|(reserved, _)| name.eq_ignore_ascii_case(reserved)) {
+            bail!("invalid header `{raw}`, `{}` is set by the client, use {option} instead", name.to_ascii_lowercase());
+        }
+        Ok(Self {
+            name: AsciiMetadataKey::from_bytes(name.as_bytes())
+                .with_context(|| format!("invalid header name `{name}`"))?,
+            value: AsciiMetadataValue::try_from(value)
+                .with_context(|| format!("invalid value for header `{name}`"))?,
+        })
+    }
+
+    /// The header as it should appear in logs.
+    pub fn display(&self) -> String {
+        let name = self.name.as_str();
+        let value = self.value.to_str().unwrap_or_default();
+        if SECRET_HINTS.iter().any(|hint| name.contains(hint)) {
+            format!("{name}: {}", config::redact(value))
+        } else {
+            format!("{name}: {value}")
+        }
+    }
+}
+
+/// Signs requests for providers that authenticate with an HMAC instead of
+/// a static token: `x-signature` is the hex HMAC-SHA256, keyed with the
+/// secret, of `<x-timestamp>.<x-nonce>`, where the timestamp is Unix
+/// milliseconds and the nonce a fresh UUID.
+#[derive(Debug, Clone)]
+pub struct HmacSigner {
+    key: ring::hmac::Key,
+    /// Server clock minus the local one, in milliseconds, so a skewed local
+    /// clock doesn't get signatures rejected
+    clock_offset_ms: Arc<AtomicI64>,
+}
+
+impl HmacSigner {
+    pub fn new(secret: &str) -> Self {
+        Self {
+            key: ring::hmac::Key::new(ring::hmac::HMAC_SHA256, secret.as_bytes()),
+            clock_offset_ms: Default::default(),
+        }
+    }
+
+    /// Adopts the server's clock from an update's `created_at`. Delivery
+    /// latency makes it read slightly behind, which still beats a local
+    /// clock that is seconds off.
+    pub fn observe_server_time(&self, created_at: &prost_types::Timestamp) {
+        let server_ms = created_at.seconds * 1000 + i64::from(created_at.nanos) / 1_000_000;
+        let offset = server_ms - unix_millis();
+        let previous = self.clock_offset_ms.swap(offset, Ordering::Relaxed);
+        if (offset - previous).abs() > 1000 {
+            info!("Signing requests with the server's clock, {}ms off the local one", offset);
+        }
+    }
+
+    fn sign(&self, metadata: &mut tonic::metadata::MetadataMap) {
+        let timestamp = (unix_millis() + self.clock_offset_ms.load(Ordering::Relaxed)).to_string();
+        let nonce = uuid::Uuid::new_v4().simple().to_string();
+        let tag = ring::hmac::sign(&self.key, format!("{timestamp}.{nonce}").as_bytes());
+        let signature = crate::discriminators::hex(tag.as_ref());
+        for (name, value) in [("x-timestamp", timestamp), ("x-nonce", nonce), ("x-signature", signature)] {
+            metadata.insert(name, AsciiMetadataValue::try_from(value).expect("digits and hex are valid metadata"));
+        }
+    }
+}
+
+fn unix_millis() -> i64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+/// Adds the x-token, the request id, the HMAC signature and any custom
+/// headers to every outgoing request.
+#[derive(Debug, Clone, Default)]
+pub struct MetadataInterceptor {
+    x_token: Option<AsciiMetadataValue>,
+    request_id: Option<AsciiMetadataValue>,
+    headers: Vec<Header>,
+    hmac: Option<HmacSigner>,
+}
+
+impl Interceptor for MetadataInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let metadata = request.metadata_mut();
+        // First, so the client's own headers win over custom ones
+        for header in &self.headers {
+            metadata.insert(header.name.clone(), header.value.clone());
+        }
+        if let Some(x_token) = &self.x_token {
+            metadata.insert("x-token", x_token.clone());
+        }
+        if let Some(request_id) = &self.request_id {
+            metadata.insert("request-id", request_id.clone());
+        }
+        if let Some(signer) = &self.hmac {
+            signer.sign(metadata);
+        }
+        Ok(request)
+    }
+}
+
+/// Everything needed to (re)establish a connection.
+#[derive(Debug, Clone)]
+pub struct ConnectOptions {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    pub headers: Vec<Header>,
+    /// `HMAC_SECRET` request signing
+    pub hmac: Option<HmacSigner>,
+    pub preset: ProviderPreset,
+    /// Overrides the TLS SNI / certificate hostname
+    pub tls_domain: Option<String>,
+    /// Refuse anything older than TLS 1.3
+    pub tls13_only: bool,
+    /// Restricts which resolved addresses are connected to
+    pub ip_version: IpVersion,
+    /// Sent as `request-id` so provider logs can be matched with ours
+    pub request_id: String,
+    /// Sent as `user-agent`, followed by tonic's own version
+    pub user_agent: String,
+}
+
+/// Connects to the endpoint, following the official builder pattern for the
+/// transport and attaching our own interceptor for request metadata.
+pub async fn connect(options: &ConnectOptions) -> Result<GeyserGrpcClient<MetadataInterceptor>> {
+    let channel = connect_channel(options).await?;
+    geyser_client(channel, options)
+}
+
+/// One connection shared by several subscribers to the same endpoint, each
+/// multiplexing its streams over it, for providers that limit concurrent
+/// connections per token.
+///
+/// The connection stays open while any [`SharedClient`] holds it and a new
+/// one is opened once all of them are dropped. Concurrent callers wait for
+/// a single connect attempt instead of each opening their own. Every caller
+/// must pass the same options: the transport settings of the one that
+/// opened the connection apply to all of them.
+#[derive(Default)]
+pub struct SharedConnection {
+    current: tokio::sync::Mutex<std::sync::Weak<Channel>>,
+}
+
+/// A client on a [`SharedConnection`].
+pub struct SharedClient {
+    pub client: GeyserGrpcClient<MetadataInterceptor>,
+    /// Whether this call opened the connection rather than reusing it
+    pub opened: bool,
+    pub lease: ConnectionLease,
+}
+
+/// Keeps a [`SharedConnection`] open while held.
+pub struct ConnectionLease {
+    _connection: Arc<Channel>,
+}
+
+impl SharedConnection {
+    pub async fn connect(&self, options: &ConnectOptions) -> Result<SharedClient> {
+        let mut current = self.current.lock().await;
+        let (connection, opened) = match current.upgrade() {
+            Some(connection) => (connection, false),
+            None => {
+                let connection = Arc::new(connect_channel(options).await?);
+                *current = Arc::downgrade(&connection);
+                (connection, true)
+            }
+        };
+        let client = geyser_client(Channel::clone(&connection), options)?;
+        Ok(SharedClient { client, opened, lease: ConnectionLease { _connection: connection } })
+    }
+}
+
+async fn connect_channel(options: &ConnectOptions) -> Result<Channel> {
+    let preset = options.preset;
+    let mut tls_config = ClientTlsConfig::new().with_native_roots();
+    if let Some(domain) = &options.tls_domain {
+        tls_config = tls_config.domain_name(domain.clone());
+    }
+    let mut builder = GeyserGrpcClient::build_from_shared(options.endpoint.clone())?
+        .connect_timeout(preset.connect_timeout)
+        .tls_config(tls_config)?;
+    // tonic's transport overwrites a `user-agent` set by an interceptor, so
+    // it is set on the channel, which sends it with every request
+    builder.endpoint = builder
+        .endpoint
+        .user_agent(options.user_agent.clone())
+        .context("invalid user agent")?;
+    if let Some(interval) = preset.http2_keep_alive_interval {
+        builder = builder
+            .http2_keep_alive_interval(interval)
+            .keep_alive_while_idle(true);
+    }
+
+    let channel = if options.tls13_only {
+        connect_tls13(options).await?
+    } else if options.ip_version != IpVersion::Auto {
+        // tonic still wraps this connector in its TLS for https endpoints
+        let ip_version = options.ip_version;
+        builder
+            .endpoint
+            .connect_with_connector(tower::service_fn(move |uri: Uri| async move {
+                let port = uri.port_u16().unwrap_or(if uri.scheme_str() == Some("https") { 443 } else { 80 });
+                connect_tcp(uri.host().unwrap_or_default(), port, ip_version).await.map(TokioIo::new)
+            }))
+            .await?
+    } else {
+        builder.endpoint.connect().await?
+    };
+    Ok(channel)
+}
+
+fn geyser_client(channel: Channel, options: &ConnectOptions) -> Result<GeyserGrpcClient<MetadataInterceptor>> {
+    let interceptor = MetadataInterceptor {
+        x_token: options
+            .x_token
+            .as_deref()
+            .map(AsciiMetadataValue::try_from)
+            .transpose()
+            .context("invalid access token")?,
+        request_id: Some(
+            AsciiMetadataValue::try_from(options.request_id.as_str()).context("invalid request id")?,
+        ),
+        headers: options.headers.clone(),
+        hmac: options.hmac.clone(),
+    };
+
+    Ok(GeyserGrpcClient::new(
+        HealthClient::with_interceptor(channel.clone(), interceptor.clone()),
+        GeyserClient::with_interceptor(channel, interceptor)
+            .max_decoding_message_size(options.preset.max_decoding_message_size),
+    ))
+}
+
+/// The hostname TLS will present and verify: the override if set,
+/// otherwise the endpoint's host.
+pub fn effective_tls_domain(options: &ConnectOptions) -> Option<String> {
+    options.tls_domain.clone().or_else(|| {
+        options
+            .endpoint
+            .parse::<tonic::codegen::http::Uri>()
+            .ok()
+            .and_then(|uri| uri.host().map(str::to_string))
+    })
+}
+
+/// Logs the custom headers, redacting values that look like credentials.
+pub fn log_headers(headers: &[Header]) {
+    for header in headers {
+        info!("Sending custom header {}", header.display());
+    }
+}
+
+/// Connects through our own rustls connector restricted to TLS 1.3, logging
+/// the negotiated version and cipher suite.
+///
+/// tonic only runs its own TLS for `https` URIs, so the endpoint is handed
+/// to tonic as `http` and the TLS stream is opened in the connector instead.
+async fn connect_tls13(options: &ConnectOptions) -> Result<Channel> {
+    let Some(plain_uri) = options.endpoint.strip_prefix("https://") else {
+        bail!("TLS_1_3_ONLY requires an https:// endpoint, got {}", options.endpoint);
+    };
+    let domain = effective_tls_domain(options).context("could not determine the TLS server name")?;
+    let server_name = ServerName::try_from(domain.clone())
+        .with_context(|| format!("invalid TLS server name `{domain}`"))?;
+
+    let mut tls_config = ClientConfig::builder_with_protocol_versions(&[&rustls::version::TLS13])
+        .with_root_certificates(native_roots())
+        .with_no_client_auth();
+    tls_config.alpn_protocols = vec![b"h2".to_vec()];
+    let connector = TlsConnector::from(Arc::new(tls_config));
+    let ip_version = options.ip_version;
+
+    let preset = options.preset;
+    let mut endpoint = Endpoint::from_shared(format!("http://{plain_uri}"))?
+        .connect_timeout(preset.connect_timeout)
+        .user_agent(options.user_agent.clone())
+        .context("invalid user agent")?;
+    if let Some(interval) = preset.http2_keep_alive_interval {
+        endpoint = endpoint
+            .http2_keep_alive_interval(interval)
+            .keep_alive_while_idle(true);
+    }
+
+    let channel = endpoint
+        .connect_with_connector(tower::service_fn(move |uri: Uri| {
+            let connector = connector.clone();
+            let server_name = server_name.clone();
+            async move {
+                let host = uri.host().unwrap_or_default().to_string();
+                let port = uri.port_u16().unwrap_or(443);
+                let tcp = connect_tcp(&host, port, ip_version).await?;
+                let tls = connector.connect(server_name, tcp).await.map_err(|e| {
+                    std::io::Error::other(format!(
+                        "TLS 1.3 handshake with {host}:{port} failed, the server may not support TLS 1.3: {e}"
+                    ))
+                })?;
+                let (_, session) = tls.get_ref();
+                info!(
+                    "TLS handshake complete: version={:?}, cipher={:?}",
+                    session.protocol_version(),
+                    session.negotiated_cipher_suite().map(|suite| suite.suite())
+                );
+                Ok::<_, std::io::Error>(TokioIo::new(tls))
+            }
+        }))
+        .await?;
+    Ok(channel)
+}
+
+/// The system's root certificates, for TLS connections opened outside tonic.
+pub fn native_roots() -> RootCertStore {
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        warn!("Failed to load a native root certificate: {}", error);
+    }
+    roots.add_parsable_certificates(native.certs);
+    roots
+}
+
+/// Resolves `host` and connects to the first reachable address allowed by
+/// `ip_version`, logging which one was used.
+pub async fn connect_tcp(host: &str, port: u16, ip_version: IpVersion) -> std::io::Result<TcpStream> {
+    // IPv6 literals keep their brackets in URIs
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let addrs = allowed_addrs(host, tokio::net::lookup_host((host, port)).await?, ip_version)?;
+
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(tcp) => {
+                tcp.set_nodelay(true)?;
+                let family = if addr.is_ipv4() { "IPv4" } else { "IPv6" };
+                info!("Connected to {} at {} over {}", host, addr, family);
+                return Ok(tcp);
+            }
+            Err(e) => {
+                warn!("Failed to connect to {} at {}: {}", host, addr, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    Err(last_error.expect("at least one address was tried"))
+}
+
+/// The resolved addresses of `host` that `ip_version` allows, or an error
+/// naming what is missing if there are none.
+fn allowed_addrs(
+    host: &str,
+    addrs: impl Iterator<Item = SocketAddr>,
+    ip_version: IpVersion,
+) -> std::io::Result<Vec<SocketAddr>> {
+    let addrs: Vec<SocketAddr> = addrs.filter(|addr| ip_version.allows(addr)).collect();
+    if addrs.is_empty() {
+        let family = match ip_version {
+            IpVersion::Auto => "",
+            IpVersion::V4 => "IPv4 ",
+            IpVersion::V6 => "IPv6 ",
+        };
+        return Err(std::io::Error::other(format!("{host} has no {family}address")));
+    }
+    Ok(addrs)
+}
+
+/// Answers a server ping on a stream's request sink.
+///
+/// A reply that can't be handed over within [`PING_REPLY_TIMEOUT`], or a
+/// sink that was closed, is logged and returned as an error, so the caller
+/// reconnects instead of blocking its receive loop on a stream the server
+/// no longer reads.
+pub async fn reply_to_ping<S>(subscribe_tx: &mut S) -> Result<()>
+where
+    S: Sink<SubscribeRequest> + Unpin,
+    S::Error: std::error::Error + Send + Sync + 'static,
+{
+    let reply = SubscribeRequest { ping: Some(SubscribeRequestPing { id: 1 }), ..Default::default() };
+    match tokio::time::timeout(PING_REPLY_TIMEOUT, subscribe_tx.send(reply)).await {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => {
+            warn!("The request stream is closed, can't reply to the server's ping");
+            Err(anyhow::Error::new(e).context("failed to reply to a ping: the request stream is closed"))
+        }
+        Err(_) => {
+            warn!("Replying to the server's ping took over {:?}, the request stream looks stuck", PING_REPLY_TIMEOUT);
+            bail!("failed to reply to a ping within {PING_REPLY_TIMEOUT:?}: the server stopped reading the request stream")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    /// A request stream the server stopped reading: never ready for more.
+    struct StalledSink;
+
+    impl Sink<SubscribeRequest> for StalledSink {
+        type Error = std::io::Error;
+
+        fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Pending
+        }
+
+        fn start_send(self: Pin<&mut Self>, _: SubscribeRequest) -> Result<(), Self::Error> {
+            unreachable!("never ready")
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Pending
+        }
+
+        fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Pending
+        }
+    }
+
+    #[tokio::test]
+    async fn ping_reply_gives_up_on_a_stalled_request_stream() {
+        tokio::time::pause();
+        let started = tokio::time::Instant::now();
+        let error = reply_to_ping(&mut StalledSink).await.unwrap_err();
+        // Paused time advances to the timer, rounded up to the millisecond
+        let waited = started.elapsed();
+        assert!(waited >= PING_REPLY_TIMEOUT && waited < PING_REPLY_TIMEOUT + Duration::from_millis(10), "{waited:?}");
+        assert_eq!(
+            error.to_string(),
+            "failed to reply to a ping within 10s: the server stopped reading the request stream"
+        );
+    }
+
+    #[test]
+    fn missing_addresses_name_the_family_asked_for() {
+        let v4: SocketAddr = "127.0.0.1:443".parse().unwrap();
+        let v6: SocketAddr = "[::1]:443".parse().unwrap();
+        let error = |addrs: Vec<SocketAddr>, ip_version| {
+            allowed_addrs("grpc.example.com", addrs.into_iter(), ip_version).unwrap_err().to_string()
+        };
+        assert_eq!(error(vec![], IpVersion::Auto), "grpc.example.com has no address");
+        assert_eq!(error(vec![v6], IpVersion::V4), "grpc.example.com has no IPv4 address");
+        assert_eq!(error(vec![v4], IpVersion::V6), "grpc.example.com has no IPv6 address");
+        assert_eq!(allowed_addrs("localhost", [v4, v6].into_iter(), IpVersion::V6).unwrap(), [v6]);
+        assert_eq!(allowed_addrs("localhost", [v4, v6].into_iter(), IpVersion::Auto).unwrap(), [v4, v6]);
+    }
+
+    #[test]
+    fn headers_the_client_sets_are_reserved() {
+        let error = Header::parse("X-Token:forged").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid header `X-Token:forged`, `x-token` is set by the client, use GEYSER_ACCESS_TOKEN instead"
+        );
+        for (name, _) in RESERVED_HEADERS {
+            assert!(Header::parse(&format!("{name}:value")).is_err(), "{name}");
+        }
+        assert!(Header::parse("x-api-key:abc123").is_ok());
+    }
+
+    #[test]
+    fn custom_headers_leave_the_client_metadata_alone() {
+        let mut interceptor = MetadataInterceptor {
+            x_token: Some(AsciiMetadataValue::from_static("secret")),
+            request_id: Some(AsciiMetadataValue::from_static("run-1")),
+            // Built directly, past the check in `Header::parse`
+            headers: vec![Header::parse("x-tenant:acme").unwrap(), Header {
+                name: AsciiMetadataKey::from_static("x-token"),
+                value: AsciiMetadataValue::from_static("forged"),
+            }],
+            hmac: None,
+        };
+        let request = interceptor.call(Request::new(())).unwrap();
+        let metadata = request.metadata();
+        assert_eq!(metadata.get("x-token").unwrap(), "secret");
+        assert_eq!(metadata.get("request-id").unwrap(), "run-1");
+        assert_eq!(metadata.get("x-tenant").unwrap(), "acme");
+    }
+
+    #[tokio::test]
+    async fn ping_reply_on_a_closed_request_stream() {
+        let (mut subscribe_tx, subscribe_rx) = futures::channel::mpsc::channel::<SubscribeRequest>(1);
+        drop(subscribe_rx);
+        let error = reply_to_ping(&mut subscribe_tx).await.unwrap_err();
+        assert_eq!(error.to_string(), "failed to reply to a ping: the request stream is closed");
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::{BTreeMap, HashMap};
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::sinks::ReceivedAt;
+
+/// The held updates of one slot by pubkey, with their arrival order.
+type SlotAccounts = HashMap<Vec<u8>, (u64, SubscribeUpdate, ReceivedAt)>;
+
+/// Keeps only the last account update per (pubkey, slot), releasing a
+/// slot's updates once the stream moves past it.
+#[derive(Default)]
+pub struct AccountCoalescer {
+    pending: BTreeMap<u64, SlotAccounts>,
+    arrivals: u64,
+    /// Updates replaced by a later one for the same account and slot
+    coalesced: u64,
+}
+
+impl AccountCoalescer {
+    /// Holds an account update, replacing an earlier one for the same
+    /// account in the same slot. Returns `false` for other updates, which
+    /// aren't held.
+    pub fn hold(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> bool {
+        let Some(UpdateOneof::Account(account_update)) = &update.update_oneof else {
+            return false;
+        };
+        let Some(account) = &account_update.account else {
+            return false;
+        };
+        self.arrivals += 1;
+        let previous = self
+            .pending
+            .entry(account_update.slot)
+            .or_default()
+            .insert(account.pubkey.clone(), (self.arrivals, update.clone(), received));
+        if previous.is_some() {
+            self.coalesced += 1;
+        }
+        true
+    }
+
+    /// Takes every held update of slots before `slot`, oldest slot first and
+    /// in arrival order within a slot.
+    pub fn release_before(&mut self, slot: u64) -> Vec<(SubscribeUpdate, ReceivedAt)> {
+        let later = self.pending.split_off(&slot);
+        let released = std::mem::replace(&mut self.pending, later);
+        drain(released)
+    }
+
+    /// Takes everything still held, e.g. on shutdown.
+    pub fn release_all(&mut self) -> Vec<(SubscribeUpdate, ReceivedAt)> {
+        drain(std::mem::take(&mut self.pending))
+    }
+
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+}
+
+fn drain(slots: BTreeMap<u64, SlotAccounts>) -> Vec<(SubscribeUpdate, ReceivedAt)> {
+    let mut released = Vec::new();
+    for accounts in slots.into_values() {
+        let mut accounts: Vec<_> = accounts.into_values().collect();
+        accounts.sort_unstable_by_key(|(arrival, _, _)| *arrival);
+        released.extend(accounts.into_iter().map(|(_, update, received)| (update, received)));
+    }
+    released
+}
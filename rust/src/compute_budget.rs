@@ -0,0 +1,56 @@
+use yellowstone_grpc_proto::prelude::Message;
+
+/// `ComputeBudget111111111111111111111111111111`
+const COMPUTE_BUDGET_PROGRAM_ID: [u8; 32] = [
+    3, 6, 70, 111, 229, 33, 23, 50, 255, 236, 173, 186, 114, 195, 155, 231, 188, 140, 229, 187,
+    197, 247, 18, 107, 44, 67, 155, 58, 64, 0, 0, 0,
+];
+
+const SET_COMPUTE_UNIT_LIMIT: u8 = 2;
+const SET_COMPUTE_UNIT_PRICE: u8 = 3;
+
+/// Compute budget requested by a transaction's Compute Budget instructions.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeBudget {
+    /// `SetComputeUnitLimit`, in compute units
+    pub unit_limit: Option<u32>,
+    /// `SetComputeUnitPrice`, in micro-lamports per compute unit
+    pub unit_price: Option<u64>,
+}
+
+impl ComputeBudget {
+    /// Decodes the Compute Budget instructions of `message`. Instructions
+    /// that are missing or malformed are left as `None`.
+    pub fn from_message(message: &Message) -> Self {
+        let mut budget = Self::default();
+        for instruction in &message.instructions {
+            let program = message.account_keys.get(instruction.program_id_index as usize);
+            if program.is_none_or(|key| key.as_slice() != COMPUTE_BUDGET_PROGRAM_ID) {
+                continue;
+            }
+            match instruction.data.split_first() {
+                Some((&SET_COMPUTE_UNIT_LIMIT, rest)) => {
+                    budget.unit_limit = rest.try_into().ok().map(u32::from_le_bytes);
+                }
+                Some((&SET_COMPUTE_UNIT_PRICE, rest)) => {
+                    budget.unit_price = rest.try_into().ok().map(u64::from_le_bytes);
+                }
+                _ => {}
+            }
+        }
+        budget
+    }
+
+    /// `, cu_price=..., cu_limit=...` for the fields that are set, to append
+    /// to a transaction log line.
+    pub fn log_suffix(&self) -> String {
+        let mut suffix = String::new();
+        if let Some(price) = self.unit_price {
+            suffix.push_str(&format!(", cu_price={price} micro-lamports"));
+        }
+        if let Some(limit) = self.unit_limit {
+            suffix.push_str(&format!(", cu_limit={limit}"));
+        }
+        suffix
+    }
+}
@@ -0,0 +1,490 @@
+use anyhow::{Context, Result, anyhow, bail};
+use clap::{Arg, ArgAction, Command};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Options that only make sense on the command line.
+const NOT_IN_FILE: [&str; 3] = ["config", "help", "version"];
+
+/// Options that may also be written in the `filters` section: what is
+/// subscribed to and what the client-side filters let through.
+const FILTER_OPTIONS: [&str; 18] = [
+    "accounts_file",
+    "transactions",
+    "tx_signatures",
+    "decode_votes",
+    "interslot_updates",
+    "nonempty_txn_signature",
+    "min_lamports",
+    "include_pubkeys",
+    "exclude_pubkeys",
+    "programs",
+    "shard",
+    "only_changed",
+    "stop_on_match",
+    "no_slots",
+    "no_accounts",
+    "no_transactions",
+    "no_blocks",
+    "request_json",
+];
+
+/// Options that may also be written in the `sinks` section.
+const SINK_OPTIONS: [&str; 7] = ["jsonl", "checksum", "compress_output", "strict_order", "sqlite", "ws_addr", "manifest"];
+
+/// The sections a file may group options in, with the options each takes.
+const SECTIONS: [(&str, &[&str]); 2] = [("filters", &FILTER_OPTIONS), ("sinks", &SINK_OPTIONS)];
+
+/// The config file. Options are keyed by their name and hold a value or a
+/// list; they can be written at the top level or, for the options that
+/// belong there, in the `filters` and `sinks` sections.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    filters: BTreeMap<String, Setting>,
+    sinks: BTreeMap<String, Setting>,
+    #[serde(flatten)]
+    options: BTreeMap<String, Setting>,
+}
+
+/// One option's value. Scalars are kept as written, for the options' own
+/// value parsers to check.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+enum Setting {
+    Scalar(String),
+    List(Vec<String>),
+}
+
+/// A parsed YAML value.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Scalar(String),
+    List(Vec<String>),
+    Map(Entries),
+}
+
+/// The keys of a mapping with their line and value, in file order.
+type Entries = Vec<(usize, String, Node)>;
+
+/// Problems found so far, with their line.
+type Errors = Vec<(usize, String)>;
+
+/// The line of every option, keyed by its section and name.
+type Lines = HashMap<(&'static str, String), usize>;
+
+/// The `--config` / `CONFIG_FILE` path, looked up before the full parse
+/// since the file supplies defaults for that parse.
+pub fn path_from_args() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    std::env::var_os("CONFIG_FILE").map(PathBuf::from)
+}
+
+/// Loads the YAML config file at `path` and installs its values as the
+/// defaults of `command`'s options, so environment variables and flags
+/// still override them. Every invalid entry is reported, not just the
+/// first.
+///
+/// Keys are the option names, in snake_case (`accounts_file`) or
+/// kebab-case (`accounts-file`), at the top level or in the `filters` and
+/// `sinks` sections.
+pub fn apply(command: Command, path: &Path) -> Result<Command> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("failed to read config file {}", path.display()))?;
+    apply_str(command, &contents).map_err(|e| anyhow!("invalid config file {}:\n  {:#}", path.display(), e))
+}
+
+fn apply_str(mut command: Command, contents: &str) -> Result<Command> {
+    let (root, mut errors) = parse(contents);
+    let (file, lines) = load(root, &mut errors)?;
+
+    // Validated against a built copy, which has the flags' value parsers
+    let mut built = command.clone();
+    built.build();
+    let mut defaults = Vec::new();
+    let sections = [("", &file.options), ("filters", &file.filters), ("sinks", &file.sinks)];
+    for (section, options) in sections {
+        for (key, setting) in options {
+            let line = lines[&(section, key.clone())];
+            let id = key.replace('-', "_");
+            let Some(arg) = built
+                .get_arguments()
+                .find(|arg| arg.get_id().as_str() == id && !NOT_IN_FILE.contains(&id.as_str()))
+            else {
+                errors.push((line, format!("unknown option `{key}`")));
+                continue;
+            };
+            if let Some((_, members)) = SECTIONS.iter().find(|(name, _)| *name == section)
+                && !members.contains(&id.as_str())
+            {
+                errors.push((line, format!("`{key}` doesn't belong in `{section}`, move it to the top level")));
+                continue;
+            }
+
+            let values = match setting {
+                Setting::Scalar(value) => vec![value.clone()],
+                Setting::List(values) if arg.get_value_delimiter().is_some() => values.clone(),
+                Setting::List(_) => {
+                    errors.push((line, format!("`{key}` takes a single value, not a list")));
+                    continue;
+                }
+            };
+            // Flags only accept `true`/`false` here, not YAML's yes/no
+            let invalid = values.iter().find_map(|value| check_value(arg, value).err());
+            if let Some(error) = invalid {
+                errors.push((line, clap_message(&error)));
+                continue;
+            }
+            if matches!(arg.get_action(), ArgAction::SetTrue) && values.iter().any(|value| value == "false") {
+                continue;
+            }
+            defaults.push((arg.get_id().clone(), values));
+        }
+    }
+
+    if !errors.is_empty() {
+        errors.sort_by_key(|(line, _)| *line);
+        let errors: Vec<String> = errors.into_iter().map(|(line, error)| format!("line {line}: {error}")).collect();
+        bail!("{}", errors.join("\n  "));
+    }
+    for (id, values) in defaults {
+        command = command.mut_arg(id, |arg| arg.default_values(values));
+    }
+    Ok(command)
+}
+
+/// Reads the parsed document into a [`ConfigFile`], with the line of
+/// every option keyed by its section (`""` at the top level). Mappings
+/// anywhere but in a section, and options set twice, are errors.
+fn load(root: Entries, errors: &mut Errors) -> Result<(ConfigFile, Lines)> {
+    let mut lines = HashMap::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+    let mut document = serde_json::Map::new();
+    let mut sections: BTreeMap<&str, serde_json::Map<String, serde_json::Value>> = BTreeMap::new();
+
+    let mut add = |section: &'static str, line: usize, key: String, node: Node, errors: &mut Errors| {
+        let value = match node {
+            Node::Scalar(value) => serde_json::Value::String(value),
+            Node::List(items) => items.into_iter().map(serde_json::Value::String).collect(),
+            Node::Map(_) => {
+                errors.push((line, format!("`{key}` takes a value or a list, not a mapping")));
+                return None;
+            }
+        };
+        let id = key.replace('-', "_");
+        if let Some(first) = seen.get(&id) {
+            errors.push((line, format!("`{key}` is already set on line {first}")));
+            return None;
+        }
+        seen.insert(id, line);
+        lines.insert((section, key.clone()), line);
+        Some((key, value))
+    };
+
+    for (line, key, node) in root {
+        match SECTIONS.iter().find(|(name, _)| *name == key) {
+            Some((section, _)) => {
+                let Node::Map(entries) = node else {
+                    errors.push((line, format!("`{key}` is a section, indent its options below it")));
+                    continue;
+                };
+                let options = sections.entry(section).or_default();
+                for (line, key, node) in entries {
+                    options.extend(add(section, line, key, node, errors));
+                }
+            }
+            None => document.extend(add("", line, key, node, errors)),
+        }
+    }
+    for (section, options) in sections {
+        document.insert(section.to_string(), serde_json::Value::Object(options));
+    }
+    let file = serde_json::from_value(serde_json::Value::Object(document)).context("unexpected config layout")?;
+    Ok((file, lines))
+}
+
+/// Runs `value` through the option's value parser on its own, so that
+/// other options' requirements don't get in the way.
+fn check_value(arg: &Arg, value: &str) -> Result<(), clap::Error> {
+    let long = arg.get_long().unwrap_or(arg.get_id().as_str()).to_string();
+    let mut check = Arg::new(arg.get_id().clone())
+        .long(long.clone())
+        .value_parser(arg.get_value_parser().clone());
+    if let Some(names) = arg.get_value_names() {
+        check = check.value_names(names.to_vec());
+    }
+    Command::new("config")
+        .no_binary_name(true)
+        .arg(check)
+        .try_get_matches_from([OsString::from(format!("--{long}={value}"))])
+        .map(drop)
+}
+
+/// The first line of a clap error, without its `error: ` prefix.
+fn clap_message(error: &clap::Error) -> String {
+    let rendered = error.to_string();
+    let line = rendered.lines().next().unwrap_or_default();
+    line.strip_prefix("error: ").unwrap_or(line).to_string()
+}
+
+/// A line that holds something, with its indentation.
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// Parses the subset of YAML a config needs: `key: value` mappings,
+/// nested by indentation, block lists (`- item`) and flow lists
+/// (`[a, b]`), quoted or plain scalars and `#` comments. Empty values and
+/// `null`/`~` leave an option unset.
+fn parse(contents: &str) -> (Entries, Errors) {
+    let mut errors = Vec::new();
+    let mut lines = Vec::new();
+    for (index, raw) in contents.lines().enumerate() {
+        let number = index + 1;
+        let text = strip_comment(raw).trim_end();
+        if text.trim().is_empty() || text == "---" {
+            continue;
+        }
+        let trimmed = text.trim_start_matches(' ');
+        if trimmed.starts_with('\t') {
+            errors.push((number, "indent with spaces, not tabs".to_string()));
+            continue;
+        }
+        lines.push(Line { number, indent: text.len() - trimmed.len(), text: trimmed });
+    }
+    let mut next = 0;
+    let root = parse_map(&lines, &mut next, 0, &mut errors);
+    (root, errors)
+}
+
+/// Reads the mapping whose keys are indented by `indent`, starting at
+/// `lines[*next]`, up to the first line indented less.
+fn parse_map(lines: &[Line], next: &mut usize, indent: usize, errors: &mut Errors) -> Entries {
+    let mut entries = Entries::new();
+    while let Some(line) = lines.get(*next) {
+        if line.indent < indent {
+            break;
+        }
+        *next += 1;
+        if line.indent > indent {
+            errors.push((line.number, "unexpected indentation".to_string()));
+            continue;
+        }
+        if line.text.starts_with('-') {
+            errors.push((line.number, "a list item needs a key above it".to_string()));
+            continue;
+        }
+        let Some((key, value)) = line.text.split_once(':') else {
+            errors.push((line.number, "expected `key: value`".to_string()));
+            continue;
+        };
+        let key = key.trim().to_string();
+        if let Some((first, _, _)) = entries.iter().find(|(_, existing, _)| *existing == key) {
+            errors.push((line.number, format!("`{key}` is already set on line {first}")));
+            // Skips whatever is nested below it
+            parse_map(lines, next, indent + 1, &mut Vec::new());
+            continue;
+        }
+        let value = value.trim();
+        let node = match lines.get(*next) {
+            _ if !value.is_empty() => parse_scalar(value),
+            Some(child) if child.indent > indent && child.text.starts_with('-') => {
+                Some(Node::List(parse_list(lines, next, child.indent, errors)))
+            }
+            Some(child) if child.indent > indent => Some(Node::Map(parse_map(lines, next, child.indent, errors))),
+            // Nothing below it leaves the option unset
+            _ => None,
+        };
+        entries.extend(node.map(|node| (line.number, key, node)));
+    }
+    entries
+}
+
+/// Reads the `- item` lines indented by `indent`.
+fn parse_list(lines: &[Line], next: &mut usize, indent: usize, errors: &mut Errors) -> Vec<String> {
+    let mut items = Vec::new();
+    while let Some(line) = lines.get(*next).filter(|line| line.indent >= indent) {
+        *next += 1;
+        match line.text.strip_prefix('-') {
+            Some(item) if line.indent == indent && !item.contains(": ") => items.push(unquote(item.trim())),
+            Some(_) if line.indent == indent => {
+                errors.push((line.number, "list items must be values, not mappings".to_string()));
+            }
+            _ => errors.push((line.number, "expected a `- item` of the list above".to_string())),
+        }
+    }
+    items
+}
+
+/// A value on the same line as its key; `None` for `~` and `null`.
+fn parse_scalar(value: &str) -> Option<Node> {
+    if value == "~" || value == "null" {
+        return None;
+    }
+    match value.strip_prefix('[').and_then(|value| value.strip_suffix(']')) {
+        Some(items) => Some(Node::List(
+            items.split(',').map(str::trim).filter(|item| !item.is_empty()).map(unquote).collect(),
+        )),
+        None => Some(Node::Scalar(unquote(value))),
+    }
+}
+
+/// Drops a `#` comment, unless the `#` is inside quotes or part of a word.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, c) in line.char_indices() {
+        match quote {
+            Some(open) if c == open => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..index],
+            None => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+fn unquote(value: &str) -> String {
+    if let Some(inner) = value.strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+        inner.replace("\\\"", "\"").replace("\\\\", "\\")
+    } else if let Some(inner) = value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')) {
+        inner.replace("''", "'")
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use clap::{CommandFactory, FromArgMatches};
+
+    fn scalar(value: &str) -> Node {
+        Node::Scalar(value.to_string())
+    }
+
+    fn list(items: &[&str]) -> Node {
+        Node::List(items.iter().map(ToString::to_string).collect())
+    }
+
+    /// The options `contents` sets, as the run would see them.
+    fn load_config(contents: &str) -> Result<Config> {
+        let command = apply_str(Config::command(), contents)?;
+        Ok(Config::from_arg_matches(&command.try_get_matches_from(["client"])?)?)
+    }
+
+    #[test]
+    fn comments_are_kept_inside_quotes() {
+        assert_eq!(strip_comment(r#"x_token: "abc # def"  # the token"#), r#"x_token: "abc # def"  "#);
+        assert_eq!(strip_comment("user_agent: 'it''s #1' # quoted"), "user_agent: 'it''s #1' ");
+        assert_eq!(strip_comment("tls_domain: host#1"), "tls_domain: host#1");
+        assert_eq!(strip_comment("# a whole line"), "");
+        let (root, errors) = parse("user_agent: \"a # b\" # c\n");
+        assert!(errors.is_empty());
+        assert_eq!(root, [(1, "user_agent".to_string(), scalar("a # b"))]);
+    }
+
+    #[test]
+    fn block_and_flow_lists() {
+        let contents = "x_tokens:\n  - 'one'\n  - two # the second\nheaders: [\"a:1\", b:2]\nprograms: []\n";
+        let (root, errors) = parse(contents);
+        assert!(errors.is_empty(), "{errors:?}");
+        assert_eq!(
+            root,
+            [
+                (1, "x_tokens".to_string(), list(&["one", "two"])),
+                (4, "headers".to_string(), list(&["a:1", "b:2"])),
+                (5, "programs".to_string(), list(&[])),
+            ]
+        );
+        let config = load_config(contents).unwrap();
+        assert_eq!(config.x_tokens, ["one", "two"]);
+        assert_eq!(config.headers, ["a:1", "b:2"]);
+    }
+
+    #[test]
+    fn sections_group_options() {
+        let contents = "\
+commitment: finalized
+filters:
+  transactions: true
+  tx-signatures:
+    - 5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW
+sinks:
+  jsonl: updates.jsonl
+  checksum: true
+";
+        let config = load_config(contents).unwrap();
+        assert!(config.transactions);
+        assert_eq!(config.tx_signatures.len(), 1);
+        assert_eq!(config.jsonl.as_deref(), Some(Path::new("updates.jsonl")));
+        assert!(config.checksum);
+    }
+
+    #[test]
+    fn the_example_config_loads() {
+        let config = load_config(include_str!("../config.example.yaml")).unwrap();
+        assert_eq!(config.endpoint, "https://basic.grpc.solanavibestation.com");
+        assert!(!config.transactions);
+    }
+
+    #[test]
+    fn unknown_keys_are_reported() {
+        let error = load_config("acounts_file: accounts.txt\nfilters:\n  transactoins: true\n").unwrap_err();
+        assert_eq!(
+            format!("{error:#}"),
+            "line 1: unknown option `acounts_file`\n  line 3: unknown option `transactoins`"
+        );
+    }
+
+    #[test]
+    fn every_error_is_reported_with_its_line() {
+        let contents = "\
+commitment: sometimes
+filters:
+  jsonl: updates.jsonl
+sinks: updates.jsonl
+endpoint:
+  url: https://example.com
+\tx_token: abc
+endpoint_tokens: tokens.json
+endpoint_tokens: other.json
+commitment_level
+transactions: yes
+";
+        let error = load_config(contents).unwrap_err();
+        let expected = [
+            "line 1: invalid value 'sometimes' for '--commitment <COMMITMENT>'",
+            "line 3: `jsonl` doesn't belong in `filters`, move it to the top level",
+            "line 4: `sinks` is a section, indent its options below it",
+            "line 5: `endpoint` takes a value or a list, not a mapping",
+            "line 7: indent with spaces, not tabs",
+            "line 9: `endpoint_tokens` is already set on line 8",
+            "line 10: expected `key: value`",
+            "line 11: invalid value 'yes' for '--transactions <TRANSACTIONS>'",
+        ];
+        assert_eq!(format!("{error:#}"), expected.join("\n  "));
+    }
+
+    #[test]
+    fn an_option_is_set_once_across_sections() {
+        let error = load_config("transactions: true\nfilters:\n  transactions: false\n").unwrap_err();
+        assert_eq!(format!("{error:#}"), "line 3: `transactions` is already set on line 1");
+    }
+}
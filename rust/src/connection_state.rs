@@ -0,0 +1,67 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Where the reconnect loop currently is.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Opening a connection and subscribing
+    #[default]
+    Connecting,
+    /// Streaming
+    Connected,
+    /// Waiting to retry after a failure
+    Backoff,
+    /// Closed on purpose after `IDLE_UNSUBSCRIBE_SECS`
+    Idle,
+}
+
+impl ConnectionState {
+    pub const ALL: [Self; 4] = [Self::Connecting, Self::Connected, Self::Backoff, Self::Idle];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Connecting => "connecting",
+            Self::Connected => "connected",
+            Self::Backoff => "backoff",
+            Self::Idle => "idle",
+        }
+    }
+}
+
+/// Connection health as seen by the reconnect loop, for the metrics
+/// endpoint.
+#[derive(Debug, Default)]
+pub struct ConnectionHealth {
+    pub state: ConnectionState,
+    /// Failed attempts since the last stream that delivered messages
+    pub consecutive_failures: u32,
+    /// When the last reconnect (any connection after the first) succeeded
+    pub last_reconnect_unix: Option<u64>,
+    connected_once: bool,
+}
+
+impl ConnectionHealth {
+    pub fn connecting(&mut self) {
+        self.state = ConnectionState::Connecting;
+    }
+
+    pub fn connected(&mut self) {
+        self.state = ConnectionState::Connected;
+        if self.connected_once {
+            self.last_reconnect_unix = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|elapsed| elapsed.as_secs());
+        }
+        self.connected_once = true;
+    }
+
+    pub fn failed(&mut self, consecutive_failures: u32) {
+        self.state = ConnectionState::Backoff;
+        self.consecutive_failures = consecutive_failures;
+    }
+
+    pub fn idle(&mut self) {
+        self.state = ConnectionState::Idle;
+        self.consecutive_failures = 0;
+    }
+}
@@ -0,0 +1,33 @@
+/// Anchor account discriminators (`sha256("account:<Name>")[..8]`) of
+/// widely used programs. Add entries as needed; the first match wins.
+const KNOWN: &[([u8; 8], &str, &str)] = &[
+    ([63, 149, 209, 12, 225, 128, 99, 9], "Whirlpool", "Orca Whirlpools"),
+    ([157, 20, 49, 224, 217, 87, 193, 254], "WhirlpoolsConfig", "Orca Whirlpools"),
+    ([69, 97, 189, 190, 110, 7, 66, 187], "TickArray", "Orca Whirlpools"),
+    ([170, 188, 143, 228, 122, 64, 247, 208], "Position", "Orca Whirlpools"),
+    ([247, 237, 227, 245, 215, 195, 222, 70], "PoolState", "Raydium"),
+    ([218, 244, 33, 104, 203, 203, 43, 111], "AmmConfig", "Raydium"),
+    ([70, 111, 150, 126, 230, 15, 25, 117], "PersonalPositionState", "Raydium CLMM"),
+    ([192, 155, 85, 205, 49, 249, 129, 42], "TickArrayState", "Raydium CLMM"),
+    ([33, 11, 49, 98, 181, 101, 177, 13], "LbPair", "Meteora DLMM"),
+    ([92, 142, 92, 220, 5, 148, 70, 181], "BinArray", "Meteora DLMM"),
+    ([67, 178, 130, 109, 126, 114, 28, 42], "MarginfiAccount", "marginfi"),
+    ([142, 49, 166, 242, 50, 66, 97, 188], "Bank", "marginfi"),
+];
+
+/// `, discriminator=<hex>[, type=<Name> (<program>)]` for an account's
+/// data, to append to its log line.
+pub fn log_suffix(data: &[u8]) -> String {
+    let Some(discriminator) = data.first_chunk::<8>() else {
+        return format!(", discriminator=none ({} bytes of data)", data.len());
+    };
+    match KNOWN.iter().find(|(known, _, _)| known == discriminator) {
+        Some((_, name, program)) => format!(", discriminator={}, type={name} ({program})", hex(discriminator)),
+        None => format!(", discriminator={}", hex(discriminator)),
+    }
+}
+
+/// Lower-case hex encoding.
+pub fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{byte:02x}")).collect()
+}
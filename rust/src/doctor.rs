@@ -0,0 +1,313 @@
+use anyhow::{Result, bail};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_stream::StreamExt;
+use tonic::codegen::http::Uri;
+use yellowstone_grpc_client::{GeyserGrpcClient, GeyserGrpcClientError};
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::client::{self, ConnectOptions, MetadataInterceptor};
+use crate::config::Config;
+use crate::request;
+
+/// How long the clock check waits for a timestamped update.
+const CLOCK_CHECK_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Clock differences above this are reported; latency measurements and
+/// anything comparing `created_at` with local time get skewed.
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(2);
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Pass,
+    /// Worth fixing, but a run can still work
+    Warn,
+    /// A run will fail
+    Fail,
+    /// Couldn't be checked
+    Skipped,
+}
+
+struct Checklist {
+    failures: usize,
+}
+
+impl Checklist {
+    fn report(&mut self, outcome: Outcome, name: &str, detail: &str, hint: Option<&str>) {
+        let label = match outcome {
+            Outcome::Pass => "PASS",
+            Outcome::Warn => "WARN",
+            Outcome::Fail => "FAIL",
+            Outcome::Skipped => "SKIP",
+        };
+        println!("[{label}] {name}: {detail}");
+        if let Some(hint) = hint
+            && outcome != Outcome::Pass
+        {
+            println!("       hint: {hint}");
+        }
+        if outcome == Outcome::Fail {
+            self.failures += 1;
+        }
+    }
+}
+
+/// Runs the setup checks, printing a checklist. Fails if any critical
+/// check failed, so the exit code can gate scripts.
+pub async fn run(config: &Config, options: &ConnectOptions) -> Result<()> {
+    let mut checks = Checklist { failures: 0 };
+    println!("Checking the setup for {}", options.endpoint);
+
+    // Filters don't need the network, and a broken one fails every run
+    match request::build_subscribe_requests(config) {
+        Ok(requests) => {
+            let data_filters: usize = requests
+                .iter()
+                .map(|request| request.accounts.len() + request.transactions.len())
+                .sum();
+            if data_filters == 0 {
+                checks.report(
+                    Outcome::Warn,
+                    "filters",
+                    "no account or transaction filter is configured, only slot updates will arrive",
+                    Some("set ACCOUNTS_FILE, ACCOUNT_OWNERS or a transaction filter, see `--help`"),
+                );
+            } else {
+                checks.report(
+                    Outcome::Pass,
+                    "filters",
+                    &format!("{} account/transaction filters in {} request(s)", data_filters, requests.len()),
+                    None,
+                );
+            }
+        }
+        Err(e) => checks.report(
+            Outcome::Fail,
+            "filters",
+            &format!("{e:#}"),
+            Some("fix the filter options; `--list-filters` shows what is subscribed"),
+        ),
+    }
+
+    match &options.x_token {
+        None => checks.report(
+            Outcome::Warn,
+            "token",
+            "no access token configured",
+            Some("fine for IP-authenticated endpoints, otherwise set GEYSER_ACCESS_TOKEN"),
+        ),
+        Some(token) => match token_problem(token) {
+            Some(problem) => checks.report(
+                Outcome::Fail,
+                "token",
+                problem,
+                Some("copy the token exactly as the provider shows it, without quotes or a prefix"),
+            ),
+            None => checks.report(Outcome::Pass, "token", &format!("{} characters", token.len()), None),
+        },
+    }
+
+    let uri = match options.endpoint.parse::<Uri>() {
+        Ok(uri) if uri.host().is_some() && matches!(uri.scheme_str(), Some("http" | "https")) => uri,
+        _ => {
+            checks.report(
+                Outcome::Fail,
+                "endpoint",
+                &format!("`{}` is not an http(s) URL with a host", options.endpoint),
+                Some("set GEYSER_ENDPOINT to e.g. https://grpc.example.com:443"),
+            );
+            return finish(checks);
+        }
+    };
+    let tls = uri.scheme_str() == Some("https");
+    let host = uri.host().unwrap_or_default().trim_start_matches('[').trim_end_matches(']').to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    let transport = if tls { "TLS" } else { "plaintext" };
+    checks.report(Outcome::Pass, "endpoint", &format!("{host}:{port} over {transport}"), None);
+
+    let timeout = options.preset.connect_timeout;
+    match tokio::time::timeout(timeout, tokio::net::lookup_host((host.as_str(), port))).await {
+        Ok(Ok(addrs)) => {
+            let addrs: Vec<String> = addrs.map(|addr| addr.ip().to_string()).collect();
+            checks.report(Outcome::Pass, "DNS", &format!("{host} resolves to {}", addrs.join(", ")), None);
+        }
+        Ok(Err(e)) => {
+            checks.report(
+                Outcome::Fail,
+                "DNS",
+                &format!("failed to resolve {host}: {e}"),
+                Some("check the hostname and the DNS settings"),
+            );
+            return finish(checks);
+        }
+        Err(_) => {
+            checks.report(
+                Outcome::Fail,
+                "DNS",
+                &format!("resolving {host} took over {timeout:?}"),
+                Some("check the DNS settings"),
+            );
+            return finish(checks);
+        }
+    }
+
+    let tcp = match tokio::time::timeout(timeout, client::connect_tcp(&host, port, options.ip_version)).await {
+        Ok(Ok(tcp)) => {
+            let peer = tcp.peer_addr().map(|addr| addr.to_string()).unwrap_or_default();
+            checks.report(Outcome::Pass, "TCP", &format!("connected to {peer}"), None);
+            tcp
+        }
+        Ok(Err(e)) => {
+            checks.report(
+                Outcome::Fail,
+                "TCP",
+                &format!("{e}"),
+                Some("check the port and that no firewall blocks outbound connections; try IP_VERSION"),
+            );
+            return finish(checks);
+        }
+        Err(_) => {
+            checks.report(
+                Outcome::Fail,
+                "TCP",
+                &format!("no connection within {timeout:?}"),
+                Some("a firewall may be dropping the connection"),
+            );
+            return finish(checks);
+        }
+    };
+
+    if tls {
+        let domain = client::effective_tls_domain(options).unwrap_or_else(|| host.clone());
+        let handshake = async {
+            let server_name = ServerName::try_from(domain.clone())?;
+            let mut tls_config = ClientConfig::builder()
+                .with_root_certificates(client::native_roots())
+                .with_no_client_auth();
+            tls_config.alpn_protocols = vec![b"h2".to_vec()];
+            let stream = TlsConnector::from(Arc::new(tls_config)).connect(server_name, tcp).await?;
+            let (_, session) = stream.get_ref();
+            anyhow::Ok((session.protocol_version(), session.alpn_protocol() == Some(b"h2")))
+        };
+        match tokio::time::timeout(timeout, handshake).await {
+            Ok(Ok((version, true))) => checks.report(
+                Outcome::Pass,
+                "TLS",
+                &format!("{version:?} handshake with {domain}, HTTP/2 negotiated"),
+                None,
+            ),
+            Ok(Ok((version, false))) => checks.report(
+                Outcome::Fail,
+                "TLS",
+                &format!("{version:?} handshake with {domain}, but the server didn't agree to HTTP/2"),
+                Some("the endpoint may be a web page or proxy rather than the gRPC port"),
+            ),
+            Ok(Err(e)) => checks.report(
+                Outcome::Fail,
+                "TLS",
+                &format!("handshake with {domain} failed: {e:#}"),
+                Some("check the system clock and root certificates; set TLS_DOMAIN if the certificate names another host"),
+            ),
+            Err(_) => checks.report(
+                Outcome::Fail,
+                "TLS",
+                &format!("no handshake within {timeout:?}"),
+                Some("the port may not speak TLS; try http://"),
+            ),
+        }
+        if checks.failures > 0 {
+            return finish(checks);
+        }
+    } else {
+        drop(tcp);
+    }
+
+    let mut client = match client::connect(options).await {
+        Ok(client) => client,
+        Err(e) => {
+            checks.report(Outcome::Fail, "gRPC", &format!("{e:#}"), None);
+            return finish(checks);
+        }
+    };
+    match client.get_version().await {
+        Ok(version) => checks.report(Outcome::Pass, "gRPC", &format!("server version {}", version.version), None),
+        Err(e) => {
+            let hint = match &e {
+                GeyserGrpcClientError::TonicStatus(status)
+                    if matches!(status.code(), tonic::Code::Unauthenticated | tonic::Code::PermissionDenied) =>
+                {
+                    "check the access token"
+                }
+                _ => "the provider's status page or support can tell whether the endpoint is up",
+            };
+            checks.report(Outcome::Fail, "gRPC", &format!("GetVersion failed: {e}"), Some(hint));
+            return finish(checks);
+        }
+    }
+
+    match clock_skew(&mut client).await {
+        Ok(skew) if skew.abs() <= MAX_CLOCK_SKEW.as_secs_f64() => {
+            checks.report(Outcome::Pass, "clock", &format!("local clock {skew:+.3}s off the server's created_at"), None)
+        }
+        Ok(skew) => checks.report(
+            Outcome::Warn,
+            "clock",
+            &format!("local clock {skew:+.3}s off the server's created_at, including network latency"),
+            Some("sync the system clock (e.g. enable NTP); latency figures are off by as much"),
+        ),
+        Err(e) => checks.report(Outcome::Skipped, "clock", &format!("{e:#}"), None),
+    }
+
+    finish(checks)
+}
+
+fn finish(checks: Checklist) -> Result<()> {
+    if checks.failures > 0 {
+        bail!("{} critical check(s) failed", checks.failures);
+    }
+    println!("All critical checks passed");
+    Ok(())
+}
+
+/// What's wrong with a token as pasted, if it looks broken.
+fn token_problem(token: &str) -> Option<&'static str> {
+    if token.chars().any(char::is_whitespace) {
+        Some("the token contains whitespace, likely a stray newline or space")
+    } else if token.starts_with(['"', '\'']) || token.ends_with(['"', '\'']) {
+        Some("the token is wrapped in quotes")
+    } else if token.to_ascii_lowercase().starts_with("bearer") {
+        Some("the token starts with `Bearer`; x-token takes the bare token")
+    } else if !token.is_ascii() {
+        Some("the token contains non-ASCII characters, which can't be sent as a header")
+    } else {
+        None
+    }
+}
+
+/// Seconds the local clock is ahead of the server's timestamp on the first
+/// slot update. Network latency is included, so small positive values are
+/// expected.
+async fn clock_skew(client: &mut GeyserGrpcClient<MetadataInterceptor>) -> Result<f64> {
+    let request = SubscribeRequest {
+        slots: [("doctor".to_string(), SubscribeRequestFilterSlots::default())].into(),
+        ..Default::default()
+    };
+    let (_subscribe_tx, mut stream) = client.subscribe_with_request(Some(request)).await?;
+    let created_at = tokio::time::timeout(CLOCK_CHECK_TIMEOUT, async {
+        while let Some(message) = stream.next().await {
+            if let Some(created_at) = message?.created_at {
+                return Ok(created_at);
+            }
+        }
+        bail!("the server ended the stream")
+    });
+    let created_at = match created_at.await {
+        Ok(created_at) => created_at?,
+        Err(_) => bail!("no timestamped update within {CLOCK_CHECK_TIMEOUT:?}"),
+    };
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs_f64();
+    Ok(now - (created_at.seconds as f64 + f64::from(created_at.nanos) / 1e9))
+}
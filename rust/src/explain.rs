@@ -0,0 +1,190 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use yellowstone_grpc_proto::prelude::*;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter::Filter as AccountsFilter;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter_lamports::Cmp;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter_memcmp::Data;
+
+/// How many pubkeys of a list are spelled out before summarising the rest.
+const LIST_PREVIEW: usize = 3;
+
+/// Explains in plain words what the subscription will receive, one
+/// paragraph per stream, derived from the requests that will be sent.
+pub fn describe(requests: &[SubscribeRequest]) -> String {
+    let mut out = String::new();
+    for (index, request) in requests.iter().enumerate() {
+        if index > 0 {
+            out.push('\n');
+        }
+        let commitment = CommitmentLevel::try_from(request.commitment.unwrap_or_default())
+            .map(|level| level.as_str_name().to_lowercase())
+            .unwrap_or_else(|_| format!("unknown({})", request.commitment.unwrap_or_default()));
+        let _ = write!(out, "Stream {} of {} at {} commitment", index + 1, requests.len(), commitment);
+        match request.from_slot {
+            Some(slot) => {
+                let _ = writeln!(out, ", replaying from slot {slot}:");
+            }
+            None => out.push_str(", starting at the current slot:\n"),
+        }
+
+        for (name, filter) in sorted(&request.slots) {
+            let interslot = if filter.interslot_updates == Some(true) {
+                ", plus intra-slot statuses (first shred, completed, ...)"
+            } else {
+                ""
+            };
+            let scope = if filter.filter_by_commitment == Some(true) {
+                "slots as they reach the commitment level"
+            } else {
+                "every slot status change"
+            };
+            let _ = writeln!(out, "  slots \"{name}\": {scope}{interslot}");
+        }
+        for (name, filter) in sorted(&request.accounts) {
+            let _ = writeln!(out, "  accounts \"{name}\": {}", describe_accounts(filter));
+        }
+        for (name, filter) in sorted(&request.transactions) {
+            let _ = writeln!(out, "  transactions \"{name}\": {}", describe_transactions(filter));
+        }
+        for (name, filter) in sorted(&request.transactions_status) {
+            let _ = writeln!(out, "  transaction statuses \"{name}\": {}", describe_transactions(filter));
+        }
+        for (name, filter) in sorted(&request.blocks) {
+            let _ = writeln!(
+                out,
+                "  blocks \"{name}\": {}, transactions {}, accounts {}, entries {}",
+                accounts_clause("touching", &filter.account_include),
+                yes_no(filter.include_transactions),
+                yes_no(filter.include_accounts),
+                yes_no(filter.include_entries)
+            );
+        }
+        for name in sorted(&request.blocks_meta).into_iter().map(|(name, _)| name) {
+            let _ = writeln!(out, "  block metadata \"{name}\": every block");
+        }
+        for name in sorted(&request.entry).into_iter().map(|(name, _)| name) {
+            let _ = writeln!(out, "  entries \"{name}\": every entry");
+        }
+
+        if !request.accounts.is_empty() {
+            if request.accounts_data_slice.is_empty() {
+                out.push_str("  account data: full\n");
+            } else {
+                let slices: Vec<String> = request
+                    .accounts_data_slice
+                    .iter()
+                    .map(|slice| format!("bytes {}..{}", slice.offset, slice.offset + slice.length))
+                    .collect();
+                let _ = writeln!(out, "  account data: only {}", slices.join(", "));
+            }
+        }
+    }
+    out
+}
+
+fn describe_accounts(filter: &SubscribeRequestFilterAccounts) -> String {
+    let mut parts = Vec::new();
+    if filter.account.is_empty() && filter.owner.is_empty() {
+        parts.push("every account".to_string());
+    }
+    if !filter.account.is_empty() {
+        parts.push(list("account", &filter.account));
+    }
+    if !filter.owner.is_empty() {
+        parts.push(format!("owned by {}", list("program", &filter.owner)));
+    }
+    for data_filter in filter.filters.iter().filter_map(|data_filter| data_filter.filter.as_ref()) {
+        parts.push(match data_filter {
+            AccountsFilter::Memcmp(memcmp) => {
+                let data = match &memcmp.data {
+                    Some(Data::Bytes(bytes)) => format!("0x{}", crate::discriminators::hex(bytes)),
+                    Some(Data::Base58(data)) => format!("{data} (base58)"),
+                    Some(Data::Base64(data)) => format!("{data} (base64)"),
+                    None => "nothing".to_string(),
+                };
+                format!("data at offset {} matching {}", memcmp.offset, data)
+            }
+            AccountsFilter::Datasize(size) => format!("exactly {size} bytes of data"),
+            AccountsFilter::TokenAccountState(_) => "valid token accounts only".to_string(),
+            AccountsFilter::Lamports(lamports) => match lamports.cmp {
+                Some(Cmp::Eq(value)) => format!("lamports == {value}"),
+                Some(Cmp::Ne(value)) => format!("lamports != {value}"),
+                Some(Cmp::Lt(value)) => format!("lamports < {value}"),
+                Some(Cmp::Gt(value)) => format!("lamports > {value}"),
+                None => "any lamports".to_string(),
+            },
+        });
+    }
+    if filter.nonempty_txn_signature == Some(true) {
+        parts.push("only updates caused by a transaction".to_string());
+    }
+    parts.join(", ")
+}
+
+fn describe_transactions(filter: &SubscribeRequestFilterTransactions) -> String {
+    let mut parts = Vec::new();
+    match &filter.signature {
+        Some(signature) => parts.push(format!("the transaction {signature}")),
+        None => parts.push("every transaction".to_string()),
+    }
+    parts.push(
+        match filter.vote {
+            Some(true) => "votes only",
+            Some(false) => "votes excluded",
+            None => "votes included",
+        }
+        .to_string(),
+    );
+    parts.push(
+        match filter.failed {
+            Some(true) => "failed only",
+            Some(false) => "failed excluded",
+            None => "failed included",
+        }
+        .to_string(),
+    );
+    if !filter.account_include.is_empty() {
+        parts.push(accounts_clause("touching any of", &filter.account_include));
+    }
+    if !filter.account_required.is_empty() {
+        parts.push(accounts_clause("touching all of", &filter.account_required));
+    }
+    if !filter.account_exclude.is_empty() {
+        parts.push(accounts_clause("not touching", &filter.account_exclude));
+    }
+    parts.join(", ")
+}
+
+fn accounts_clause(verb: &str, accounts: &[String]) -> String {
+    if accounts.is_empty() {
+        "all of them".to_string()
+    } else {
+        format!("{verb} {}", list("account", accounts))
+    }
+}
+
+/// `1 account (Abc...)` or `120 accounts (A, B, C, and 117 more)`.
+fn list(noun: &str, items: &[String]) -> String {
+    let plural = if items.len() == 1 { "" } else { "s" };
+    let mut preview: Vec<&str> = items.iter().take(LIST_PREVIEW).map(String::as_str).collect();
+    let more = items.len().saturating_sub(LIST_PREVIEW);
+    let more = format!("and {more} more");
+    if items.len() > LIST_PREVIEW {
+        preview.push(&more);
+    }
+    format!("{} {noun}{plural} ({})", items.len(), preview.join(", "))
+}
+
+fn yes_no(value: Option<bool>) -> &'static str {
+    match value {
+        Some(true) => "included",
+        _ => "excluded",
+    }
+}
+
+/// Filters in name order, since request maps have no stable order.
+fn sorted<T>(filters: &HashMap<String, T>) -> Vec<(&String, &T)> {
+    let mut filters: Vec<_> = filters.iter().collect();
+    filters.sort_by_key(|(name, _)| *name);
+    filters
+}
@@ -0,0 +1,145 @@
+use anyhow::{Context, Result, bail};
+use clap::Args;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio_stream::StreamExt;
+use tracing::{info, warn};
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::account_fields::AccountFields;
+use crate::client::{self, ConnectOptions};
+use crate::sinks::{ReceivedAt, jsonl};
+use crate::stats;
+
+/// An owner whose accounts change every slot, so one turns up quickly.
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+/// Written in this order, one of each.
+const KINDS: [&str; 4] = ["slot", "account", "transaction", "block"];
+
+#[derive(Debug, Clone, Args)]
+pub struct FixturesArgs {
+    /// Where to write the fixtures, one JSONL record per update type
+    #[arg(long, default_value = "fixtures.jsonl")]
+    pub output: PathBuf,
+
+    /// Give up on the update types still missing after this many seconds
+    #[arg(long, default_value_t = 30)]
+    pub timeout_secs: u64,
+}
+
+/// Broad filters that produce every update type within a few slots,
+/// whatever the configured filters are.
+fn fixtures_request() -> SubscribeRequest {
+    let name = "fixtures".to_string();
+    SubscribeRequest {
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        slots: HashMap::from([(name.clone(), SubscribeRequestFilterSlots::default())]),
+        accounts: HashMap::from([(name.clone(), SubscribeRequestFilterAccounts {
+            owner: vec![TOKEN_PROGRAM.to_string()],
+            ..Default::default()
+        })]),
+        transactions: HashMap::from([(name, SubscribeRequestFilterTransactions {
+            vote: Some(false),
+            ..Default::default()
+        })]),
+        ..Default::default()
+    }
+}
+
+/// Blocks go on a stream of their own: some providers refuse block
+/// subscriptions, which shouldn't cost the other fixtures.
+fn blocks_request() -> SubscribeRequest {
+    SubscribeRequest {
+        commitment: Some(CommitmentLevel::Confirmed as i32),
+        blocks: HashMap::from([("fixtures".to_string(), SubscribeRequestFilterBlocks {
+            // The block itself, not a copy of every transaction in it
+            include_transactions: Some(false),
+            include_accounts: Some(false),
+            include_entries: Some(false),
+            ..Default::default()
+        })]),
+        ..Default::default()
+    }
+}
+
+/// Captures one update of each type and writes them as JSONL fixtures.
+pub async fn run(options: &ConnectOptions, args: &FixturesArgs) -> Result<()> {
+    let timeout = Duration::from_secs(args.timeout_secs);
+    info!("Capturing one slot, account, transaction and block update from {}", options.endpoint);
+
+    let mut client = client::connect(options).await?;
+    let (subscribe_tx, stream) = client.subscribe_with_request(Some(fixtures_request())).await?;
+    let mut subscribe_txs = vec![subscribe_tx];
+    let mut streams = vec![stream];
+    match client.subscribe_with_request(Some(blocks_request())).await {
+        Ok((subscribe_tx, stream)) => {
+            subscribe_txs.push(subscribe_tx);
+            streams.push(stream);
+        }
+        Err(e) => warn!("Block subscription refused, the fixtures will have no block: {}", e),
+    }
+    let mut stream = futures::stream::select_all(
+        streams
+            .into_iter()
+            .enumerate()
+            .map(|(index, stream)| stream.map(move |message| (index, message))),
+    );
+
+    let mut captured: BTreeMap<usize, serde_json::Value> = BTreeMap::new();
+    let deadline = tokio::time::Instant::now() + timeout;
+    while captured.len() < KINDS.len() {
+        let Ok(next) = tokio::time::timeout_at(deadline, stream.next()).await else {
+            break;
+        };
+        let Some((index, message)) = next else {
+            warn!("The server ended the stream");
+            break;
+        };
+        let update = match message {
+            Ok(update) => update,
+            // A refused block stream fails here rather than on subscribing
+            Err(status) if index == 1 => {
+                warn!("Block stream failed, the fixtures will have no block: {}", status);
+                continue;
+            }
+            Err(status) => return Err(status.into()),
+        };
+        if matches!(update.update_oneof, Some(subscribe_update::UpdateOneof::Ping(_))) {
+            client::reply_to_ping(&mut subscribe_txs[index]).await?;
+            continue;
+        }
+        let kind = stats::update_kind(&update);
+        let Some(position) = KINDS.iter().position(|candidate| *candidate == kind) else {
+            continue;
+        };
+        if captured.contains_key(&position) {
+            continue;
+        }
+        if let Some(record) = jsonl::to_json(&update, ReceivedAt::now(), &AccountFields::default()) {
+            info!("Captured a {} update at slot {}", kind, record["slot"]);
+            captured.insert(position, record);
+        }
+    }
+
+    let mut file = std::fs::File::create(&args.output)
+        .with_context(|| format!("failed to create fixtures file {}", args.output.display()))?;
+    for record in captured.values() {
+        serde_json::to_writer(&mut file, record)?;
+        file.write_all(b"\n")?;
+    }
+    info!("Wrote {} fixtures to {}", captured.len(), args.output.display());
+
+    let missing: Vec<&str> = KINDS
+        .iter()
+        .enumerate()
+        .filter(|(position, _)| !captured.contains_key(position))
+        .map(|(_, kind)| *kind)
+        .collect();
+    if !missing.is_empty() {
+        bail!("no {} update within {:?}", missing.join(" or "), timeout);
+    }
+    Ok(())
+}
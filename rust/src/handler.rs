@@ -0,0 +1,622 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::Ordering;
+use tracing::{debug, error, info, warn};
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, SubscribeUpdateSlot, subscribe_update::UpdateOneof};
+use yellowstone_grpc_proto::prost::Message;
+
+use crate::baseline::BaselineDiff;
+use crate::compute_budget::ComputeBudget;
+use crate::discriminators;
+use crate::inner_instructions;
+use crate::logging::OutputFormat;
+use crate::recent_errors::ErrorClass;
+use crate::reorder::Reordered;
+use crate::session::Session;
+use crate::sinks::ReceivedAt;
+use crate::slot_history::{Anomaly, Fork};
+use crate::slot_status;
+use crate::stats;
+use crate::tx_error;
+use crate::votes::VoteSummary;
+use crate::warmup::{self, SnapshotMode, Warmup};
+
+/// Logged in place of a missing or malformed transaction signature.
+const INVALID_SIGNATURE: &str = "<invalid>";
+
+/// What to do when handling an update panics.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PanicPolicy {
+    /// Log the panic, skip the update and keep streaming
+    #[default]
+    Continue,
+    /// Let the panic take the process down
+    Abort,
+}
+
+/// What to do with a message whose update type the linked proto doesn't
+/// know.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownUpdatePolicy {
+    /// Drop the stream and resubscribe
+    #[default]
+    Reconnect,
+    /// Log it, skip the message and keep streaming
+    Skip,
+}
+
+/// The proto crate updates are decoded with, named in mismatch hints.
+const LINKED_PROTO: &str = "yellowstone-grpc-proto 8.0.0";
+
+/// Logs and counts a message that doesn't match the linked proto, which
+/// usually means the provider upgraded to a newer one.
+pub fn report_schema_error(session: &Session, problem: &str) {
+    session.schema_errors.fetch_add(1, Ordering::Relaxed);
+    error!(
+        "{}. This suggests a proto version mismatch: the provider may be running a newer Yellowstone proto than the linked {}",
+        problem, LINKED_PROTO
+    );
+}
+
+/// How the stream loop should proceed after an update was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    /// Handled; hand it to the sink
+    Processed,
+    /// Dropped by a client-side filter
+    Skip,
+    /// A server ping that needs a reply on its stream
+    Ping,
+    /// Every watched signature was seen
+    Stop,
+    /// The message carried no update
+    EndOfStream,
+}
+
+/// Runs a received update through the client-side filters, account
+/// coalescing, the raw output, the handler and the sink, in that order.
+pub fn process_update(
+    session: &Session,
+    update: &SubscribeUpdate,
+    received: ReceivedAt,
+    warmup: &mut Warmup,
+) -> Result<Flow> {
+    if let Some(shard) = session.shard
+        && stats::update_slot(update).is_some_and(|slot| !shard.owns(slot))
+    {
+        session.filtered.fetch_add(1, Ordering::Relaxed);
+        return Ok(Flow::Skip);
+    }
+
+    if let Some(filter) = &session.pubkey_filter
+        && !filter.allows(update)
+    {
+        session.filtered.fetch_add(1, Ordering::Relaxed);
+        return Ok(Flow::Skip);
+    }
+
+    if let Some(filter) = &session.program_filter
+        && let Some(matches) = filter.matches(update)
+    {
+        if !matches {
+            session.program_misses.fetch_add(1, Ordering::Relaxed);
+            session.filtered.fetch_add(1, Ordering::Relaxed);
+            return Ok(Flow::Skip);
+        }
+        session.program_matches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    if let Some(min) = session.min_lamports
+        && let Some(UpdateOneof::Account(account)) = &update.update_oneof
+        && let Some(info) = &account.account
+        // A closed account is worth seeing whatever its balance was
+        && info.lamports != 0
+        && info.lamports < min
+    {
+        session.filtered.fetch_add(1, Ordering::Relaxed);
+        return Ok(Flow::Skip);
+    }
+
+    // Observed either way, so the switch to live is logged
+    let snapshot = warmup.observe(update) | warmup::is_startup(update);
+    match session.snapshot_mode {
+        SnapshotMode::Exclude if snapshot => {
+            session.snapshot_skipped.fetch_add(1, Ordering::Relaxed);
+            return Ok(Flow::Skip);
+        }
+        SnapshotMode::Only if !snapshot && matches!(update.update_oneof, Some(UpdateOneof::Account(_))) => {
+            session.filtered.fetch_add(1, Ordering::Relaxed);
+            return Ok(Flow::Skip);
+        }
+        _ => {}
+    }
+
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &session.filter_script
+        && !script.allows(update)
+    {
+        session.filtered.fetch_add(1, Ordering::Relaxed);
+        return Ok(Flow::Skip);
+    }
+
+    if let Some(coalescer) = &session.coalescer {
+        let mut coalescer = coalescer.lock().unwrap();
+        let released = stats::update_slot(update).map(|slot| coalescer.release_before(slot));
+        let held = coalescer.hold(update, received);
+        drop(coalescer);
+        for (update, received) in released.into_iter().flatten() {
+            dispatch(session, &update, received)?;
+        }
+        if held {
+            // Handled once released, but it is activity all the same
+            return Ok(Flow::Processed);
+        }
+    }
+
+    if let Some(reorder) = &session.reorder {
+        let reordered = reorder.lock().unwrap().push(update, received);
+        match reordered {
+            Reordered::Held => return Ok(Flow::Processed),
+            Reordered::Release(released) => {
+                for (update, received) in released {
+                    dispatch(session, &update, received)?;
+                }
+            }
+        }
+    }
+
+    dispatch(session, update, received)
+}
+
+/// Hands the updates `--coalesce-accounts` or `--reorder` still hold to the
+/// handler and sinks, e.g. on shutdown.
+pub fn release_held(session: &Session) -> Result<()> {
+    let mut released = Vec::new();
+    if let Some(coalescer) = &session.coalescer {
+        released.extend(coalescer.lock().unwrap().release_all());
+    }
+    if let Some(reorder) = &session.reorder {
+        released.extend(reorder.lock().unwrap().release_all());
+    }
+    for (update, received) in released {
+        dispatch(session, &update, received)?;
+    }
+    Ok(())
+}
+
+/// The raw output, the handler, the account cache and the sinks.
+fn dispatch(session: &Session, update: &SubscribeUpdate, received: ReceivedAt) -> Result<Flow> {
+    let update = match &session.pipeline {
+        Some(pipeline) => match pipeline.lock().unwrap().before_decode(update) {
+            Some(update) => update,
+            None => {
+                session.filtered.fetch_add(1, Ordering::Relaxed);
+                return Ok(Flow::Skip);
+            }
+        },
+        None => Cow::Borrowed(update),
+    };
+    let update = update.as_ref();
+
+    if session.output_format == OutputFormat::Prototext {
+        // Debug output keeps every field exactly as received
+        writeln!(std::io::stdout().lock(), "{update:#?}")?;
+    }
+
+    let flow = handle_update_catching(session, update, session.panic_policy);
+    if matches!(flow, Flow::Processed | Flow::Ping) {
+        if let Some(account_state) = &session.account_state {
+            account_state.observe(update);
+        }
+        let sunk = match &session.pipeline {
+            Some(pipeline) => pipeline.lock().unwrap().before_sink(update),
+            None => Some(Cow::Borrowed(update)),
+        };
+        match sunk {
+            Some(update) => session.write_sink(&update, received),
+            None => {
+                session.filtered.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+    Ok(flow)
+}
+
+fn log_commitment_anomaly(slot_update: &SubscribeUpdateSlot, anomaly: Anomaly) {
+    let status = slot_status::slot_status_name(slot_update.status);
+    match anomaly {
+        Anomaly::Downgrade { seen } => warn!(
+            "Commitment downgrade: slot={} was already {}, now reported {}",
+            slot_update.slot,
+            slot_status::slot_status_name(seen as i32),
+            status
+        ),
+        Anomaly::DeadAfter { seen } => warn!(
+            "Commitment anomaly: slot={} was already {}, now reported dead",
+            slot_update.slot,
+            slot_status::slot_status_name(seen as i32)
+        ),
+        Anomaly::ForkBelowFinalized { parent, finalized } => warn!(
+            "Fork below finalization: slot={} (status={}) has parent {}, older than finalized slot {}",
+            slot_update.slot, status, parent, finalized
+        ),
+    }
+}
+
+fn log_fork(slot_update: &SubscribeUpdateSlot, fork: &Fork) {
+    let abandoned = fork.abandoned.iter().map(u64::to_string).collect::<Vec<_>>().join(", ");
+    match fork.abandoned_commitment {
+        Some(commitment) => warn!(
+            "Fork: slot={} builds on parent {}, replacing {} slot(s) [{}] that reached {}",
+            slot_update.slot,
+            fork.parent,
+            fork.abandoned.len(),
+            abandoned,
+            slot_status::slot_status_name(commitment as i32)
+        ),
+        None => info!(
+            "Fork: slot={} builds on parent {}, abandoning {} slot(s) [{}]",
+            slot_update.slot,
+            fork.parent,
+            fork.abandoned.len(),
+            abandoned
+        ),
+    }
+}
+
+/// Runs [`handle_update`], containing panics according to `policy`.
+///
+/// A contained panic is logged with the update that caused it and counted,
+/// and the update is skipped. The poison it left on the session's locks is
+/// cleared, so the next update is handled as usual; a tracker keeps whatever
+/// it held when the panic hit.
+pub fn handle_update_catching(session: &Session, update: &SubscribeUpdate, policy: PanicPolicy) -> Flow {
+    catch_panic(session, update, policy, || handle_update(session, update))
+}
+
+fn catch_panic(
+    session: &Session,
+    update: &SubscribeUpdate,
+    policy: PanicPolicy,
+    handle: impl FnOnce() -> Flow,
+) -> Flow {
+    match panic::catch_unwind(AssertUnwindSafe(handle)) {
+        Ok(flow) => flow,
+        Err(payload) if policy == PanicPolicy::Abort => panic::resume_unwind(payload),
+        Err(payload) => {
+            session.clear_poison();
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            error!(
+                "Handler panicked on {} update (filters {:?}, created_at {:?}): {}",
+                stats::update_kind(update),
+                update.filters,
+                update.created_at,
+                message
+            );
+            session.handler_panics.fetch_add(1, Ordering::Relaxed);
+            session.recent_errors.lock().unwrap().push(
+                ErrorClass::Handler,
+                None,
+                format!("panicked on {} update: {}", stats::update_kind(update), message),
+            );
+            Flow::Skip
+        }
+    }
+}
+
+/// Logs an update and feeds it to the session's trackers.
+pub fn handle_update(session: &Session, update: &SubscribeUpdate) -> Flow {
+    // Asked at the log line, so updates dropped by filters aren't counted
+    let log_each = || {
+        session.follow_rate.as_ref().is_none_or(|burst| {
+            burst
+                .lock()
+                .unwrap()
+                .admit(stats::update_kind(update), stats::update_slot(update))
+        })
+    };
+    session.tx_counts.lock().unwrap().flush_stale();
+    match &update.update_oneof {
+        Some(UpdateOneof::Slot(slot_update)) => {
+            session.tx_counts.lock().unwrap().observe_slot(slot_update.slot);
+            session.last_slot.fetch_max(slot_update.slot, Ordering::Relaxed);
+            session.checkpoint_slot(slot_update.slot);
+            if let Some(slot_rate) = &session.slot_rate {
+                slot_rate.lock().unwrap().observe(slot_update.slot);
+            }
+            let recorded = session.slot_history.lock().unwrap().record(slot_update);
+            if recorded.duplicate {
+                warn!(
+                    "Duplicate slot notification: slot={}, status={} was already received",
+                    slot_update.slot,
+                    slot_status::slot_status_name(slot_update.status)
+                );
+                session.duplicate_slot_updates.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(anomaly) = recorded.anomaly {
+                log_commitment_anomaly(slot_update, anomaly);
+                session.commitment_anomalies.fetch_add(1, Ordering::Relaxed);
+            }
+            if let Some(fork) = recorded.fork {
+                log_fork(slot_update, &fork);
+                if let Some(account_state) = &session.account_state {
+                    let rolled_back = account_state.rollback(&fork.abandoned);
+                    if rolled_back > 0 {
+                        info!("Rolled back {} cached account states from the abandoned slots", rolled_back);
+                    }
+                }
+                session.forks.fetch_add(1, Ordering::Relaxed);
+            }
+            if log_each() {
+                let line = format!(
+                    "Slot update: slot={}, parent={}, status={}",
+                    slot_update.slot,
+                    slot_update.parent.unwrap_or(0),
+                    slot_status::slot_status_name(slot_update.status)
+                );
+                match &session.active_slots {
+                    Some(active_slots) => active_slots.lock().unwrap().slot_update(slot_update.slot, line),
+                    None => info!("{}", line),
+                }
+            }
+            if let Some(dead_error) = &slot_update.dead_error {
+                warn!("Slot {} is dead: {}", slot_update.slot, dead_error);
+            }
+        }
+        Some(UpdateOneof::Account(account_update)) => {
+            let Some(account) = &account_update.account else {
+                report_schema_error(
+                    session,
+                    &format!("Account update at slot {} has no account info, skipping", account_update.slot),
+                );
+                return Flow::Skip;
+            };
+            if session.only_changed {
+                let changed = session
+                    .account_hashes
+                    .lock()
+                    .unwrap()
+                    .update(&account.pubkey, &account.data);
+                if !changed {
+                    session.unchanged_accounts.fetch_add(1, Ordering::Relaxed);
+                    return Flow::Skip;
+                }
+            }
+            if let Some(baseline) = &session.baseline {
+                let diff = baseline
+                    .lock()
+                    .unwrap()
+                    .diff(&account.pubkey, account.lamports, &account.data);
+                if diff == BaselineDiff::Unchanged {
+                    session.unchanged_accounts.fetch_add(1, Ordering::Relaxed);
+                    return Flow::Skip;
+                }
+                info!(
+                    "Account {} since baseline: pubkey={}",
+                    diff.as_str(),
+                    bs58::encode(&account.pubkey).into_string()
+                );
+            }
+            session.mark_active_slot(account_update.slot);
+            if log_each() {
+                let discriminator = if session.show_discriminator {
+                    discriminators::log_suffix(&account.data)
+                } else {
+                    String::new()
+                };
+                info!(
+                    "Account update: {}{}",
+                    session.account_fields.log_line(account_update, account),
+                    discriminator
+                );
+                #[cfg(feature = "anchor")]
+                if let Some(idl) = &session.idl {
+                    log_decoded_account(idl, &account.pubkey, &account.data);
+                }
+            }
+        }
+        Some(UpdateOneof::Transaction(tx_update)) => {
+            let signature = match tx_update.transaction.as_ref().map(|info| info.signature.as_slice()) {
+                Some(bytes) if bytes.len() == 64 => bs58::encode(bytes).into_string(),
+                Some(bytes) => {
+                    report_schema_error(
+                        session,
+                        &format!(
+                            "Transaction update at slot {} has a malformed {}-byte signature",
+                            tx_update.slot,
+                            bytes.len()
+                        ),
+                    );
+                    INVALID_SIGNATURE.to_string()
+                }
+                None => {
+                    report_schema_error(
+                        session,
+                        &format!("Transaction update at slot {} has no transaction info", tx_update.slot),
+                    );
+                    INVALID_SIGNATURE.to_string()
+                }
+            };
+            {
+                let mut watch = session.signatures.lock().unwrap();
+                if watch.is_active() && !watch.observe(&signature, tx_update.slot) {
+                    session.filtered.fetch_add(1, Ordering::Relaxed);
+                    return Flow::Skip;
+                }
+            }
+
+            session.tx_counts.lock().unwrap().record_transaction(tx_update.slot);
+            session.mark_active_slot(tx_update.slot);
+            let budget = tx_update
+                .transaction
+                .as_ref()
+                .and_then(|info| info.transaction.as_ref())
+                .and_then(|tx| tx.message.as_ref())
+                .map(ComputeBudget::from_message)
+                .unwrap_or_default();
+            let log_line = log_each();
+            let vote = tx_update
+                .transaction
+                .as_ref()
+                .filter(|info| log_line && session.decode_votes && info.is_vote)
+                .and_then(|info| info.transaction.as_ref())
+                .and_then(|tx| tx.message.as_ref())
+                .and_then(VoteSummary::from_message);
+            if let Some(vote) = vote {
+                info!(
+                    "Vote: slot={}, validator={}, vote_account={}, {}, signature={}",
+                    tx_update.slot,
+                    vote.validator,
+                    vote.vote_account.as_deref().unwrap_or("<unknown>"),
+                    vote.describe_slots(),
+                    signature
+                );
+            } else if log_line {
+                let error = tx_update
+                    .transaction
+                    .as_ref()
+                    .and_then(|info| info.meta.as_ref())
+                    .and_then(|meta| meta.err.as_ref())
+                    .map(|err| format!(", failed: {}", tx_error::describe(&err.err)))
+                    .unwrap_or_default();
+                info!(
+                    "Transaction update: slot={}, signature={}{}{}",
+                    tx_update.slot,
+                    signature,
+                    budget.log_suffix(),
+                    error
+                );
+                if let Some(max_depth) = session.inner_max_depth
+                    && let Some(info) = &tx_update.transaction
+                {
+                    for line in inner_instructions::describe(info, max_depth) {
+                        info!("{}", line);
+                    }
+                }
+            }
+
+            if session.stop_on_match && session.signatures.lock().unwrap().all_seen() {
+                info!("All watched transactions seen, stopping");
+                return Flow::Stop;
+            }
+        }
+        Some(UpdateOneof::Block(block_update)) => {
+            if log_each() {
+                info!(
+                    "Block update: slot={}, blockhash={}",
+                    block_update.slot,
+                    bs58::encode(&block_update.blockhash).into_string()
+                );
+            }
+        }
+        Some(UpdateOneof::Ping(_ping)) => {
+            if session.no_ping_reply {
+                info!("Received ping from server - not replying (--no-ping-reply)");
+                return Flow::Processed;
+            }
+            info!("Received ping from server - replying to keep connection alive");
+            return Flow::Ping;
+        }
+        Some(UpdateOneof::Pong(pong)) => {
+            info!("Received pong response with id: {}", pong.id);
+        }
+        None => {
+            // prost leaves the oneof empty for update types it doesn't know,
+            // and drops their fields, so only the rest of the message is left
+            report_unknown(session, update, "unknown");
+            report_schema_error(
+                session,
+                &format!(
+                    "Message (filters {:?}, {} bytes without the unknown update) carries no update type the client knows",
+                    update.filters,
+                    update.encoded_len()
+                ),
+            );
+            return match session.unknown_update_policy {
+                UnknownUpdatePolicy::Reconnect => Flow::EndOfStream,
+                UnknownUpdatePolicy::Skip => Flow::Skip,
+            };
+        }
+        _ => {
+            report_unknown(session, update, stats::update_kind(update));
+            warn!(
+                "Received {} update ({} bytes), which this client doesn't handle",
+                stats::update_kind(update),
+                update.encoded_len()
+            );
+        }
+    }
+    Flow::Processed
+}
+
+/// Counts an update the client doesn't handle and, with
+/// `--log-unknown-raw`, logs its encoding at debug level.
+fn report_unknown(session: &Session, update: &SubscribeUpdate, kind: &str) {
+    session.unknown_updates.fetch_add(1, Ordering::Relaxed);
+    if session.log_unknown_raw {
+        debug!("Raw {} update: {}", kind, discriminators::hex(&update.encode_to_vec()));
+    }
+}
+
+/// Logs account data decoded with the IDL, or as hex if no IDL account
+/// type matches its discriminator.
+#[cfg(feature = "anchor")]
+fn log_decoded_account(idl: &crate::anchor::AnchorIdl, pubkey: &[u8], data: &[u8]) {
+    let pubkey = bs58::encode(pubkey).into_string();
+    match idl.decode(data) {
+        Some(Ok((name, fields))) => info!("Account data: pubkey={}, type={}, fields={}", pubkey, name, fields),
+        Some(Err(e)) => warn!("Account data: pubkey={}: {:#}", pubkey, e),
+        None => info!("Account data: pubkey={}, raw={}", pubkey, crate::discriminators::hex(data)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yellowstone_grpc_proto::prelude::SubscribeUpdateSlot;
+
+    fn slot_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["slots".to_string()],
+            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot { slot, ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn panic_holding_locks_doesnt_break_the_next_update() {
+        let session = Session::default();
+        let flow = catch_panic(&session, &slot_update(1), PanicPolicy::Continue, || {
+            let _counts = session.tx_counts.lock().unwrap();
+            let _history = session.slot_history.lock().unwrap();
+            panic!("bug in the handler");
+        });
+        assert_eq!(flow, Flow::Skip);
+        assert_eq!(session.handler_panics.load(Ordering::Relaxed), 1);
+        assert!(!session.tx_counts.is_poisoned() && !session.slot_history.is_poisoned());
+
+        for slot in 2..=3 {
+            assert_eq!(handle_update_catching(&session, &slot_update(slot), PanicPolicy::Continue), Flow::Processed);
+        }
+        assert_eq!(session.handler_panics.load(Ordering::Relaxed), 1);
+        assert_eq!(session.last_slot.load(Ordering::Relaxed), 3);
+        assert_eq!(session.recent_errors.lock().unwrap().total(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "bug in the handler")]
+    fn abort_policy_lets_the_panic_through() {
+        let session = Session::default();
+        catch_panic(&session, &slot_update(1), PanicPolicy::Abort, || panic!("bug in the handler"));
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::{debug, info};
+
+use crate::config;
+use crate::metrics;
+use crate::session::Session;
+
+/// Request heads larger than this are refused.
+const MAX_HEAD_BYTES: usize = 8 * 1024;
+
+/// Request bodies larger than this are refused; only `PUT /loglevel` has one.
+const MAX_BODY_BYTES: usize = 8 * 1024;
+
+/// Connections that haven't sent a complete request by then are closed, so
+/// idle or slow clients can't hold tasks and sockets open.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A parsed HTTP/1.1 request head. Only what the routes need is kept.
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Everything after `?`, undecoded
+    pub query: String,
+    headers: Vec<(String, String)>,
+    /// As much as `Content-Length` announced, lossily decoded
+    pub body: String,
+}
+
+impl Request {
+    /// The value of header `name`, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// A complete response; every connection is closed after one.
+pub struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    pub fn text(status: u16, body: impl Into<String>) -> Self {
+        Self { status, content_type: "text/plain; charset=utf-8", body: body.into() }
+    }
+
+    fn json(status: u16, body: &serde_json::Value) -> Self {
+        Self { status, content_type: "application/json", body: format!("{body}\n") }
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            403 => "Forbidden",
+            426 => "Upgrade Required",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            500 => "Internal Server Error",
+            _ => "",
+        }
+    }
+}
+
+/// Binds the HTTP endpoint. Done before subscribing, so an address in use
+/// fails the run right away.
+pub async fn bind(addr: SocketAddr) -> Result<TcpListener> {
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("failed to bind HTTP endpoint {addr}"))?;
+    info!("Serving metrics on http://{}/metrics", addr);
+    Ok(listener)
+}
+
+/// Serves requests until the process exits.
+pub async fn serve(listener: TcpListener, session: Arc<Session>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                debug!("HTTP accept failed: {}", e);
+                continue;
+            }
+        };
+        let session = session.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, peer, &session).await {
+                debug!("HTTP request from {} failed: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, peer: SocketAddr, session: &Session) -> Result<()> {
+    let response = match read_request(&mut stream).await? {
+        Some(request) => route(&request, peer, session),
+        None => Response::text(400, "malformed request\n"),
+    };
+    write_response(&mut stream, &response).await
+}
+
+/// Writes `response` and closes the connection.
+pub async fn write_response(stream: &mut TcpStream, response: &Response) -> Result<()> {
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.reason(),
+        response.content_type,
+        response.body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(response.body.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Reads the request head, or returns `None` if it isn't valid HTTP. Fails
+/// if the request isn't complete within [`REQUEST_TIMEOUT`].
+pub async fn read_request(stream: &mut TcpStream) -> Result<Option<Request>> {
+    tokio::time::timeout(REQUEST_TIMEOUT, read_request_unbounded(stream))
+        .await
+        .with_context(|| format!("no complete request within {}s", REQUEST_TIMEOUT.as_secs()))?
+}
+
+async fn read_request_unbounded(stream: &mut TcpStream) -> Result<Option<Request>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0; 1024];
+    let head_len = loop {
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end;
+        }
+        if buf.len() > MAX_HEAD_BYTES {
+            return Ok(None);
+        }
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+    let Ok(head) = std::str::from_utf8(&buf[..head_len]) else {
+        return Ok(None);
+    };
+    let mut lines = head.lines();
+    let mut parts = lines.next().unwrap_or_default().split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return Ok(None);
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut request = Request {
+        method: method.to_string(),
+        path: path.to_string(),
+        query: query.to_string(),
+        headers,
+        body: String::new(),
+    };
+
+    let length = match request.header("content-length").map(str::parse::<usize>) {
+        None => 0,
+        Some(Ok(length)) if length <= MAX_BODY_BYTES => length,
+        Some(_) => return Ok(None),
+    };
+    // Whatever arrived with the head is the start of the body
+    let mut body = buf.split_off(head_len + 4);
+    while body.len() < length {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    body.truncate(length);
+    request.body = String::from_utf8_lossy(&body).into_owned();
+    Ok(Some(request))
+}
+
+fn route(request: &Request, peer: SocketAddr, session: &Session) -> Response {
+    match (request.method.as_str(), request.path.as_str()) {
+        ("GET", "/metrics") => Response {
+            status: 200,
+            content_type: "text/plain; version=0.0.4; charset=utf-8",
+            body: metrics::render(session),
+        },
+        (_, "/metrics") => Response::text(405, "only GET is supported\n"),
+        ("GET", "/errors") => {
+            let recent_errors = session.recent_errors.lock().unwrap();
+            let body = serde_json::json!({"total": recent_errors.total(), "errors": recent_errors.entries()});
+            Response::json(200, &body)
+        }
+        (_, "/errors") => Response::text(405, "only GET is supported\n"),
+        ("GET", path) if path.starts_with("/account/") => {
+            account(session, &path["/account/".len()..], &request.query)
+        }
+        (_, path) if path.starts_with("/account/") => Response::text(405, "only GET is supported\n"),
+        ("GET", "/loglevel") => match &session.log_filter {
+            Some(handle) => match handle.with_current(ToString::to_string) {
+                Ok(filter) => Response::text(200, format!("{filter}\n")),
+                Err(e) => Response::text(500, format!("{e}\n")),
+            },
+            None => Response::text(404, "the log filter can't be changed in this run\n"),
+        },
+        // Everything else is read-only; only a local operator may change logging
+        ("PUT", "/loglevel") if !peer.ip().to_canonical().is_loopback() => {
+            Response::text(403, "the log filter can only be changed from localhost\n")
+        }
+        ("PUT", "/loglevel") => set_log_filter(session, request.body.trim()),
+        (_, "/loglevel") => Response::text(405, "only GET and PUT are supported\n"),
+        _ => Response::text(404, "not found\n"),
+    }
+}
+
+/// Replaces the log filter with `filter`, e.g. `debug` or
+/// `info,svs_rust_grpc_example::handler=trace`.
+fn set_log_filter(session: &Session, filter: &str) -> Response {
+    let Some(handle) = &session.log_filter else {
+        return Response::text(404, "the log filter can't be changed in this run\n");
+    };
+    if filter.is_empty() {
+        return Response::text(400, "send the new filter as the body, e.g. `debug` or `info,h2=debug`\n");
+    }
+    let targets = match filter.parse::<tracing_subscriber::filter::Targets>() {
+        Ok(targets) => targets,
+        Err(e) => return Response::text(400, format!("invalid log filter `{filter}`: {e}\n")),
+    };
+    let previous = handle.with_current(ToString::to_string).unwrap_or_default();
+    // Logged before the switch, so it shows under the filter being replaced
+    info!("Changing the log filter from `{}` to `{}` (PUT /loglevel)", previous, targets);
+    match handle.reload(targets) {
+        Ok(()) => Response::text(200, format!("{filter}\n")),
+        Err(e) => Response::text(500, format!("{e}\n")),
+    }
+}
+
+/// The latest cached state of one account, with `--account-cache`.
+fn account(session: &Session, pubkey: &str, query: &str) -> Response {
+    let Some(account_state) = &session.account_state else {
+        return Response::text(404, "the account cache is disabled, set ACCOUNT_CACHE\n");
+    };
+    let key = match config::parse_pubkey(pubkey) {
+        Ok(key) => key,
+        Err(e) => return Response::text(400, format!("{e}\n")),
+    };
+    let level = match query.split('&').find_map(|pair| pair.strip_prefix("commitment=")) {
+        Some(name) => match config::Commitment::from_str(name, true) {
+            Ok(commitment) if account_state.tracks(commitment.level()) => Some(commitment.level()),
+            Ok(_) => {
+                let tracked = account_state.tracked_names();
+                let message = format!("not cached at {name}, only at {tracked}; see ACCOUNT_CACHE_COMMITMENTS\n");
+                return Response::text(400, message);
+            }
+            Err(_) => return Response::text(400, "commitment must be processed, confirmed or finalized\n"),
+        },
+        None => None,
+    };
+    match account_state.get(&key, level) {
+        Some(account) => Response::json(200, &account),
+        None => Response::text(404, "no update for this account, or it isn't subscribed\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_loglevel(filter: &str) -> Request {
+        Request {
+            method: "PUT".to_string(),
+            path: "/loglevel".to_string(),
+            query: String::new(),
+            headers: Vec::new(),
+            body: filter.to_string(),
+        }
+    }
+
+    #[test]
+    fn log_filter_changes_only_from_loopback() {
+        let session = Session::default();
+        let request = put_loglevel("debug");
+        for remote in ["10.0.0.7:41000", "[2001:db8::1]:41000"] {
+            let response = route(&request, remote.parse().unwrap(), &session);
+            assert_eq!(response.status, 403, "{remote}");
+        }
+        // Past the check; this session just has no filter to change
+        for local in ["127.0.0.1:41000", "[::1]:41000", "[::ffff:127.0.0.1]:41000"] {
+            let response = route(&request, local.parse().unwrap(), &session);
+            assert_eq!(response.status, 404, "{local}");
+        }
+    }
+
+    #[tokio::test]
+    async fn incomplete_requests_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (mut stream, _) = listener.accept().await.unwrap();
+        client.write_all(b"GET /metrics HTTP/1.1\r\n").await.unwrap();
+
+        tokio::time::pause();
+        let started = tokio::time::Instant::now();
+        let err = read_request(&mut stream).await.err().expect("the head never completes");
+        assert_eq!(err.to_string(), "no complete request within 10s");
+        assert!(started.elapsed() >= REQUEST_TIMEOUT);
+    }
+}
@@ -0,0 +1,121 @@
+use yellowstone_grpc_proto::prelude::{InnerInstruction, SubscribeUpdateTransactionInfo};
+
+/// Inner instruction lines logged per transaction at most; the rest are
+/// summarized in one line.
+const MAX_LINES: usize = 64;
+
+const SYSTEM_PROGRAM: &str = "11111111111111111111111111111111";
+const TOKEN_PROGRAM: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+const TOKEN_2022_PROGRAM: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+/// Names of well-known programs, for the log.
+const KNOWN_PROGRAMS: [(&str, &str); 9] = [
+    (SYSTEM_PROGRAM, "System"),
+    (TOKEN_PROGRAM, "Token"),
+    (TOKEN_2022_PROGRAM, "Token-2022"),
+    ("ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL", "Associated Token"),
+    ("ComputeBudget111111111111111111111111111111", "Compute Budget"),
+    ("Vote111111111111111111111111111111111111111", "Vote"),
+    ("Stake11111111111111111111111111111111111111", "Stake"),
+    ("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr", "Memo"),
+    ("BPFLoaderUpgradeab1e11111111111111111111111", "BPF Loader"),
+];
+
+/// One line per inner instruction (cross-program invocation) of `info`,
+/// indented by stack height, down to `max_depth`. Empty if the transaction
+/// made no CPIs or the provider didn't send them.
+pub fn describe(info: &SubscribeUpdateTransactionInfo, max_depth: u32) -> Vec<String> {
+    let Some(meta) = &info.meta else {
+        return Vec::new();
+    };
+    // Indexes cover the message's keys, then addresses loaded from lookup
+    // tables, writable before read-only
+    let keys: Vec<&[u8]> = info
+        .transaction
+        .as_ref()
+        .and_then(|tx| tx.message.as_ref())
+        .map(|message| message.account_keys.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .chain(&meta.loaded_writable_addresses)
+        .chain(&meta.loaded_readonly_addresses)
+        .map(Vec::as_slice)
+        .collect();
+
+    let mut lines = Vec::new();
+    let mut too_deep = 0;
+    let mut over_limit = 0;
+    for group in &meta.inner_instructions {
+        for instruction in &group.instructions {
+            // Top-level instructions are at height 1; unknown means an old
+            // validator, which only recorded a flat list
+            let depth = instruction.stack_height.unwrap_or(2);
+            if depth > max_depth {
+                too_deep += 1;
+            } else if lines.len() >= MAX_LINES {
+                over_limit += 1;
+            } else {
+                lines.push(describe_one(group.index, depth, instruction, &keys));
+            }
+        }
+    }
+    if too_deep > 0 {
+        lines.push(format!("  {too_deep} inner instructions below depth {max_depth} not shown"));
+    }
+    if over_limit > 0 {
+        lines.push(format!("  {over_limit} more inner instructions not shown"));
+    }
+    lines
+}
+
+fn describe_one(outer: u32, depth: u32, instruction: &InnerInstruction, keys: &[&[u8]]) -> String {
+    let indent = "  ".repeat(depth.saturating_sub(1) as usize);
+    let program = keys
+        .get(instruction.program_id_index as usize)
+        .map(|key| bs58::encode(key).into_string());
+    let Some(program) = program else {
+        return format!(
+            "{indent}inner [{outer}] depth={depth} program=<index {} out of range>",
+            instruction.program_id_index
+        );
+    };
+    let name = KNOWN_PROGRAMS
+        .iter()
+        .find(|(id, _)| *id == program)
+        .map(|(_, name)| format!(" ({name})"))
+        .unwrap_or_default();
+    let decoded = decode(&program, &instruction.data)
+        .map(|decoded| format!(": {decoded}"))
+        .unwrap_or_default();
+    format!("{indent}inner [{outer}] depth={depth} program={program}{name}{decoded}")
+}
+
+/// The most common instructions of the System and Token programs.
+fn decode(program: &str, data: &[u8]) -> Option<String> {
+    let amount = |bytes: &[u8]| bytes.get(..8)?.try_into().ok().map(u64::from_le_bytes);
+    match program {
+        SYSTEM_PROGRAM => {
+            let (tag, rest) = data.split_first_chunk::<4>()?;
+            match u32::from_le_bytes(*tag) {
+                0 => Some("create account".to_string()),
+                1 => Some("assign".to_string()),
+                2 => Some(format!("transfer {} lamports", amount(rest)?)),
+                8 => Some("allocate".to_string()),
+                _ => None,
+            }
+        }
+        TOKEN_PROGRAM | TOKEN_2022_PROGRAM => {
+            let (tag, rest) = data.split_first()?;
+            match tag {
+                1 | 16 | 18 => Some("initialize account".to_string()),
+                3 => Some(format!("transfer {}", amount(rest)?)),
+                7 => Some(format!("mint to {}", amount(rest)?)),
+                8 => Some(format!("burn {}", amount(rest)?)),
+                9 => Some("close account".to_string()),
+                12 => Some(format!("transfer checked {} (decimals {})", amount(rest)?, rest.get(8)?)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
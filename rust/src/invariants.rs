@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::slot_status;
+
+/// Account write versions are remembered for this many slots below the
+/// highest one seen.
+const ACCOUNT_SLOTS: u64 = 64;
+
+/// An ordering a well-behaved provider keeps and a stream broke.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// A slot status for a lower slot than one already reported with it
+    SlotBackwards { status: i32, slot: u64, previous: u64 },
+    /// An account update that doesn't raise the write version within a slot
+    WriteVersion { pubkey: String, slot: u64, write_version: u64, previous: u64 },
+    /// A transaction from a lower slot than the previous transaction
+    TransactionBackwards { slot: u64, previous: u64 },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::SlotBackwards { status, slot, previous } => write!(
+                f,
+                "slot {} reported {} after slot {} already was",
+                slot,
+                slot_status::slot_status_name(*status),
+                previous
+            ),
+            Violation::WriteVersion { pubkey, slot, write_version, previous } => write!(
+                f,
+                "account {} at slot {} has write_version {} after {}",
+                pubkey, slot, write_version, previous
+            ),
+            Violation::TransactionBackwards { slot, previous } => {
+                write!(f, "transaction at slot {} after one at slot {}", slot, previous)
+            }
+        }
+    }
+}
+
+/// Checks each stream's updates, in arrival order, against the orderings
+/// consumers tend to rely on: slots never go back within a status,
+/// account write versions rise within a (pubkey, slot), and transaction
+/// slots never go back. A new subscription starts a new checker, since a
+/// replay after reconnecting legitimately goes back.
+#[derive(Debug, Default)]
+pub struct Invariants {
+    /// Highest slot per slot status
+    slots: HashMap<i32, u64>,
+    /// Last write version per pubkey, by slot
+    accounts: BTreeMap<u64, HashMap<Vec<u8>, u64>>,
+    last_transaction_slot: Option<u64>,
+}
+
+impl Invariants {
+    pub fn check(&mut self, update: &SubscribeUpdate) -> Option<Violation> {
+        match update.update_oneof.as_ref()? {
+            UpdateOneof::Slot(slot) => {
+                let highest = self.slots.entry(slot.status).or_insert(slot.slot);
+                if slot.slot < *highest {
+                    let previous = *highest;
+                    return Some(Violation::SlotBackwards { status: slot.status, slot: slot.slot, previous });
+                }
+                *highest = slot.slot;
+                None
+            }
+            UpdateOneof::Account(account) => {
+                let info = account.account.as_ref()?;
+                let versions = self.accounts.entry(account.slot).or_default();
+                let violation = match versions.insert(info.pubkey.clone(), info.write_version) {
+                    Some(previous) if info.write_version <= previous => {
+                        versions.insert(info.pubkey.clone(), previous);
+                        Some(Violation::WriteVersion {
+                            pubkey: bs58::encode(&info.pubkey).into_string(),
+                            slot: account.slot,
+                            write_version: info.write_version,
+                            previous,
+                        })
+                    }
+                    _ => None,
+                };
+                let highest = self.accounts.keys().next_back().copied().unwrap_or_default();
+                let oldest = highest.saturating_sub(ACCOUNT_SLOTS);
+                while self.accounts.first_key_value().is_some_and(|(slot, _)| *slot < oldest) {
+                    self.accounts.pop_first();
+                }
+                violation
+            }
+            UpdateOneof::Transaction(tx) => {
+                let previous = self.last_transaction_slot;
+                self.last_transaction_slot = Some(previous.map_or(tx.slot, |previous| previous.max(tx.slot)));
+                match previous {
+                    Some(previous) if tx.slot < previous => {
+                        Some(Violation::TransactionBackwards { slot: tx.slot, previous })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Checks a recorded stream, such as a capture read back, from a fresh
+/// checker and returns every violation in order. The binary checks live
+/// streams update by update instead, so only tests call this.
+#[allow(dead_code)]
+pub fn check_invariants(updates: &[SubscribeUpdate]) -> Vec<Violation> {
+    let mut invariants = Invariants::default();
+    updates.iter().filter_map(|update| invariants.check(update)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yellowstone_grpc_proto::prelude::{
+        SlotStatus, SubscribeUpdateAccount, SubscribeUpdateAccountInfo, SubscribeUpdateSlot,
+        SubscribeUpdateTransaction,
+    };
+
+    fn update(update: UpdateOneof) -> SubscribeUpdate {
+        SubscribeUpdate { filters: vec!["fixture".to_string()], update_oneof: Some(update), ..Default::default() }
+    }
+
+    fn slot(slot: u64, status: SlotStatus) -> SubscribeUpdate {
+        update(UpdateOneof::Slot(SubscribeUpdateSlot { slot, status: status as i32, ..Default::default() }))
+    }
+
+    fn account(pubkey: u8, slot: u64, write_version: u64) -> SubscribeUpdate {
+        update(UpdateOneof::Account(SubscribeUpdateAccount {
+            slot,
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: vec![pubkey; 32],
+                write_version,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+    }
+
+    fn transaction(slot: u64) -> SubscribeUpdate {
+        update(UpdateOneof::Transaction(SubscribeUpdateTransaction { slot, ..Default::default() }))
+    }
+
+    /// A well-behaved capture: statuses interleave, and each of slot, write
+    /// version and transaction slot only goes up where it must.
+    fn clean_capture() -> Vec<SubscribeUpdate> {
+        vec![
+            slot(100, SlotStatus::SlotProcessed),
+            account(1, 100, 5),
+            account(2, 100, 3),
+            transaction(100),
+            account(1, 100, 6),
+            slot(101, SlotStatus::SlotProcessed),
+            // Confirmed trails processed, which is no regression
+            slot(100, SlotStatus::SlotConfirmed),
+            // A new slot starts write versions afresh
+            account(1, 101, 1),
+            transaction(101),
+            transaction(101),
+            slot(101, SlotStatus::SlotConfirmed),
+        ]
+    }
+
+    #[test]
+    fn clean_capture_has_no_violations() {
+        assert_eq!(check_invariants(&clean_capture()), Vec::new());
+    }
+
+    #[test]
+    fn slot_going_back_within_a_status() {
+        let mut capture = clean_capture();
+        capture.push(slot(99, SlotStatus::SlotProcessed));
+        assert_eq!(check_invariants(&capture), vec![Violation::SlotBackwards {
+            status: SlotStatus::SlotProcessed as i32,
+            slot: 99,
+            previous: 101,
+        }]);
+    }
+
+    #[test]
+    fn write_version_not_rising_within_a_slot() {
+        let mut capture = clean_capture();
+        capture.push(account(1, 100, 6));
+        capture.push(account(1, 100, 4));
+        let pubkey = bs58::encode([1; 32]).into_string();
+        assert_eq!(check_invariants(&capture), vec![
+            Violation::WriteVersion { pubkey: pubkey.clone(), slot: 100, write_version: 6, previous: 6 },
+            // The highest version is kept, not the offending one
+            Violation::WriteVersion { pubkey, slot: 100, write_version: 4, previous: 6 },
+        ]);
+    }
+
+    #[test]
+    fn transaction_from_an_earlier_slot() {
+        let mut capture = clean_capture();
+        capture.push(transaction(100));
+        capture.push(transaction(101));
+        assert_eq!(check_invariants(&capture), vec![Violation::TransactionBackwards { slot: 100, previous: 101 }]);
+    }
+}
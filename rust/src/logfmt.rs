@@ -0,0 +1,137 @@
+use std::fmt;
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::time::{FormatTime, SystemTime};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+
+/// Formats events as logfmt: `time=... level=info target=... msg="..."`,
+/// followed by the fields of the event and its spans.
+pub struct Logfmt;
+
+impl<S, N> FormatEvent<S, N> for Logfmt
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let mut time = String::new();
+        SystemTime.format_time(&mut Writer::new(&mut time))?;
+        write!(writer, "time={} level={} target=", time, event.metadata().level().as_str().to_lowercase())?;
+        write_value(&mut writer, event.metadata().target())?;
+
+        let mut visitor = Visitor { writer: writer.by_ref(), separate: true, result: Ok(()) };
+        event.record(&mut visitor);
+        visitor.result?;
+
+        // Span fields were already formatted by `LogfmtFields`
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                let extensions = span.extensions();
+                if let Some(fields) = extensions.get::<FormattedFields<N>>()
+                    && !fields.is_empty()
+                {
+                    write!(writer, " {}", fields.fields)?;
+                }
+            }
+        }
+        writeln!(writer)
+    }
+}
+
+/// Formats span fields as logfmt pairs, for [`Logfmt`] to append.
+pub struct LogfmtFields;
+
+impl<'writer> FormatFields<'writer> for LogfmtFields {
+    fn format_fields<R: RecordFields>(&self, writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = Visitor { writer, separate: false, result: Ok(()) };
+        fields.record(&mut visitor);
+        visitor.result
+    }
+}
+
+struct Visitor<'a> {
+    writer: Writer<'a>,
+    /// Whether to write a space before the next pair
+    separate: bool,
+    result: fmt::Result,
+}
+
+impl Visitor<'_> {
+    fn pair(&mut self, field: &Field, value: &str) {
+        if self.result.is_err() {
+            return;
+        }
+        let key = match field.name() {
+            "message" => "msg",
+            name => name,
+        };
+        let separator = if self.separate { " " } else { "" };
+        self.separate = true;
+        self.result = write!(self.writer, "{separator}{key}=").and_then(|()| write_value(&mut self.writer, value));
+    }
+}
+
+impl Visit for Visitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.pair(field, value);
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.pair(field, &format!("{value:?}"));
+    }
+}
+
+/// Writes `value` bare if it is a single token, otherwise quoted with `"`
+/// and `\` escaped.
+fn write_value(writer: &mut Writer<'_>, value: &str) -> fmt::Result {
+    let bare = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && c != '"' && c != '=' && c != '\\');
+    if bare {
+        return writer.write_str(value);
+    }
+    writer.write_char('"')?;
+    for c in value.chars() {
+        match c {
+            '"' => writer.write_str("\\\"")?,
+            '\\' => writer.write_str("\\\\")?,
+            '\n' => writer.write_str("\\n")?,
+            '\r' => writer.write_str("\\r")?,
+            '\t' => writer.write_str("\\t")?,
+            c => writer.write_char(c)?,
+        }
+    }
+    writer.write_char('"')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formatted(value: &str) -> String {
+        let mut out = String::new();
+        write_value(&mut Writer::new(&mut out), value).unwrap();
+        out
+    }
+
+    #[test]
+    fn single_tokens_stay_bare() {
+        assert_eq!(formatted("info"), "info");
+        assert_eq!(formatted("276389641"), "276389641");
+        assert_eq!(formatted("https://grpc.example.com:443/"), "https://grpc.example.com:443/");
+    }
+
+    #[test]
+    fn other_values_are_quoted_and_escaped() {
+        assert_eq!(formatted("Stream ended, reconnecting"), r#""Stream ended, reconnecting""#);
+        assert_eq!(formatted(r#"message: "error reading a body""#), r#""message: \"error reading a body\"""#);
+        assert_eq!(formatted(r"C:\captures"), r#""C:\\captures""#);
+        assert_eq!(formatted("first\nsecond\r\tthird"), r#""first\nsecond\r\tthird""#);
+        assert_eq!(formatted("key=value"), r#""key=value""#);
+        assert_eq!(formatted(""), r#""""#);
+    }
+}
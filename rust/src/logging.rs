@@ -0,0 +1,79 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::io::{self, Write};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Registry, reload};
+
+use crate::logfmt::{Logfmt, LogfmtFields};
+
+/// Where log lines are written.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    Stdout,
+    #[default]
+    Stderr,
+}
+
+/// How log lines are formatted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+    /// `key=value` pairs, see [`crate::logfmt`]
+    Logfmt,
+}
+
+/// How updates are written to stdout, separately from logs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Updates only appear as log lines
+    #[default]
+    Log,
+    /// Every update is printed in full using the prost debug representation
+    Prototext,
+}
+
+/// Swaps the log filter of the running process, for `PUT /loglevel`.
+pub type LogFilterHandle = reload::Handle<Targets, Registry>;
+
+/// Installs the global tracing subscriber, logging at info level and above
+/// until the filter is changed through the returned handle.
+///
+/// Logs default to stderr so stdout stays free for data when piping.
+pub fn init(target: LogTarget, format: LogFormat) -> LogFilterHandle {
+    let (filter, handle) = reload::Layer::new(Targets::new().with_default(LevelFilter::INFO));
+    let registry = tracing_subscriber::registry().with(filter);
+    let layer = tracing_subscriber::fmt::layer();
+    match (target, format) {
+        (LogTarget::Stdout, LogFormat::Text) => registry.with(layer.with_writer(io::stdout)).init(),
+        (LogTarget::Stderr, LogFormat::Text) => registry.with(layer.with_writer(io::stderr)).init(),
+        (LogTarget::Stdout, LogFormat::Json) => registry.with(layer.json().with_writer(io::stdout)).init(),
+        (LogTarget::Stderr, LogFormat::Json) => registry.with(layer.json().with_writer(io::stderr)).init(),
+        (LogTarget::Stdout, LogFormat::Logfmt) => registry.with(logfmt(layer).with_writer(io::stdout)).init(),
+        (LogTarget::Stderr, LogFormat::Logfmt) => registry.with(logfmt(layer).with_writer(io::stderr)).init(),
+    }
+    handle
+}
+
+fn logfmt<S>(layer: tracing_subscriber::fmt::Layer<S>) -> tracing_subscriber::fmt::Layer<S, LogfmtFields, Logfmt>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+{
+    layer.fmt_fields(LogfmtFields).event_format(Logfmt)
+}
+
+/// Writes a raw line to the log target, for output that is already
+/// structured (such as the JSON run summary).
+pub fn write_line(target: LogTarget, line: &str) -> io::Result<()> {
+    match target {
+        LogTarget::Stdout => writeln!(io::stdout().lock(), "{line}"),
+        LogTarget::Stderr => writeln!(io::stderr().lock(), "{line}"),
+    }
+}
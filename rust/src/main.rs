@@ -1,135 +1,1017 @@
-use anyhow::Result;
-use std::env;
+mod account_fields;
+mod account_hashes;
+mod account_state;
+mod active_slots;
+mod backfill;
+mod baseline;
+#[cfg(feature = "anchor")]
+mod anchor;
+mod bench;
+mod checkpoint;
+mod catch_up;
+mod burst;
+mod client;
+mod compute_budget;
+mod config;
+mod coalesce;
+mod config_file;
+mod connection_state;
+mod discriminators;
+mod doctor;
+mod explain;
+mod fixtures;
+mod handler;
+mod http;
+mod inner_instructions;
+mod invariants;
+mod logfmt;
+mod logging;
+mod manifest;
+mod metrics;
+#[cfg(test)]
+mod mock_server;
+mod pipeline;
+mod provider;
+mod pubkey_filter;
+mod recent_errors;
+mod reorder;
+mod request;
+mod request_json;
+mod rpc;
+#[cfg(feature = "scripting")]
+mod script;
+mod session;
+mod shard;
+mod signatures;
+mod sinks;
+mod slot_history;
+mod slot_range;
+mod slot_rate;
+mod slot_status;
+#[cfg(feature = "statsd")]
+mod statsd;
+mod throttle;
+mod tokens;
+mod stats;
+mod tx_counts;
+mod tx_error;
+mod votes;
+mod warmup;
+
+use anyhow::{Context, Result, bail};
+use config::{Command, Config};
+use session::Session;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use tokio_stream::StreamExt;
-use tracing::{info, error, warn};
+use tracing::{info, error, warn, Instrument};
 use yellowstone_grpc_client::GeyserGrpcClient;
 use yellowstone_grpc_proto::prelude::*;
-use tonic::transport::ClientTlsConfig;
-use futures::sink::SinkExt;
-use futures::TryFutureExt;
-use backoff::{future::retry, ExponentialBackoff};
+use backoff::{backoff::Backoff, ExponentialBackoff};
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     // Load environment variables from .env file
     dotenv::dotenv().ok();
     
+    // Configuration - you'll need to set these based on your provider
+    let config = Config::load()?;
+
     // Initialize logging
-    tracing_subscriber::fmt::init();
+    let log_filter = logging::init(config.log_target, config.log_format);
 
-    // Configuration - you'll need to set these based on your provider
-    let endpoint = env::var("GEYSER_ENDPOINT")
-        .unwrap_or_else(|_| "https://your-provider-endpoint".to_string());
-    
-    let x_token = env::var("GEYSER_ACCESS_TOKEN").ok();
-
-    // Retry with exponential backoff - this is the official Triton pattern
-    retry(ExponentialBackoff::default(), move || {
-        let endpoint = endpoint.clone();
-        let x_token = x_token.clone();
-        
-        async move {
-            info!("Connecting to gRPC endpoint: {}", endpoint);
-            
-            // Create client following the official example pattern
-            let client = GeyserGrpcClient::build_from_shared(endpoint)
-                .map_err(|e| backoff::Error::transient(anyhow::Error::from(e)))?
-                .x_token(x_token)
-                .map_err(|e| backoff::Error::transient(anyhow::Error::from(e)))?
-                .tls_config(ClientTlsConfig::new().with_native_roots())
-                .map_err(|e| backoff::Error::transient(anyhow::Error::from(e)))?
-                .connect()
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(threads) = config.worker_threads {
+        runtime.worker_threads(threads.get());
+    }
+    let runtime = runtime.enable_all().build().context("failed to start the tokio runtime")?;
+    info!("Running on {} worker threads", runtime.metrics().num_workers());
+    runtime.block_on(run(config, log_filter))
+}
+
+async fn run(mut config: Config, log_filter: logging::LogFilterHandle) -> Result<()> {
+    if let Some(path) = &config.config {
+        info!("Loaded configuration from {}", path.display());
+    }
+    if config.output_format != logging::OutputFormat::Log && config.log_target == logging::LogTarget::Stdout {
+        warn!("Updates and logs are both written to stdout and will interleave; consider LOG_TARGET=stderr");
+    }
+
+    let endpoint = config.endpoint.clone();
+    let x_tokens = config.x_tokens();
+    let token_pool = (x_tokens.len() > 1).then(|| tokens::TokenPool::new(x_tokens.clone()));
+    let x_token = match &token_pool {
+        Some(pool) => {
+            info!("Rotating between access tokens {} for {}", pool.describe(), endpoint);
+            pool.current()
+        }
+        None => {
+            let x_token = match x_tokens.first() {
+                Some(token) => Some(token.clone()),
+                None => token_for_endpoint(&config, &endpoint)?,
+            };
+            match &x_token {
+                Some(token) => info!("Using access token {} for {}", config::redact(token), endpoint),
+                None => info!("No access token configured for {}", endpoint),
+            }
+            x_token
+        }
+    };
+
+    let headers = config
+        .headers
+        .iter()
+        .map(|raw| client::Header::parse(raw))
+        .collect::<Result<Vec<_>>>()?;
+    client::log_headers(&headers);
+
+    let hmac = config
+        .hmac_secret
+        .as_deref()
+        .filter(|secret| !secret.is_empty())
+        .map(client::HmacSigner::new);
+    if hmac.is_some() {
+        info!("Signing requests with HMAC-SHA256 (HMAC_SECRET)");
+    }
+
+    let preset = config.provider.preset();
+    info!("Using {:?} provider preset: {:?}", config.provider, preset);
+    if config.no_ping_reply {
+        match preset.http2_keep_alive_interval {
+            Some(interval) => info!("Not replying to server pings, relying on HTTP/2 keepalive every {:?}", interval),
+            None => warn!(
+                "Not replying to server pings and the {:?} preset has no HTTP/2 keepalive: providers that expect ping replies may close the stream",
+                config.provider
+            ),
+        }
+    }
+
+    let connect_options = client::ConnectOptions {
+        endpoint: endpoint.clone(),
+        x_token,
+        headers,
+        hmac: hmac.clone(),
+        preset,
+        tls_domain: config.tls_domain.clone(),
+        tls13_only: config.tls13_only,
+        ip_version: config.ip_version,
+        request_id: config
+            .request_id
+            .clone()
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        user_agent: config.user_agent.clone(),
+    };
+    info!("Using user agent {} (tonic appends its own version)", connect_options.user_agent);
+    if connect_options.endpoint.starts_with("https://") {
+        match client::effective_tls_domain(&connect_options) {
+            Some(domain) => info!("Using TLS server name {}", domain),
+            None => warn!("Could not determine the TLS server name for {}", connect_options.endpoint),
+        }
+    }
+
+    if let Some(Command::Bench(args)) = &config.command {
+        return bench::run(&connect_options, args).await;
+    }
+    if let Some(Command::Fixtures(args)) = &config.command {
+        return fixtures::run(&connect_options, args).await;
+    }
+    if let Some(Command::Doctor) = &config.command {
+        return doctor::run(&config, &connect_options).await;
+    }
+
+    if let Some(path) = &config.checkpoint_file
+        && config.from_slot.is_none()
+        && let Some(slot) = checkpoint::Checkpoint::load(path)?
+    {
+        info!("Resuming from slot {} saved in {}", slot, path.display());
+        config.from_slot = Some(slot);
+    }
+
+    // Build the subscription once so configuration errors fail fast
+    let requests = request::build_subscribe_requests(&config)?;
+    if let Some(path) = &config.dump_request {
+        request_json::dump(path, &requests)?;
+        info!("Wrote the subscription request to {}", path.display());
+    }
+    if config.list_filters {
+        print!("{}", explain::describe(&requests));
+        return Ok(());
+    }
+    let catch_up = catch_up_range(&config)?;
+    if let Some(shard) = config.shard {
+        info!("Processing shard {} (slots where slot % {} == {})", shard, shard.total, shard.index);
+    }
+
+    if config.reorder && config.coalesce_accounts {
+        bail!("--reorder and --coalesce-accounts both hold updates until a slot is over and can't be combined");
+    }
+
+    // Shared across reconnects so counts survive a dropped stream
+    let session = Arc::new(Session {
+        #[cfg(feature = "scripting")]
+        filter_script: load_filter_script(&config)?,
+        #[cfg(feature = "anchor")]
+        idl: load_idl(&config)?,
+        stats_interval: std::time::Duration::from_secs(config.stats_interval_secs),
+        output_format: config.output_format,
+        filter_counts: std::sync::Mutex::new(stats::FilterCounter::new(&requests)),
+        signatures: std::sync::Mutex::new(signatures::SignatureWatch::new(&config.tx_signatures)?),
+        stop_on_match: config.stop_on_match,
+        only_changed: config.only_changed,
+        coalescer: config.coalesce_accounts.then(Default::default),
+        reorder: config
+            .reorder
+            .then(|| std::sync::Mutex::new(reorder::ReorderBuffer::new(config.reorder_window_slots))),
+        tokens: token_pool.map(std::sync::Mutex::new),
+        pipeline: match config.pipeline.is_empty() {
+            true => None,
+            false => Some(std::sync::Mutex::new(pipeline::Pipeline::parse(&config.pipeline)?)),
+        },
+        min_lamports: config.min_lamports,
+        account_fields: account_fields::AccountFields::new(&config.account_fields),
+        pubkey_filter: pubkey_filter::PubkeyFilter::new(&config.include_pubkeys, &config.exclude_pubkeys),
+        program_filter: pubkey_filter::ProgramFilter::new(&config.programs),
+        shard: config.shard,
+        show_discriminator: config.show_discriminator,
+        inner_max_depth: config.show_inner.then_some(config.inner_max_depth),
+        decode_votes: config.decode_votes,
+        checkpoint: config
+            .checkpoint_file
+            .as_deref()
+            .map(|path| std::sync::Mutex::new(checkpoint::Checkpoint::new(path, config.checkpoint_every_slots))),
+        baseline: config
+            .baseline
+            .as_deref()
+            .map(baseline::Baseline::load)
+            .transpose()?
+            .map(std::sync::Mutex::new),
+        snapshot_mode: snapshot_mode(&config)?,
+        panic_policy: config.on_handler_panic,
+        no_ping_reply: config.no_ping_reply,
+        unknown_update_policy: config.on_unknown_update,
+        log_unknown_raw: config.log_unknown_raw,
+        check_invariants: config.check_invariants,
+        max_bytes_per_sec: config.max_bytes_per_sec,
+        hmac,
+        max_lifetime: config.subscription_max_lifetime_secs.map(std::time::Duration::from_secs),
+        warm_standby: config.warm_standby,
+        first_message_timeout: (config.first_message_timeout_secs > 0)
+            .then(|| std::time::Duration::from_secs(config.first_message_timeout_secs)),
+        idle_unsubscribe: config.idle_unsubscribe_secs.map(std::time::Duration::from_secs),
+        idle_resubscribe: std::time::Duration::from_secs(config.idle_resubscribe_secs),
+        slot_range: (config.from_slot.is_some() || config.to_slot.is_some())
+            .then(|| std::sync::Mutex::new(slot_range::SlotRange::new(config.from_slot, config.to_slot))),
+        slot_rate: config.slot_rate.then(|| {
+            std::sync::Mutex::new(slot_rate::SlotRateEstimator::new(
+                std::time::Duration::from_secs(config.slot_rate_window_secs),
+                config.slot_rate_min,
+                std::time::Duration::from_secs(config.slot_stall_secs),
+            ))
+        }),
+        active_slots: config.active_slots_only.then(|| {
+            std::sync::Mutex::new(active_slots::ActiveSlots::new(std::time::Duration::from_millis(
+                config.active_slots_window_ms,
+            )))
+        }),
+        rpc_backfill: backfill::RpcBackfill::new(&config)?,
+        follow_rate: config.follow_rate.then(|| {
+            std::sync::Mutex::new(burst::BurstLogger::new(
+                std::time::Duration::from_millis(config.follow_rate_window_ms),
+                config.follow_rate_threshold,
+            ))
+        }),
+        recent_errors: std::sync::Mutex::new(recent_errors::RecentErrors::new(config.error_history)),
+        sinks: sinks::SinkSet::start(open_sinks(&config)?, config.strict_order)?,
+        account_state: load_account_state(&config)?,
+        log_filter: Some(log_filter),
+        ..Default::default()
+    });
+
+    if let Some(addr) = config.http_addr {
+        let listener = http::bind(addr).await?;
+        tokio::spawn(http::serve(listener, session.clone()));
+    }
+    if session.slot_rate.is_some() {
+        tokio::spawn(slot_rate::watch(session.clone()));
+    }
+
+    #[cfg(feature = "statsd")]
+    if let Some(addr) = &config.statsd_addr {
+        let interval = std::time::Duration::from_secs(config.statsd_interval_secs);
+        let exporter = statsd::StatsdExporter::connect(addr, config.statsd_format, interval).await?;
+        tokio::spawn(exporter.run(session.clone()));
+    }
+
+    let mut manifest = open_manifest(&config, &connect_options.request_id)?;
+
+    let retry = RetryLimits {
+        max_attempts: config.max_reconnect_attempts,
+        min_delay: std::time::Duration::from_millis(config.min_reconnect_delay_ms),
+        max_retry_after: std::time::Duration::from_secs(config.max_retry_after_secs),
+        max_per_minute: config.max_reconnects_per_minute,
+    };
+    // Every log line of the subscription carries the request id
+    let span = tracing::info_span!("subscription", request_id = %connect_options.request_id);
+    let subscriber = async {
+        match catch_up {
+            Some(range) => {
+                catch_up::run(
+                    &connect_options,
+                    &requests,
+                    &session,
+                    range,
+                    config.catch_up_connections,
+                    config.catch_up_ordered,
+                    config.catch_up_share_connection,
+                )
                 .await
-                .map_err(|e| backoff::Error::transient(anyhow::Error::from(e)))?;
-            
+            }
+            None => run_with_reconnect(&connect_options, &requests, &session, retry).await,
+        }
+    }
+    .instrument(span);
+
+    let result = tokio::select! {
+        result = subscriber => result,
+        _ = tokio::signal::ctrl_c() => {
+            info!("Received Ctrl-C, shutting down");
+            Ok(())
+        }
+    };
+
+    if let Err(e) = handler::release_held(&session) {
+        error!("{:#}", e);
+    }
+    session.flush_sink();
+    session.save_baseline();
+    session.flush_checkpoint();
+
+    // Report on every exit path, including when retries are exhausted
+    let stats = session.run_stats();
+    match config.log_format {
+        logging::LogFormat::Json => logging::write_line(config.log_target, &serde_json::to_string(&stats)?)?,
+        logging::LogFormat::Text | logging::LogFormat::Logfmt => stats.log(),
+    }
+    if let Some(manifest) = &mut manifest
+        && let Err(e) = manifest.finish(stats)
+    {
+        error!("{:#}", e);
+    }
+    result
+}
+
+/// The slot range to split across connections, if parallel catch-up is on.
+fn catch_up_range(config: &Config) -> Result<Option<(u64, u64)>> {
+    if config.catch_up_connections <= 1 {
+        return Ok(None);
+    }
+    match (config.from_slot, config.to_slot) {
+        (Some(from), Some(to)) => Ok(Some((from, to))),
+        _ => anyhow::bail!("--catch-up-connections needs both --from-slot and --to-slot"),
+    }
+}
+
+/// Files the run writes data to, for the manifest.
+fn output_paths(config: &Config) -> Vec<std::path::PathBuf> {
+    let mut outputs = Vec::new();
+    outputs.extend(
+        config
+            .jsonl
+            .as_deref()
+            .filter(|path| path.as_os_str() != "-")
+            .map(|path| sinks::jsonl::output_path(path, config.compress_output)),
+    );
+    #[cfg(feature = "sqlite")]
+    outputs.extend(config.sqlite.clone());
+    outputs
+}
+
+fn open_manifest(config: &Config, request_id: &str) -> Result<Option<manifest::Manifest>> {
+    let outputs = output_paths(config);
+    let path = config.manifest.clone().or_else(|| {
+        outputs.first().map(|output| {
+            let mut path = output.clone().into_os_string();
+            path.push(".manifest.json");
+            path.into()
+        })
+    });
+    let Some(path) = path else {
+        return Ok(None);
+    };
+    let manifest = manifest::Manifest::new(path, config, request_id, outputs)?;
+    manifest.write()?;
+    info!("Wrote run manifest to {}", manifest.path().display());
+    Ok(Some(manifest))
+}
+
+/// Opens every configured sink; all of them receive every update.
+fn open_sinks(config: &Config) -> Result<Vec<Box<dyn sinks::Sink>>> {
+    let mut sinks: Vec<Box<dyn sinks::Sink>> = Vec::new();
+    if let Some(path) = &config.jsonl {
+        let account_fields = account_fields::AccountFields::new(&config.account_fields);
+        let jsonl = sinks::jsonl::JsonlSink::open(path, account_fields, config.checksum, config.compress_output)?;
+        sinks.push(Box::new(jsonl));
+    }
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &config.sqlite {
+        sinks.push(Box::new(sinks::sqlite::SqliteSink::open(path)?));
+    }
+    #[cfg(feature = "ws")]
+    if let Some(addr) = config.ws_addr {
+        sinks.push(Box::new(sinks::websocket::WebSocketSink::open(addr)?));
+    }
+    Ok(sinks)
+}
+
+/// `--snapshot-mode`, with `--skip-snapshot` as its older spelling.
+fn snapshot_mode(config: &Config) -> Result<warmup::SnapshotMode> {
+    match (config.skip_snapshot, config.snapshot_mode) {
+        (false, mode) => Ok(mode),
+        (true, warmup::SnapshotMode::Only) => bail!("--skip-snapshot contradicts --snapshot-mode only"),
+        (true, _) => Ok(warmup::SnapshotMode::Exclude),
+    }
+}
+
+/// Builds the `--account-cache` for the accounts of `--accounts-file`.
+fn load_account_state(config: &Config) -> Result<Option<account_state::AccountState>> {
+    if !config.account_cache {
+        return Ok(None);
+    }
+    let Some(path) = &config.accounts_file else {
+        warn!("--account-cache has no effect without --accounts-file");
+        return Ok(None);
+    };
+    if config.http_addr.is_none() {
+        warn!("--account-cache is only served with --http-addr");
+    }
+    let pubkeys = config::load_accounts_file(path)?;
+    let default = config.accounts_commitment.unwrap_or(config.commitment).level();
+    let extra: Vec<_> = config.account_cache_commitments.iter().map(|commitment| commitment.level()).collect();
+    let account_state = account_state::AccountState::new(&pubkeys, default, &extra)?;
+    info!(
+        "Caching the latest state of {} accounts at {} for /account/<pubkey>",
+        pubkeys.len(),
+        account_state.tracked_names()
+    );
+    Ok(Some(account_state))
+}
+
+#[cfg(feature = "scripting")]
+fn load_filter_script(config: &Config) -> Result<Option<script::FilterScript>> {
+    let Some(path) = &config.filter_script else {
+        return Ok(None);
+    };
+    let budget = std::time::Duration::from_millis(config.filter_script_budget_ms);
+    let script = script::FilterScript::load(path, budget)?;
+    info!("Loaded filter script {} ({:?} budget per update)", path.display(), budget);
+    Ok(Some(script))
+}
+
+#[cfg(feature = "anchor")]
+fn load_idl(config: &Config) -> Result<Option<anchor::AnchorIdl>> {
+    let Some(path) = &config.idl else {
+        return Ok(None);
+    };
+    let idl = anchor::AnchorIdl::load(path)?;
+    info!("Loaded {} account types from IDL {}", idl.account_count(), path.display());
+    Ok(Some(idl))
+}
+
+/// Bounds on the reconnect loop.
+struct RetryLimits {
+    /// Consecutive failed attempts before giving up
+    max_attempts: Option<u32>,
+    /// Lower bound for every delay the backoff produces
+    min_delay: std::time::Duration,
+    /// Longest `retry-after` hint from the provider to wait for
+    max_retry_after: std::time::Duration,
+    /// `MAX_RECONNECTS_PER_MINUTE`, across every kind of reconnect
+    max_per_minute: Option<u32>,
+}
+
+/// Runs the subscription, reconnecting with exponential backoff like the
+/// official Triton example, until it stops cleanly or retries run out.
+///
+/// The backoff and attempt count are reset whenever a stream delivered at
+/// least one message, so the limits apply to consecutive failures rather
+/// than to the lifetime of the process. Whichever of `max_attempts` and the
+/// backoff's elapsed-time limit is hit first ends the loop. Delays never drop
+/// below `min_delay`, even while the backoff is still small.
+async fn run_with_reconnect(
+    connect_options: &client::ConnectOptions,
+    requests: &[SubscribeRequest],
+    session: &Session,
+    retry: RetryLimits,
+) -> Result<()> {
+    let mut backoff = ExponentialBackoff::default();
+    let mut attempts = 0u32;
+    let mut limiter = retry.max_per_minute.map(throttle::ReconnectLimiter::new);
+
+    loop {
+        // Recycles and server-side closes skip the backoff, but not this
+        if let Some(limiter) = &mut limiter {
+            limiter.acquire().await;
+        }
+        let received_before = session.messages.lock().unwrap().total();
+        let result = async {
+            info!(
+                "Connecting to gRPC endpoint: {} (request-id {})",
+                connect_options.endpoint, connect_options.request_id
+            );
+
+            session.connection.lock().unwrap().connecting();
+            let mut connect_options = connect_options.clone();
+            if let Some(tokens) = &session.tokens {
+                connect_options.x_token = tokens.lock().unwrap().current();
+            }
+
+            // Create client following the official example pattern
+            let client = client::connect(&connect_options).await?;
+
             info!("Successfully connected to Yellowstone gRPC");
-            
+            session.connections.fetch_add(1, Ordering::Relaxed);
+            session.connection.lock().unwrap().connected();
+
+            // Resume a bounded replay where the last stream left off
+            let mut requests = requests.to_vec();
+            if let Some(range) = &session.slot_range {
+                let resume = range.lock().unwrap().resume_slot();
+                for request in requests.iter_mut().filter(|request| request.from_slot.is_some()) {
+                    request.from_slot = resume;
+                }
+            }
+
             // Run the subscription logic
-            run_subscription(client).await.map_err(|e| backoff::Error::transient(e))?;
-            
-            Ok::<(), backoff::Error<anyhow::Error>>(())
+            run_subscription(client, requests, session, &connect_options).await
+        }
+        .await;
+
+        let error = match result {
+            Ok(SubscriptionEnd::Done) => return Ok(()),
+            Ok(SubscriptionEnd::Idle) => {
+                session.connection.lock().unwrap().idle();
+                wait_to_resubscribe(session).await;
+                backoff.reset();
+                attempts = 0;
+                continue;
+            }
+            Ok(SubscriptionEnd::Recycled) => {
+                // Planned, so neither a failure nor a reason to back off
+                info!("Reconnecting to {} for a fresh subscription", connect_options.endpoint);
+                session.recycles.fetch_add(1, Ordering::Relaxed);
+                backoff.reset();
+                attempts = 0;
+                continue;
+            }
+            Ok(SubscriptionEnd::ServerClosed) if session.messages.lock().unwrap().total() > received_before => {
+                // A deploy or rebalance on the provider's side, not a failure
+                info!("Reconnecting to {} after the server closed the stream", connect_options.endpoint);
+                backoff.reset();
+                attempts = 0;
+                continue;
+            }
+            // Closing before any message is more likely a rejected subscription
+            Ok(SubscriptionEnd::ServerClosed) => anyhow::anyhow!("the server closed the stream without sending any messages"),
+            Err(error) => error,
+        };
+
+        let class = match session.connection.lock().unwrap().state {
+            connection_state::ConnectionState::Connected => recent_errors::ErrorClass::Stream,
+            _ => recent_errors::ErrorClass::Connect,
+        };
+        session.recent_errors.lock().unwrap().record(class, &error);
+
+        let status = error.chain().find_map(|cause| cause.downcast_ref::<tonic::Status>());
+        if let Some(tokens) = &session.tokens
+            && let Some(status) = status
+        {
+            tokens.lock().unwrap().on_error(status.code());
+        }
+
+        if session.messages.lock().unwrap().total() > received_before {
+            backoff.reset();
+            attempts = 0;
         }
-        .inspect_err(|error| error!("Connection failed, will retry: {error}"))
-    })
-    .await
-    .map_err(Into::into)
+        attempts += 1;
+        session.connection.lock().unwrap().failed(attempts);
+
+        if let Some(max) = retry.max_attempts
+            && attempts >= max
+        {
+            return Err(error.context(format!(
+                "giving up after {attempts} consecutive failed connection attempts (MAX_RECONNECT_ATTEMPTS={max})"
+            )));
+        }
+        let Some(backoff_delay) = backoff.next_backoff() else {
+            return Err(error.context(format!(
+                "giving up after {attempts} consecutive failed connection attempts: no successful stream within the {:?} retry window",
+                backoff.max_elapsed_time.unwrap_or_default()
+            )));
+        };
+
+        let mut delay = backoff_delay.max(retry.min_delay);
+        if let Some(hint) = status.and_then(retry_after_hint) {
+            let honored = hint.min(retry.max_retry_after);
+            if honored > delay {
+                info!(
+                    "Provider asked to retry after {:?}, waiting {:?} instead of the backoff's {:?}",
+                    hint, honored, delay
+                );
+                delay = honored;
+            }
+        }
+        error!("Connection failed (attempt {attempts}), will retry in {delay:?}: {error:#}");
+        tokio::time::sleep(delay).await;
+    }
 }
 
-async fn run_subscription(mut client: GeyserGrpcClient<impl tonic::service::Interceptor>) -> Result<()> {
-    
-    // Use the new subscribe_with_request method like the official example
-    let (mut subscribe_tx, mut stream) = client.subscribe_with_request(Some(SubscribeRequest {
-        slots: std::collections::HashMap::from([
-            ("client".to_string(), SubscribeRequestFilterSlots {
-                filter_by_commitment: Some(true),
-                interslot_updates: Some(false),
+/// Picks the token for `endpoint`: an entry in the endpoint token map wins,
+/// otherwise the shared `GEYSER_ACCESS_TOKEN` is used.
+fn token_for_endpoint(config: &Config, endpoint: &str) -> Result<Option<String>> {
+    if let Some(path) = &config.endpoint_tokens {
+        let tokens = config::load_endpoint_tokens(path)?;
+        if let Some(token) = tokens.get(endpoint).filter(|token| !token.is_empty()) {
+            return Ok(Some(token.clone()));
+        }
+        warn!("No entry for {} in {}, falling back to GEYSER_ACCESS_TOKEN", endpoint, path.display());
+    }
+    Ok(config.x_token())
+}
+
+/// Why a subscription ended without an error.
+enum SubscriptionEnd {
+    /// Stopped on purpose, e.g. past `--to-slot`
+    Done,
+    /// Closed after `IDLE_UNSUBSCRIBE_SECS` without matching updates
+    Idle,
+    /// Ended by the server on purpose: the stream finished cleanly, or the
+    /// connection was shut down after an HTTP/2 GOAWAY
+    ServerClosed,
+    /// Closed after `SUBSCRIPTION_MAX_LIFETIME_SECS` for a fresh connection
+    Recycled,
+}
+
+/// The `retry-after` metadata of a RESOURCE_EXHAUSTED status, in seconds.
+fn retry_after_hint(status: &tonic::Status) -> Option<std::time::Duration> {
+    if status.code() != tonic::Code::ResourceExhausted {
+        return None;
+    }
+    let seconds: f64 = status.metadata().get("retry-after")?.to_str().ok()?.trim().parse().ok()?;
+    std::time::Duration::try_from_secs_f64(seconds).ok()
+}
+
+/// Whether `status` comes from the server shutting the connection down
+/// gracefully, as providers do when rolling out a deploy, rather than from
+/// a failure. h2 only reports the GOAWAY (`NO_ERROR`) itself to streams the
+/// server never started; a subscription already streaming sees the orderly
+/// TCP close that follows once the server stops draining.
+fn is_graceful_goaway(status: &tonic::Status) -> bool {
+    let mut source = std::error::Error::source(status);
+    while let Some(error) = source {
+        if let Some(h2) = error.downcast_ref::<h2::Error>() {
+            if h2.is_go_away() {
+                return h2.is_remote() && h2.reason() == Some(h2::Reason::NO_ERROR);
+            }
+            return h2.get_io().is_some_and(|io| io.kind() == std::io::ErrorKind::BrokenPipe);
+        }
+        source = error.source();
+    }
+    false
+}
+
+/// Account, transaction, block and entry updates, as opposed to slot
+/// updates and pings that arrive regardless of the filters' matches.
+fn is_data_update(update: &SubscribeUpdate) -> bool {
+    use subscribe_update::UpdateOneof;
+    !matches!(
+        update.update_oneof,
+        None | Some(UpdateOneof::Slot(_) | UpdateOneof::Ping(_) | UpdateOneof::Pong(_))
+    )
+}
+
+/// Waits until an idle subscription should be reopened: after
+/// `IDLE_RESUBSCRIBE_SECS`, or earlier on SIGUSR1.
+async fn wait_to_resubscribe(session: &Session) {
+    let timer = tokio::time::sleep(session.idle_resubscribe);
+    #[cfg(unix)]
+    if let Ok(mut signal) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::user_defined1()) {
+        tokio::select! {
+            _ = timer => info!("Resubscribing after {:?} idle", session.idle_resubscribe),
+            _ = signal.recv() => info!("Received SIGUSR1, resubscribing"),
+        }
+        return;
+    }
+    timer.await;
+    info!("Resubscribing after {:?} idle", session.idle_resubscribe);
+}
+
+/// How long a warm standby and the subscription it replaces both stream,
+/// so nothing sent around the switch is missed.
+const STANDBY_OVERLAP: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// Updates remembered to drop the copies both sides of the overlap deliver.
+const STANDBY_DEDUP_WINDOW: usize = 100_000;
+
+/// A request sink per stream, for answering pings.
+type RequestSink = std::pin::Pin<
+    Box<dyn futures::Sink<SubscribeRequest, Error = futures::channel::mpsc::SendError> + Send>,
+>;
+
+/// Stream items, tagged with the index of the stream they came from.
+type TaggedStream = futures::stream::BoxStream<'static, (usize, Result<SubscribeUpdate, tonic::Status>)>;
+
+/// The streams of one connection.
+struct Streams {
+    subscribe_txs: Vec<RequestSink>,
+    streams: Vec<TaggedStream>,
+    /// Close the streams, e.g. once a standby has taken over
+    abort_handles: Vec<futures::stream::AbortHandle>,
+}
+
+/// Connects and subscribes a warm standby.
+type StandbyConnect<'a> =
+    futures::future::BoxFuture<'a, Result<(GeyserGrpcClient<client::MetadataInterceptor>, Streams)>>;
+
+/// Opens one stream per request on `client`, numbered from `first_index`.
+async fn subscribe_all(
+    client: &mut GeyserGrpcClient<client::MetadataInterceptor>,
+    requests: Vec<SubscribeRequest>,
+    first_index: usize,
+) -> Result<Streams> {
+    let mut opened = Streams {
+        subscribe_txs: Vec::with_capacity(requests.len()),
+        streams: Vec::with_capacity(requests.len()),
+        abort_handles: Vec::with_capacity(requests.len()),
+    };
+    for (index, request) in (first_index..).zip(requests) {
+        let (subscribe_tx, stream) = client.subscribe_with_request(Some(request)).await?;
+        let (stream, abort_handle) = futures::stream::abortable(stream.map(move |message| (index, message)));
+        opened.subscribe_txs.push(Box::pin(subscribe_tx));
+        opened.streams.push(Box::pin(stream));
+        opened.abort_handles.push(abort_handle);
+    }
+    Ok(opened)
+}
+
+async fn run_subscription(
+    mut client: GeyserGrpcClient<client::MetadataInterceptor>,
+    requests: Vec<SubscribeRequest>,
+    session: &Session,
+    connect_options: &client::ConnectOptions,
+) -> Result<SubscriptionEnd> {
+    // Where this stream should pick up, so a gap can be filled from RPC
+    let mut resume_from = session.rpc_backfill.as_ref().and_then(|_| {
+        requests
+            .iter()
+            .find_map(|request| request.from_slot)
+            .or_else(|| match session.last_slot.load(Ordering::Relaxed) {
+                0 => None,
+                slot => Some(slot + 1),
             })
-        ]),
-        commitment: Some(CommitmentLevel::Confirmed as i32),
-        ..Default::default()
-    })).await?;
+    });
+    // A standby joins a live stream, it doesn't replay one
+    let standby_requests: Vec<SubscribeRequest> = match session.warm_standby {
+        true => requests.iter().map(|request| SubscribeRequest { from_slot: None, ..request.clone() }).collect(),
+        false => Vec::new(),
+    };
+
+    // Use the new subscribe_with_request method like the official example.
+    // Each commitment level gets its own stream on the same connection.
+    let opened = subscribe_all(&mut client, requests, 0).await?;
+    let mut next_index = opened.streams.len();
+    let mut subscribe_txs: HashMap<usize, RequestSink> = (0..).zip(opened.subscribe_txs).collect();
+    let mut abort_handles = opened.abort_handles;
+    let mut stream = futures::stream::select_all(opened.streams);
     
-    info!("Subscribed to slot updates, waiting for messages...");
+    info!("Subscribed, waiting for messages...");
+
+    // A fresh subscription may start with a new snapshot
+    let mut warmup = warmup::Warmup::default();
     
+    // Kept so callers can react to the status, e.g. by rotating tokens
+    let mut stream_error = None;
+
+    let mut subscribed_at = tokio::time::Instant::now();
+    let mut last_data = subscribed_at;
+    let mut got_message = false;
+    let mut throttle = session.max_bytes_per_sec.map(throttle::Throttle::new);
+    let mut invariants = session.check_invariants.then(invariants::Invariants::default);
+
+    // A warm standby is opening while `standby` is set, and streaming
+    // alongside the streams it replaces until `overlap_until`
+    let mut standby: Option<StandbyConnect<'_>> = None;
+    let mut overlap_until = None;
+    let mut boundary: Option<pipeline::Dedup> = None;
+    let mut boundary_duplicates = 0u64;
+    // The replaced streams, closed when the overlap ends
+    let mut retiring: (Vec<usize>, Vec<futures::stream::AbortHandle>) = Default::default();
+
     // Process incoming messages - this follows the official Triton example exactly
-    while let Some(message) = stream.next().await {
-        match message {
-            Ok(msg) => {
-                match msg.update_oneof {
-                    Some(subscribe_update::UpdateOneof::Slot(slot_update)) => {
-                        info!(
-                            "Slot update: slot={}, parent={}, status={:?}",
-                            slot_update.slot,
-                            slot_update.parent.unwrap_or(0),
-                            slot_update.status()
-                        );
-                    }
-                    Some(subscribe_update::UpdateOneof::Account(account_update)) => {
-                        info!(
-                            "Account update: pubkey={}, slot={}, lamports={}",
-                            bs58::encode(&account_update.account.as_ref().unwrap().pubkey).into_string(),
-                            account_update.slot,
-                            account_update.account.as_ref().unwrap().lamports
-                        );
-                    }
-                    Some(subscribe_update::UpdateOneof::Transaction(tx_update)) => {
-                        info!(
-                            "Transaction update: slot={}, signature={}",
-                            tx_update.slot,
-                            bs58::encode(&tx_update.transaction.as_ref().unwrap().signature).into_string()
+    loop {
+        // A handshake that succeeded without the server ever streaming
+        // looks like a quiet filter; even those get pings
+        let first_message_deadline = session
+            .first_message_timeout
+            .filter(|_| !got_message)
+            .map(|limit| subscribed_at + limit);
+        let idle_deadline = session.idle_unsubscribe.map(|limit| last_data + limit);
+        let lifetime_deadline = session
+            .max_lifetime
+            .filter(|_| standby.is_none() && overlap_until.is_none())
+            .map(|limit| subscribed_at + limit);
+        let deadline =
+            [first_message_deadline, idle_deadline, lifetime_deadline, overlap_until].into_iter().flatten().min();
+        let next_or_deadline = async {
+            match deadline {
+                Some(deadline) => tokio::time::timeout_at(deadline, stream.next()).await,
+                None => Ok(stream.next().await),
+            }
+        };
+        let standby_ready = async {
+            match standby.as_mut() {
+                Some(standby) => standby.await,
+                None => std::future::pending().await,
+            }
+        };
+        let next = tokio::select! {
+            ready = standby_ready => {
+                standby = None;
+                match ready {
+                    Ok((standby_client, opened)) => {
+                        info!("Warm standby subscribed, overlapping both for {:?}", STANDBY_OVERLAP);
+                        session.connections.fetch_add(1, Ordering::Relaxed);
+                        // The old client goes, its streams stay open until aborted
+                        drop(std::mem::replace(&mut client, standby_client));
+                        retiring = (
+                            subscribe_txs.keys().copied().collect(),
+                            std::mem::replace(&mut abort_handles, opened.abort_handles),
                         );
+                        subscribe_txs.extend((next_index..).zip(opened.subscribe_txs));
+                        next_index += opened.streams.len();
+                        for opened_stream in opened.streams {
+                            stream.push(opened_stream);
+                        }
+                        subscribed_at = tokio::time::Instant::now();
+                        overlap_until = Some(subscribed_at + STANDBY_OVERLAP);
+                        continue;
                     }
-                    Some(subscribe_update::UpdateOneof::Block(block_update)) => {
-                        info!(
-                            "Block update: slot={}, blockhash={}",
-                            block_update.slot,
-                            bs58::encode(&block_update.blockhash).into_string()
-                        );
+                    Err(e) => {
+                        warn!("Warm standby failed, recycling without one: {:#}", e);
+                        return Ok(SubscriptionEnd::Recycled);
                     }
-                    Some(subscribe_update::UpdateOneof::Ping(_ping)) => {
-                        info!("Received ping from server - replying to keep connection alive");
-                        // Reply to ping directly here like the official example
-                        subscribe_tx
-                            .send(SubscribeRequest {
-                                ping: Some(SubscribeRequestPing { id: 1 }),
-                                ..Default::default()
-                            })
-                            .await?;
+                }
+            }
+            next = next_or_deadline => next,
+        };
+        let next = match next {
+            Ok(next) => next,
+            Err(_) if deadline == first_message_deadline => {
+                let limit = session.first_message_timeout.unwrap_or_default();
+                warn!("No message or ping within {:?} of subscribing, the subscription may not be established", limit);
+                bail!("no message within {limit:?} of subscribing (FIRST_MESSAGE_TIMEOUT_SECS)");
+            }
+            Err(_) if deadline == lifetime_deadline && session.warm_standby => {
+                info!(
+                    "Opening a warm standby after {:?} (SUBSCRIPTION_MAX_LIFETIME_SECS)",
+                    session.max_lifetime.unwrap_or_default()
+                );
+                let requests = standby_requests.clone();
+                let first_index = next_index;
+                standby = Some(Box::pin(async move {
+                    let mut client = client::connect(connect_options).await?;
+                    let opened = subscribe_all(&mut client, requests, first_index).await?;
+                    Ok((client, opened))
+                }));
+                boundary = Some(pipeline::Dedup::new(STANDBY_DEDUP_WINDOW));
+                continue;
+            }
+            Err(_) if deadline == lifetime_deadline => {
+                info!(
+                    "Recycling the subscription after {:?} (SUBSCRIPTION_MAX_LIFETIME_SECS)",
+                    session.max_lifetime.unwrap_or_default()
+                );
+                return Ok(SubscriptionEnd::Recycled);
+            }
+            Err(_) if deadline == overlap_until => {
+                let (indexes, handles) = std::mem::take(&mut retiring);
+                for index in indexes {
+                    subscribe_txs.remove(&index);
+                }
+                for handle in handles {
+                    handle.abort();
+                }
+                overlap_until = None;
+                boundary = None;
+                info!(
+                    "Switched to the warm standby, dropped {} updates both subscriptions delivered",
+                    boundary_duplicates
+                );
+                boundary_duplicates = 0;
+                session.recycles.fetch_add(1, Ordering::Relaxed);
+                continue;
+            }
+            Err(_) => {
+                let limit = session.idle_unsubscribe.unwrap_or_default();
+                info!("No matching updates for {:?}, unsubscribing", limit);
+                return Ok(SubscriptionEnd::Idle);
+            }
+        };
+        let Some((stream_index, message)) = next else {
+            info!("The server ended the stream");
+            return Ok(SubscriptionEnd::ServerClosed);
+        };
+        match message {
+            Ok(msg) => {
+                // Each new stream recalibrates the clock signatures use
+                if !got_message
+                    && let (Some(signer), Some(created_at)) = (&session.hmac, &msg.created_at)
+                {
+                    signer.observe_server_time(created_at);
+                }
+                got_message = true;
+                let received = sinks::ReceivedAt::now();
+                session.record_received(&msg);
+                // Both subscriptions send the same updates around a switch
+                if let Some(boundary) = &mut boundary
+                    && !matches!(
+                        msg.update_oneof,
+                        Some(subscribe_update::UpdateOneof::Ping(_) | subscribe_update::UpdateOneof::Pong(_))
+                    )
+                    && matches!(pipeline::Stage::apply(boundary, &msg), pipeline::StageResult::Drop)
+                {
+                    boundary_duplicates += 1;
+                    continue;
+                }
+                if let Some(violation) = invariants.as_mut().and_then(|invariants| invariants.check(&msg)) {
+                    warn!("Ordering invariant violated: {}", violation);
+                    session.invariant_violations.fetch_add(1, Ordering::Relaxed);
+                }
+                if let Some(throttle) = &mut throttle {
+                    throttle.pace(yellowstone_grpc_proto::prost::Message::encoded_len(&msg)).await;
+                }
+
+                if let Some(range) = &session.slot_range
+                    && let Some(slot) = stats::update_slot(&msg)
+                {
+                    let is_slot_update = matches!(msg.update_oneof, Some(subscribe_update::UpdateOneof::Slot(_)));
+                    let check = range.lock().unwrap().check(slot, is_slot_update);
+                    match check {
+                        slot_range::RangeCheck::Inside => {}
+                        slot_range::RangeCheck::Outside => {
+                            session.filtered.fetch_add(1, Ordering::Relaxed);
+                            continue;
+                        }
+                        slot_range::RangeCheck::Done => {
+                            info!("Slot {} is past --to-slot, stopping", slot);
+                            range.lock().unwrap().log_processed();
+                            return Ok(SubscriptionEnd::Done);
+                        }
                     }
-                    Some(subscribe_update::UpdateOneof::Pong(pong)) => {
-                        info!("Received pong response with id: {}", pong.id);
+                }
+
+                if let Some(expected) = resume_from
+                    && let Some(subscribe_update::UpdateOneof::Slot(slot_update)) = &msg.update_oneof
+                {
+                    resume_from = None;
+                    if slot_update.slot > expected
+                        && let Some(backfill) = &session.rpc_backfill
+                    {
+                        match backfill.fill(session, expected..slot_update.slot, &mut warmup).await? {
+                            Some(handler::Flow::Stop) => return Ok(SubscriptionEnd::Done),
+                            Some(_) => break,
+                            None => {}
+                        }
                     }
-                    None => {
-                        error!("update not found in the message");
-                        break;
+                }
+
+                match handler::process_update(session, &msg, received, &mut warmup)? {
+                    handler::Flow::Processed => {
+                        if is_data_update(&msg) {
+                            last_data = tokio::time::Instant::now();
+                        }
                     }
-                    _ => {
-                        warn!("Received unknown update type");
+                    handler::Flow::Skip => {}
+                    handler::Flow::Ping => {
+                        // Reply to ping directly here like the official example
+                        if let Some(subscribe_tx) = subscribe_txs.get_mut(&stream_index) {
+                            client::reply_to_ping(subscribe_tx).await?;
+                        }
                     }
+                    handler::Flow::Stop => return Ok(SubscriptionEnd::Done),
+                    handler::Flow::EndOfStream => break,
                 }
             }
+            Err(e) if is_graceful_goaway(&e) => {
+                info!("The server is shutting down the connection (GOAWAY), reconnecting to a fresh backend");
+                return Ok(SubscriptionEnd::ServerClosed);
+            }
             Err(e) => {
                 error!("Stream error: {}", e);
+                // tonic ends the stream on a message prost can't decode
+                if e.code() == tonic::Code::Internal && e.message().contains("decode") {
+                    handler::report_schema_error(session, "A message from the provider could not be decoded, reconnecting");
+                }
+                stream_error = Some(e);
                 break;
             }
         }
@@ -137,5 +1019,268 @@ async fn run_subscription(mut client: GeyserGrpcClient<impl tonic::service::Inte
     
     warn!("Stream closed, will reconnect...");
     // Always return an error to trigger reconnection
-    Err(anyhow::anyhow!("Stream ended, triggering reconnection"))
-}
\ No newline at end of file
+    match stream_error {
+        Some(status) => Err(anyhow::Error::new(status).context("Stream ended, triggering reconnection")),
+        None => Err(anyhow::anyhow!("Stream ended, triggering reconnection")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn slot_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["slots".to_string()],
+            update_oneof: Some(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn slots_request() -> SubscribeRequest {
+        SubscribeRequest {
+            slots: HashMap::from([("slots".to_string(), SubscribeRequestFilterSlots::default())]),
+            ..Default::default()
+        }
+    }
+
+    fn retry_limits() -> RetryLimits {
+        RetryLimits {
+            max_attempts: Some(1),
+            min_delay: Duration::ZERO,
+            max_retry_after: Duration::ZERO,
+            max_per_minute: None,
+        }
+    }
+
+    async fn wait_for(mut condition: impl FnMut() -> bool) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !condition() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("timed out waiting for the condition");
+    }
+
+    /// Keeps what reached the sink, in the order it arrived.
+    struct RecordingSink(std::sync::Arc<std::sync::Mutex<Vec<SubscribeUpdate>>>);
+
+    impl sinks::Sink for RecordingSink {
+        fn name(&self) -> &str {
+            "recording"
+        }
+
+        fn prepare(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn write(&mut self, update: &SubscribeUpdate, _: sinks::ReceivedAt) -> Result<()> {
+            self.0.lock().unwrap().push(update.clone());
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn account(pubkey: u8, slot: u64, write_version: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: vec!["accounts".to_string()],
+            update_oneof: Some(subscribe_update::UpdateOneof::Account(SubscribeUpdateAccount {
+                slot,
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: vec![pubkey; 32],
+                    owner: vec![0; 32],
+                    write_version,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_reach_the_sink_in_stream_order() {
+        // Accounts interleaved across slots, as a busy stream delivers them
+        let mut recorded = Vec::new();
+        for slot in 10..20 {
+            recorded.push(slot_update(slot));
+            for write_version in 0..5 {
+                for pubkey in [3, 1, 2] {
+                    recorded.push(account(pubkey, slot, slot * 100 + write_version));
+                }
+            }
+        }
+        let server = mock_server::MockServer::start(recorded.clone()).await;
+        let options = server.connect_options();
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let session = Session {
+            sinks: sinks::SinkSet::start(vec![Box::new(RecordingSink(received.clone()))], true).unwrap(),
+            ..Default::default()
+        };
+        let client = client::connect(&options).await.unwrap();
+        tokio::select! {
+            end = run_subscription(client, vec![slots_request()], &session, &options) => {
+                panic!("the subscription ended: {:?}", end.err())
+            }
+            () = wait_for(|| session.messages.lock().unwrap().total() == recorded.len() as u64) => {}
+        }
+        session.sinks.finish();
+        assert_eq!(*received.lock().unwrap(), recorded);
+    }
+
+    fn replay_request(from_slot: u64) -> SubscribeRequest {
+        SubscribeRequest { from_slot: Some(from_slot), ..slots_request() }
+    }
+
+    fn recorded_slots(updates: &[SubscribeUpdate]) -> Vec<u64> {
+        updates.iter().filter_map(stats::update_slot).collect()
+    }
+
+    #[tokio::test]
+    async fn clean_reconnect_resumes_from_the_last_slot() {
+        let checkpoint = tempfile::NamedTempFile::new().unwrap();
+        let first: Vec<_> = (100..=103).map(slot_update).collect();
+        let mut server = mock_server::MockServer::start(first).await;
+        let options = server.connect_options();
+        let session = Session {
+            slot_range: Some(std::sync::Mutex::new(slot_range::SlotRange::new(Some(100), None))),
+            checkpoint: Some(std::sync::Mutex::new(checkpoint::Checkpoint::new(checkpoint.path(), 1))),
+            ..Default::default()
+        };
+        let requests = [replay_request(100)];
+        let reconnect = run_with_reconnect(&options, &requests, &session, retry_limits());
+        let deploy = async {
+            wait_for(|| session.messages.lock().unwrap().total() == 4).await;
+            // The replay starts at the resume slot, so 103 comes again
+            server.restart(vec![slot_update(103), slot_update(104)], Duration::from_millis(200)).await;
+            wait_for(|| session.messages.lock().unwrap().total() == 6).await;
+        };
+        tokio::select! {
+            result = reconnect => panic!("the reconnect loop ended: {:?}", result),
+            () = deploy => {}
+        }
+        let from_slots: Vec<_> = server.subscribe_requests().iter().map(|request| request.from_slot).collect();
+        assert_eq!(from_slots, [Some(100), Some(103)]);
+        session.flush_checkpoint();
+        assert_eq!(checkpoint::Checkpoint::load(checkpoint.path()).unwrap(), Some(104));
+        // Counters carry on across the reconnect rather than starting over
+        assert_eq!(session.messages.lock().unwrap().total(), 6);
+        assert_eq!(session.connections.load(Ordering::Relaxed), 2);
+        assert_eq!(session.recent_errors.lock().unwrap().total(), 0);
+    }
+
+    #[tokio::test]
+    async fn error_reconnect_resumes_and_dedups_the_replay() {
+        let first = vec![slot_update(100), account(1, 100, 1), slot_update(101), account(1, 101, 2)];
+        let status = tonic::Status::unavailable("backend restarting");
+        let mut server = mock_server::MockServer::start_failing(first.clone(), status).await;
+        let options = server.connect_options();
+        let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let session = Session {
+            slot_range: Some(std::sync::Mutex::new(slot_range::SlotRange::new(Some(100), None))),
+            pipeline: Some(std::sync::Mutex::new(
+                pipeline::Pipeline::parse(&["dedup:100".to_string(), "decode".to_string(), "sink".to_string()])
+                    .unwrap(),
+            )),
+            sinks: sinks::SinkSet::start(vec![Box::new(RecordingSink(received.clone()))], true).unwrap(),
+            ..Default::default()
+        };
+        let requests = [replay_request(100)];
+        let retry = RetryLimits { max_attempts: Some(3), ..retry_limits() };
+        let reconnect = run_with_reconnect(&options, &requests, &session, retry);
+        // The replay from slot 101 repeats what was already handled
+        let replay = vec![slot_update(101), account(1, 101, 2), slot_update(102), account(1, 102, 3)];
+        let failover = async {
+            wait_for(|| session.recent_errors.lock().unwrap().total() == 1).await;
+            server.restart(replay, Duration::from_millis(200)).await;
+            wait_for(|| session.messages.lock().unwrap().total() == 8).await;
+        };
+        tokio::select! {
+            result = reconnect => panic!("the reconnect loop ended: {:?}", result),
+            () = failover => {}
+        }
+        let from_slots: Vec<_> = server.subscribe_requests().iter().map(|request| request.from_slot).collect();
+        assert_eq!(from_slots, [Some(100), Some(101)]);
+        session.sinks.finish();
+        let received = received.lock().unwrap();
+        assert_eq!(recorded_slots(&received), [100, 100, 101, 101, 102, 102]);
+        assert_eq!(session.filtered.load(Ordering::Relaxed), 2);
+        // One failure, counted until the new stream drops in turn
+        assert_eq!(session.connection.lock().unwrap().consecutive_failures, 1);
+        assert_eq!(session.recent_errors.lock().unwrap().total(), 1);
+        assert_eq!(session.connections.load(Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn ping_reply_keeps_the_stream_alive() {
+        let ping = SubscribeUpdate {
+            update_oneof: Some(subscribe_update::UpdateOneof::Ping(SubscribeUpdatePing {})),
+            ..Default::default()
+        };
+        // The server holds slot 2 until the ping is answered, and fails the
+        // stream if that takes longer than PING_REPLY_TIMEOUT
+        let server = mock_server::MockServer::start(vec![slot_update(1), ping, slot_update(2)]).await;
+        let options = server.connect_options();
+        let session = Session::default();
+        let client = client::connect(&options).await.unwrap();
+        tokio::select! {
+            end = run_subscription(client, vec![slots_request()], &session, &options) => {
+                panic!("the subscription ended: {:?}", end.err())
+            }
+            () = wait_for(|| session.messages.lock().unwrap().total() == 3) => {}
+        }
+        let replies: Vec<_> = server.requests.lock().unwrap().iter().filter(|request| request.ping.is_some()).cloned().collect();
+        let expected = SubscribeRequest { ping: Some(SubscribeRequestPing { id: 1 }), ..Default::default() };
+        assert_eq!(replies, [expected]);
+    }
+
+    #[tokio::test]
+    async fn goaway_mid_stream_ends_as_server_closed() {
+        let mut server = mock_server::MockServer::start(vec![slot_update(1), slot_update(2)]).await;
+        let options = server.connect_options();
+        let session = Session::default();
+        let client = client::connect(&options).await.unwrap();
+        let subscription = run_subscription(client, vec![slots_request()], &session, &options);
+        let deploy = async {
+            wait_for(|| session.messages.lock().unwrap().total() == 2).await;
+            server.restart(Vec::new(), Duration::from_millis(200)).await;
+            std::future::pending().await
+        };
+        let end = tokio::select! {
+            end = subscription => end,
+            never = deploy => never,
+        };
+        assert!(matches!(end, Ok(SubscriptionEnd::ServerClosed)), "ended with {:?}", end.err());
+    }
+
+    #[tokio::test]
+    async fn goaway_reconnects_without_counting_a_failure() {
+        let mut server = mock_server::MockServer::start(vec![slot_update(1), slot_update(2)]).await;
+        let options = server.connect_options();
+        let session = Session::default();
+        let requests = [slots_request()];
+        // A failure would end the loop: one attempt is all it gets
+        let reconnect = run_with_reconnect(&options, &requests, &session, retry_limits());
+        let deploy = async {
+            wait_for(|| session.messages.lock().unwrap().total() == 2).await;
+            server.restart(vec![slot_update(3)], Duration::from_millis(200)).await;
+            wait_for(|| session.messages.lock().unwrap().total() == 3).await;
+        };
+        tokio::select! {
+            result = reconnect => panic!("the reconnect loop ended: {:?}", result),
+            () = deploy => {}
+        }
+        assert_eq!(server.subscriptions.load(Ordering::SeqCst), 2);
+        assert_eq!(session.connections.load(Ordering::Relaxed), 2);
+        assert_eq!(session.connection.lock().unwrap().consecutive_failures, 0);
+        assert_eq!(session.recent_errors.lock().unwrap().total(), 0);
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::client::Header;
+use crate::config::{self, Config};
+use crate::sinks;
+use crate::stats::RunStats;
+
+/// Describes a run so captured data can be traced back to how it was made.
+/// Written at startup and rewritten with the end time and stats on shutdown.
+#[derive(Debug, Serialize)]
+pub struct Manifest {
+    #[serde(skip)]
+    path: PathBuf,
+    started_at_unix: u64,
+    ended_at_unix: Option<u64>,
+    version: &'static str,
+    git_commit: &'static str,
+    /// Layout of the records in `outputs`
+    schema_version: u32,
+    /// Effective configuration with credentials redacted
+    config: Value,
+    outputs: Vec<PathBuf>,
+    stats: Option<RunStats>,
+}
+
+impl Manifest {
+    pub fn new(path: PathBuf, config: &Config, request_id: &str, outputs: Vec<PathBuf>) -> Result<Self> {
+        let mut config_value = serde_json::to_value(config)?;
+        // The generated id, if none was configured
+        config_value["request_id"] = Value::String(request_id.to_string());
+        if let Some(token) = config.x_token() {
+            config_value["x_token"] = Value::String(config::redact(&token));
+        }
+        if let Some(secret) = &config.hmac_secret {
+            config_value["hmac_secret"] = Value::String(config::redact(secret));
+        }
+        config_value["endpoint"] = Value::String(config::redact_url(&config.endpoint));
+        if let Some(url) = &config.rpc_backfill {
+            config_value["rpc_backfill"] = Value::String(config::redact_url(url));
+        }
+        config_value["x_tokens"] = config.x_tokens().iter().map(|token| Value::String(config::redact(token))).collect();
+        config_value["headers"] = config
+            .headers
+            .iter()
+            .map(|raw| match Header::parse(raw) {
+                Ok(header) => Value::String(header.display()),
+                Err(_) => Value::String(config::redact(raw)),
+            })
+            .collect();
+
+        Ok(Self {
+            path,
+            started_at_unix: unix_now(),
+            ended_at_unix: None,
+            version: env!("CARGO_PKG_VERSION"),
+            git_commit: env!("GIT_COMMIT"),
+            schema_version: sinks::SCHEMA_VERSION,
+            config: config_value,
+            outputs,
+            stats: None,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records the end of the run and rewrites the file.
+    pub fn finish(&mut self, stats: RunStats) -> Result<()> {
+        self.ended_at_unix = Some(unix_now());
+        self.stats = Some(stats);
+        self.write()
+    }
+
+    pub fn write(&self) -> Result<()> {
+        let contents = serde_json::to_string_pretty(self)?;
+        fs::write(&self.path, contents)
+            .with_context(|| format!("failed to write run manifest {}", self.path.display()))
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
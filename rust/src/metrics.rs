@@ -0,0 +1,180 @@
+use std::fmt::Write;
+
+use crate::connection_state::ConnectionState;
+use crate::session::Session;
+use crate::slot_rate::Progress;
+
+/// Whether a metric only goes up or is a current value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Counter,
+    Gauge,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Kind::Counter => "counter",
+            Kind::Gauge => "gauge",
+        }
+    }
+}
+
+/// One value of a metric. Samples of the same name are adjacent and differ
+/// in their labels. Prometheus and StatsD both export these.
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub name: &'static str,
+    pub kind: Kind,
+    pub help: &'static str,
+    pub labels: Vec<(&'static str, String)>,
+    pub value: u64,
+}
+
+fn sample(name: &'static str, kind: Kind, help: &'static str, value: u64) -> Sample {
+    Sample { name, kind, help, labels: Vec::new(), value }
+}
+
+/// The session's counters and connection health, as samples.
+pub fn collect(session: &Session) -> Vec<Sample> {
+    let stats = session.run_stats();
+    let mut samples = Vec::new();
+
+    for (kind, count) in &stats.messages_by_type {
+        samples.push(Sample {
+            labels: vec![("type", kind.to_string())],
+            ..sample("geyser_messages_total", Kind::Counter, "Messages received, by update type", *count)
+        });
+    }
+    for (kind, bytes) in &stats.bytes_by_type {
+        samples.push(Sample {
+            labels: vec![("type", kind.to_string())],
+            ..sample("geyser_bytes_total", Kind::Counter, "Encoded bytes received, by update type", *bytes)
+        });
+    }
+    for (filter, kind, count) in session.filter_counts.lock().unwrap().counts() {
+        samples.push(Sample {
+            labels: vec![("filter", filter.to_string()), ("type", kind.to_string())],
+            ..sample(
+                "geyser_filter_messages_total",
+                Kind::Counter,
+                "Messages received, by the subscription filter that matched them and update type",
+                count.messages,
+            )
+        });
+    }
+    for (filter, kind, count) in session.filter_counts.lock().unwrap().counts() {
+        samples.push(Sample {
+            labels: vec![("filter", filter.to_string()), ("type", kind.to_string())],
+            ..sample(
+                "geyser_filter_bytes_total",
+                Kind::Counter,
+                "Encoded bytes received, by the subscription filter that matched them and update type",
+                count.bytes,
+            )
+        });
+    }
+    samples.push(sample(
+        "geyser_filtered_total",
+        Kind::Counter,
+        "Updates dropped by client-side filters",
+        stats.filtered,
+    ));
+    samples.push(sample(
+        "geyser_sink_errors_total",
+        Kind::Counter,
+        "Updates a sink failed to write",
+        stats.sink_errors,
+    ));
+    if let Some(slot) = stats.last_slot {
+        samples.push(sample("geyser_last_slot", Kind::Gauge, "Highest slot seen in a slot update", slot));
+    }
+
+    samples.push(sample("geyser_connections_total", Kind::Counter, "Successful connections", stats.connections));
+    samples.push(sample("geyser_reconnects_total", Kind::Counter, "Successful reconnects", stats.reconnects));
+    samples.push(sample(
+        "geyser_recycles_total",
+        Kind::Counter,
+        "Planned reconnects after the maximum subscription lifetime",
+        stats.recycles,
+    ));
+
+    if let Some(slot_rate) = &session.slot_rate {
+        let stalls = slot_rate.lock().unwrap().stall_counts();
+        samples.push(sample(
+            "geyser_slot_stall",
+            Kind::Gauge,
+            "1 while messages arrive but slots have stopped advancing",
+            u64::from(stalls.progress == Progress::SlotStall),
+        ));
+        samples.push(sample(
+            "geyser_no_data",
+            Kind::Gauge,
+            "1 while a connected stream delivers nothing, not even pings",
+            u64::from(stalls.progress == Progress::NoData),
+        ));
+        samples.push(sample(
+            "geyser_slot_stalls_total",
+            Kind::Counter,
+            "Times slots stopped advancing while messages kept arriving",
+            stalls.slot_stalls,
+        ));
+        samples.push(sample(
+            "geyser_no_data_total",
+            Kind::Counter,
+            "Times a connected stream went silent",
+            stalls.no_data,
+        ));
+    }
+
+    let health = session.connection.lock().unwrap();
+    for state in ConnectionState::ALL {
+        samples.push(Sample {
+            labels: vec![("state", state.as_str().to_string())],
+            ..sample(
+                "geyser_connection_state",
+                Kind::Gauge,
+                "1 for the current connection state",
+                u64::from(health.state == state),
+            )
+        });
+    }
+    samples.push(sample(
+        "geyser_consecutive_connection_failures",
+        Kind::Gauge,
+        "Failed connection attempts since the last stream that delivered messages",
+        u64::from(health.consecutive_failures),
+    ));
+    if let Some(at) = health.last_reconnect_unix {
+        samples.push(sample(
+            "geyser_last_reconnect_timestamp_seconds",
+            Kind::Gauge,
+            "Unix time of the last successful reconnect",
+            at,
+        ));
+    }
+    samples
+}
+
+/// Renders the session's counters in the Prometheus text format.
+pub fn render(session: &Session) -> String {
+    let mut out = String::new();
+    let mut previous = None;
+    for sample in collect(session) {
+        if previous != Some(sample.name) {
+            let _ = writeln!(out, "# HELP {} {}", sample.name, sample.help);
+            let _ = writeln!(out, "# TYPE {} {}", sample.name, sample.kind.as_str());
+            previous = Some(sample.name);
+        }
+        let labels: Vec<String> = sample.labels.iter().map(|(key, value)| format!("{key}=\"{value}\"")).collect();
+        match labels.is_empty() {
+            true => {
+                let _ = writeln!(out, "{} {}", sample.name, sample.value);
+            }
+            false => {
+                let _ = writeln!(out, "{}{{{}}} {}", sample.name, labels.join(","), sample.value);
+            }
+        }
+    }
+    out
+}
@@ -0,0 +1,256 @@
+use std::net::{Shutdown, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::{Stream, StreamExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{Request, Response, Status, Streaming};
+use yellowstone_grpc_proto::prelude::geyser_server::{Geyser, GeyserServer};
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::client::{ConnectOptions, IpVersion};
+use crate::provider::Provider;
+
+/// How long the server waits for the reply to a ping it sent before
+/// failing the stream, as providers drop clients that stop answering.
+pub const PING_REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A Geyser server on localhost for tests. Every subscription is sent the
+/// same updates and then stays open, as a quiet live stream would, or
+/// fails if the server was started with an error to end on.
+pub struct MockServer {
+    pub addr: SocketAddr,
+    /// Subscriptions opened so far, across restarts
+    pub subscriptions: Arc<AtomicUsize>,
+    /// Every request the client sent on any subscription, in order: the
+    /// subscription requests and the ping replies
+    pub requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    /// Where the listener hands new connections
+    accepted: Arc<Mutex<mpsc::UnboundedSender<std::io::Result<TcpStream>>>>,
+    backend: Backend,
+    listener: JoinHandle<()>,
+}
+
+/// One server instance behind the listener, replaced on a restart.
+struct Backend {
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+    /// A handle on every socket it was given, to cut connections that
+    /// outlast a graceful shutdown
+    sockets: Arc<Mutex<Vec<std::net::TcpStream>>>,
+}
+
+/// What each subscription to a backend is sent.
+#[derive(Clone)]
+struct Script {
+    updates: Vec<SubscribeUpdate>,
+    /// Sent after the updates instead of leaving the stream open
+    end: Option<Status>,
+}
+
+#[derive(Clone)]
+struct Recorder {
+    subscriptions: Arc<AtomicUsize>,
+    requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+}
+
+struct MockGeyser {
+    script: Script,
+    recorder: Recorder,
+}
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl Geyser for MockGeyser {
+    type SubscribeStream = UpdateStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<Streaming<SubscribeRequest>>,
+    ) -> Result<Response<UpdateStream>, Status> {
+        self.recorder.subscriptions.fetch_add(1, Ordering::SeqCst);
+        // Drained so ping replies never fill the window
+        let mut requests = request.into_inner();
+        let recorded = self.recorder.requests.clone();
+        let (ping_replied, mut ping_replies) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Some(Ok(request)) = requests.next().await {
+                if request.ping.is_some() {
+                    let _ = ping_replied.send(());
+                }
+                recorded.lock().unwrap().push(request);
+            }
+        });
+
+        let Script { updates, end } = self.script.clone();
+        let (sender, receiver) = mpsc::channel(16);
+        tokio::spawn(async move {
+            for update in updates {
+                let is_ping = matches!(update.update_oneof, Some(subscribe_update::UpdateOneof::Ping(_)));
+                if sender.send(Ok(update)).await.is_err() {
+                    return;
+                }
+                if is_ping && !matches!(tokio::time::timeout(PING_REPLY_TIMEOUT, ping_replies.recv()).await, Ok(Some(()))) {
+                    let _ = sender.send(Err(Status::deadline_exceeded("no reply to the ping"))).await;
+                    return;
+                }
+            }
+            match end {
+                Some(status) => {
+                    let _ = sender.send(Err(status)).await;
+                }
+                None => sender.closed().await,
+            }
+        });
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn subscribe_replay_info(
+        &self,
+        _: Request<SubscribeReplayInfoRequest>,
+    ) -> Result<Response<SubscribeReplayInfoResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn ping(&self, _: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn get_latest_blockhash(
+        &self,
+        _: Request<GetLatestBlockhashRequest>,
+    ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn get_block_height(
+        &self,
+        _: Request<GetBlockHeightRequest>,
+    ) -> Result<Response<GetBlockHeightResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn get_slot(&self, _: Request<GetSlotRequest>) -> Result<Response<GetSlotResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn is_blockhash_valid(
+        &self,
+        _: Request<IsBlockhashValidRequest>,
+    ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+
+    async fn get_version(&self, _: Request<GetVersionRequest>) -> Result<Response<GetVersionResponse>, Status> {
+        Err(Status::unimplemented("mock"))
+    }
+}
+
+impl Backend {
+    fn start(script: Script, recorder: Recorder) -> (Self, mpsc::UnboundedSender<std::io::Result<TcpStream>>) {
+        let service = GeyserServer::new(MockGeyser { script, recorder });
+        let (accepted, incoming) = mpsc::unbounded_channel::<std::io::Result<TcpStream>>();
+        let sockets: Arc<Mutex<Vec<std::net::TcpStream>>> = Default::default();
+        let kept = sockets.clone();
+        let incoming = tokio_stream::wrappers::UnboundedReceiverStream::new(incoming).map(move |socket| {
+            let socket = socket?.into_std()?;
+            kept.lock().unwrap().push(socket.try_clone()?);
+            TcpStream::from_std(socket)
+        });
+        let (shutdown, signal) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(service)
+                .serve_with_incoming_shutdown(incoming, async {
+                    let _ = signal.await;
+                })
+                .await
+                .unwrap();
+        });
+        (Self { shutdown, task, sockets }, accepted)
+    }
+
+    /// Shuts down the way providers roll out a deploy: an HTTP/2 GOAWAY
+    /// first, then the connections still open are cut after `drain`.
+    async fn shutdown_gracefully(self, drain: Duration) {
+        let _ = self.shutdown.send(());
+        let mut task = self.task;
+        if tokio::time::timeout(drain, &mut task).await.is_err() {
+            for socket in self.sockets.lock().unwrap().iter() {
+                let _ = socket.shutdown(Shutdown::Both);
+            }
+            task.abort();
+        }
+    }
+}
+
+impl MockServer {
+    /// Serves `updates` on a free localhost port.
+    pub async fn start(updates: Vec<SubscribeUpdate>) -> Self {
+        Self::serve(Script { updates, end: None }).await
+    }
+
+    /// Serves `updates` and then fails every subscription with `status`.
+    pub async fn start_failing(updates: Vec<SubscribeUpdate>, status: Status) -> Self {
+        Self::serve(Script { updates, end: Some(status) }).await
+    }
+
+    async fn serve(script: Script) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let recorder = Recorder { subscriptions: Default::default(), requests: Default::default() };
+        let (backend, accepted) = Backend::start(script, recorder.clone());
+        let accepted = Arc::new(Mutex::new(accepted));
+        let handoff = accepted.clone();
+        let listener = tokio::spawn(async move {
+            loop {
+                let socket = listener.accept().await.map(|(socket, _)| socket);
+                let _ = handoff.lock().unwrap().send(socket);
+            }
+        });
+        let Recorder { subscriptions, requests } = recorder;
+        Self { addr, subscriptions, requests, accepted, backend, listener }
+    }
+
+    pub fn connect_options(&self) -> ConnectOptions {
+        ConnectOptions {
+            endpoint: format!("http://{}", self.addr),
+            x_token: None,
+            headers: Vec::new(),
+            hmac: None,
+            preset: Provider::Generic.preset(),
+            tls_domain: None,
+            tls13_only: false,
+            ip_version: IpVersion::Auto,
+            request_id: "mock".to_string(),
+            user_agent: "mock".to_string(),
+        }
+    }
+
+    /// The subscription requests received so far, without the ping replies.
+    pub fn subscribe_requests(&self) -> Vec<SubscribeRequest> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().filter(|request| request.ping.is_none()).cloned().collect()
+    }
+
+    /// A rolling restart: new connections go to a fresh server serving
+    /// `updates` while the old one shuts down gracefully.
+    pub async fn restart(&mut self, updates: Vec<SubscribeUpdate>, drain: Duration) {
+        let recorder = Recorder { subscriptions: self.subscriptions.clone(), requests: self.requests.clone() };
+        let (backend, accepted) = Backend::start(Script { updates, end: None }, recorder);
+        *self.accepted.lock().unwrap() = accepted;
+        std::mem::replace(&mut self.backend, backend).shutdown_gracefully(drain).await;
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.listener.abort();
+        self.backend.task.abort();
+    }
+}
@@ -0,0 +1,209 @@
+use anyhow::{Result, bail};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet, VecDeque};
+use xxhash_rust::xxh3::xxh3_64;
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::stats;
+
+/// Updates remembered by `dedup` when no window is given.
+const DEFAULT_DEDUP_WINDOW: usize = 10_000;
+
+/// Stages `--pipeline` accepts, for the error on unknown ones.
+const STAGES: &str = "decode, sink, dedup[:<window>], sample:<n>, strip-data";
+
+/// What a stage does with an update.
+pub enum StageResult {
+    /// Hand it on unchanged
+    Pass,
+    /// Hand on this modified copy instead
+    Transform(Box<SubscribeUpdate>),
+    /// Stop here; later stages, the handler and the sinks don't see it
+    Drop,
+}
+
+/// One step of a `--pipeline`. Stages see every update except pings,
+/// which always pass so they are still answered.
+pub trait Stage: Send {
+    fn apply(&mut self, update: &SubscribeUpdate) -> StageResult;
+}
+
+/// Drops updates identical to one of the last `window`, e.g. repeats
+/// across overlapping filters or a replay after reconnecting.
+pub struct Dedup {
+    window: usize,
+    order: VecDeque<u64>,
+    seen: HashSet<u64>,
+}
+
+impl Dedup {
+    pub fn new(window: usize) -> Self {
+        Self { window, order: VecDeque::with_capacity(window), seen: HashSet::with_capacity(window) }
+    }
+}
+
+impl Stage for Dedup {
+    fn apply(&mut self, update: &SubscribeUpdate) -> StageResult {
+        // The update itself, not which filters matched it or when
+        let hash = match &update.update_oneof {
+            Some(oneof) => {
+                let mut bytes = Vec::new();
+                oneof.encode(&mut bytes);
+                xxh3_64(&bytes)
+            }
+            None => return StageResult::Pass,
+        };
+        if !self.seen.insert(hash) {
+            return StageResult::Drop;
+        }
+        self.order.push_back(hash);
+        if self.order.len() > self.window
+            && let Some(oldest) = self.order.pop_front()
+        {
+            self.seen.remove(&oldest);
+        }
+        StageResult::Pass
+    }
+}
+
+/// Passes every `every`th update of each type.
+struct Sample {
+    every: u64,
+    counts: HashMap<&'static str, u64>,
+}
+
+impl Stage for Sample {
+    fn apply(&mut self, update: &SubscribeUpdate) -> StageResult {
+        let count = self.counts.entry(stats::update_kind(update)).or_default();
+        let pass = count.is_multiple_of(self.every);
+        *count += 1;
+        if pass { StageResult::Pass } else { StageResult::Drop }
+    }
+}
+
+/// Clears account data, for when only lamports, owners and slots matter.
+struct StripData;
+
+impl Stage for StripData {
+    fn apply(&mut self, update: &SubscribeUpdate) -> StageResult {
+        match &update.update_oneof {
+            Some(UpdateOneof::Account(account))
+                if account.account.as_ref().is_some_and(|info| !info.data.is_empty()) =>
+            {
+                let mut update = update.clone();
+                if let Some(UpdateOneof::Account(account)) = &mut update.update_oneof
+                    && let Some(info) = &mut account.account
+                {
+                    info.data.clear();
+                }
+                StageResult::Transform(Box::new(update))
+            }
+            _ => StageResult::Pass,
+        }
+    }
+}
+
+/// The ordered stages of `--pipeline`. `decode` and `sink` mark where the
+/// handler and the sinks sit: stages before `decode` apply to everything,
+/// stages between `decode` and `sink` only to what the sinks receive.
+pub struct Pipeline {
+    before_decode: Vec<Box<dyn Stage>>,
+    before_sink: Vec<Box<dyn Stage>>,
+}
+
+impl Pipeline {
+    /// Builds the pipeline from its stage list, e.g.
+    /// `dedup, decode, sample:10, sink`. Every invalid stage is reported,
+    /// not just the first.
+    pub fn parse(stages: &[String]) -> Result<Self> {
+        let mut pipeline = Self { before_decode: Vec::new(), before_sink: Vec::new() };
+        let mut errors = Vec::new();
+        let (mut decode, mut sink) = (false, false);
+        for spec in stages {
+            let (name, arg) = match spec.split_once(':') {
+                Some((name, arg)) => (name.trim(), Some(arg.trim())),
+                None => (spec.trim(), None),
+            };
+            let stage: Box<dyn Stage> = match (name, arg) {
+                ("decode", None) if decode => {
+                    errors.push("`decode` is listed twice".to_string());
+                    continue;
+                }
+                ("decode", None) => {
+                    decode = true;
+                    continue;
+                }
+                ("sink", None) if sink => {
+                    errors.push("`sink` is listed twice".to_string());
+                    continue;
+                }
+                ("sink", None) => {
+                    sink = true;
+                    continue;
+                }
+                ("dedup", arg) => match arg.map(str::parse::<usize>).unwrap_or(Ok(DEFAULT_DEDUP_WINDOW)) {
+                    Ok(window) if window > 0 => Box::new(Dedup::new(window)),
+                    _ => {
+                        errors.push(format!("`{spec}`: the dedup window must be a positive number of updates"));
+                        continue;
+                    }
+                },
+                ("sample", Some(every)) => match every.parse::<u64>() {
+                    Ok(every) if every > 0 => Box::new(Sample { every, counts: HashMap::new() }),
+                    _ => {
+                        errors.push(format!("`{spec}`: sample takes a positive count, e.g. sample:10"));
+                        continue;
+                    }
+                },
+                ("strip-data", None) => Box::new(StripData),
+                ("decode" | "sink" | "sample" | "strip-data", _) => {
+                    errors.push(format!("`{spec}`: unexpected or missing argument"));
+                    continue;
+                }
+                _ => {
+                    errors.push(format!("unknown stage `{spec}`"));
+                    continue;
+                }
+            };
+            match (decode, sink) {
+                (false, false) => pipeline.before_decode.push(stage),
+                (true, false) => pipeline.before_sink.push(stage),
+                _ => errors.push(format!("`{spec}` comes after `sink`, where nothing reaches it")),
+            }
+        }
+        match (decode, sink) {
+            (true, true) => {}
+            (false, _) => errors.push("`decode` is missing".to_string()),
+            (true, false) => errors.push("`sink` is missing".to_string()),
+        }
+        if !errors.is_empty() {
+            bail!("invalid --pipeline:\n  {}\nstages are: {}", errors.join("\n  "), STAGES);
+        }
+        Ok(pipeline)
+    }
+
+    /// Runs the stages before `decode`; `None` if one dropped the update.
+    pub fn before_decode<'a>(&mut self, update: &'a SubscribeUpdate) -> Option<Cow<'a, SubscribeUpdate>> {
+        run(&mut self.before_decode, update)
+    }
+
+    /// Runs the stages between `decode` and `sink`.
+    pub fn before_sink<'a>(&mut self, update: &'a SubscribeUpdate) -> Option<Cow<'a, SubscribeUpdate>> {
+        run(&mut self.before_sink, update)
+    }
+}
+
+fn run<'a>(stages: &mut [Box<dyn Stage>], update: &'a SubscribeUpdate) -> Option<Cow<'a, SubscribeUpdate>> {
+    let mut update = Cow::Borrowed(update);
+    if matches!(update.update_oneof, Some(UpdateOneof::Ping(_))) {
+        return Some(update);
+    }
+    for stage in stages {
+        match stage.apply(&update) {
+            StageResult::Pass => {}
+            StageResult::Transform(transformed) => update = Cow::Owned(*transformed),
+            StageResult::Drop => return None,
+        }
+    }
+    Some(update)
+}
@@ -0,0 +1,50 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::time::Duration;
+
+/// Provider hint used to preload transport defaults. Presets only change
+/// the message size limit and HTTP/2 keepalive; ping replies are the same
+/// for all of them (see `--no-ping-reply`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Triton,
+    Helius,
+    #[default]
+    Generic,
+}
+
+/// Transport settings that differ between Yellowstone providers.
+#[derive(Debug, Clone, Copy)]
+pub struct ProviderPreset {
+    pub connect_timeout: Duration,
+    pub max_decoding_message_size: usize,
+    /// Client-side HTTP/2 keepalive pings, for providers that expect them
+    /// instead of (or on top of) application-level pings.
+    pub http2_keep_alive_interval: Option<Duration>,
+}
+
+impl Provider {
+    pub fn preset(self) -> ProviderPreset {
+        match self {
+            // Room for the largest blocks, without client-side keepalive
+            Provider::Triton => ProviderPreset {
+                connect_timeout: Duration::from_secs(10),
+                max_decoding_message_size: 1024 * 1024 * 1024,
+                http2_keep_alive_interval: None,
+            },
+            // As Triton, plus HTTP/2 keepalive to hold idle connections open
+            Provider::Helius => ProviderPreset {
+                connect_timeout: Duration::from_secs(10),
+                max_decoding_message_size: 1024 * 1024 * 1024,
+                http2_keep_alive_interval: Some(Duration::from_secs(30)),
+            },
+            // Large enough for full blocks, which exceed tonic's 4 MiB default
+            Provider::Generic => ProviderPreset {
+                connect_timeout: Duration::from_secs(10),
+                max_decoding_message_size: 64 * 1024 * 1024,
+                http2_keep_alive_interval: None,
+            },
+        }
+    }
+}
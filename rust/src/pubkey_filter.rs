@@ -0,0 +1,81 @@
+use std::collections::HashSet;
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, SubscribeUpdateTransaction, subscribe_update::UpdateOneof};
+
+/// Client-side `--include-pubkey` / `--exclude-pubkey`, applied to the
+/// pubkey of account updates and to every account a transaction touches.
+pub struct PubkeyFilter {
+    include: HashSet<Vec<u8>>,
+    exclude: HashSet<Vec<u8>>,
+}
+
+impl PubkeyFilter {
+    /// `None` when neither list has entries. Entries are validated by the
+    /// config parser.
+    pub fn new(include: &[String], exclude: &[String]) -> Option<Self> {
+        if include.is_empty() && exclude.is_empty() {
+            return None;
+        }
+        let decode = |keys: &[String]| {
+            keys.iter()
+                .filter_map(|key| bs58::decode(key).into_vec().ok())
+                .collect::<HashSet<_>>()
+        };
+        Some(Self { include: decode(include), exclude: decode(exclude) })
+    }
+
+    /// Whether `update` passes: none of its pubkeys is excluded and, with
+    /// an include list, at least one is included. Updates without pubkeys,
+    /// like slots and blocks, always pass.
+    pub fn allows(&self, update: &SubscribeUpdate) -> bool {
+        let keys: Vec<&[u8]> = match &update.update_oneof {
+            Some(UpdateOneof::Account(account)) => account.account.iter().map(|info| info.pubkey.as_slice()).collect(),
+            Some(UpdateOneof::Transaction(tx)) => transaction_keys(tx),
+            _ => return true,
+        };
+        if keys.iter().any(|key| self.exclude.contains(*key)) {
+            return false;
+        }
+        self.include.is_empty() || keys.iter().any(|key| self.include.contains(*key))
+    }
+}
+
+/// Client-side `--program`: keeps only transactions with one of the
+/// programs among their account keys. Other update types pass.
+pub struct ProgramFilter {
+    programs: HashSet<Vec<u8>>,
+}
+
+impl ProgramFilter {
+    /// `None` without programs. Entries are validated by the config parser.
+    pub fn new(programs: &[String]) -> Option<Self> {
+        let programs: HashSet<Vec<u8>> =
+            programs.iter().filter_map(|program| bs58::decode(program).into_vec().ok()).collect();
+        (!programs.is_empty()).then_some(Self { programs })
+    }
+
+    /// Whether the transaction lists one of the programs, or `None` for
+    /// other update types.
+    pub fn matches(&self, update: &SubscribeUpdate) -> Option<bool> {
+        match &update.update_oneof {
+            Some(UpdateOneof::Transaction(tx)) => {
+                Some(transaction_keys(tx).iter().any(|key| self.programs.contains(*key)))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Every account a transaction references: the message's account keys,
+/// then the accounts loaded from lookup tables.
+fn transaction_keys(tx: &SubscribeUpdateTransaction) -> Vec<&[u8]> {
+    let info = tx.transaction.as_ref();
+    let message_keys = info
+        .and_then(|info| info.transaction.as_ref())
+        .and_then(|tx| tx.message.as_ref())
+        .map(|message| message.account_keys.as_slice())
+        .unwrap_or_default();
+    let loaded = info
+        .and_then(|info| info.meta.as_ref())
+        .map(|meta| meta.loaded_writable_addresses.iter().chain(&meta.loaded_readonly_addresses));
+    message_keys.iter().chain(loaded.into_iter().flatten()).map(Vec::as_slice).collect()
+}
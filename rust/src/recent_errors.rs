@@ -0,0 +1,90 @@
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Errors kept when no `--error-history` is given.
+const DEFAULT_CAPACITY: usize = 20;
+
+/// Where an error happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorClass {
+    /// Connecting failed, before any stream was open
+    Connect,
+    /// An open subscription failed or ended
+    Stream,
+    /// Handling an update panicked
+    Handler,
+}
+
+impl ErrorClass {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Connect => "connect",
+            Self::Stream => "stream",
+            Self::Handler => "handler",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RecordedError {
+    pub at_unix_ms: u64,
+    pub class: ErrorClass,
+    /// gRPC status code name, e.g. `Unavailable`, if the error carried one
+    pub grpc_code: Option<String>,
+    pub message: String,
+}
+
+/// The last few errors of the run, oldest first, for the `/errors`
+/// endpoint and the run summary.
+pub struct RecentErrors {
+    entries: VecDeque<RecordedError>,
+    capacity: usize,
+    /// Every error recorded, including those no longer kept
+    total: u64,
+}
+
+impl Default for RecentErrors {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl RecentErrors {
+    pub fn new(capacity: usize) -> Self {
+        Self { entries: VecDeque::with_capacity(capacity), capacity, total: 0 }
+    }
+
+    /// Records `error`, with the gRPC code of the first status in its chain.
+    pub fn record(&mut self, class: ErrorClass, error: &anyhow::Error) {
+        let grpc_code = error
+            .chain()
+            .find_map(|cause| cause.downcast_ref::<tonic::Status>())
+            .map(|status| format!("{:?}", status.code()));
+        self.push(class, grpc_code, format!("{error:#}"));
+    }
+
+    pub fn push(&mut self, class: ErrorClass, grpc_code: Option<String>, message: String) {
+        self.total += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        let at_unix_ms = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        self.entries.push_back(RecordedError { at_unix_ms, class, grpc_code, message });
+    }
+
+    pub fn entries(&self) -> Vec<RecordedError> {
+        self.entries.iter().cloned().collect()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::{BTreeMap, BTreeSet};
+use yellowstone_grpc_proto::prelude::{SlotStatus, SubscribeUpdate, subscribe_update::UpdateOneof};
+
+use crate::sinks::ReceivedAt;
+
+type Held = (SubscribeUpdate, ReceivedAt);
+
+#[derive(Clone, Copy)]
+enum Kind {
+    Account,
+    Transaction,
+    Entry,
+    Block,
+}
+
+/// The data updates of one slot, by kind.
+#[derive(Default)]
+struct SlotUpdates {
+    accounts: Vec<Held>,
+    transactions: Vec<Held>,
+    entries: Vec<Held>,
+    blocks: Vec<Held>,
+}
+
+impl SlotUpdates {
+    /// Accounts by write version, transactions by their index in the block,
+    /// entries by index, then block and block meta updates.
+    fn into_ordered(mut self) -> Vec<Held> {
+        self.accounts.sort_by_key(|(update, _)| match &update.update_oneof {
+            Some(UpdateOneof::Account(account)) => account.account.as_ref().map_or(0, |info| info.write_version),
+            _ => 0,
+        });
+        self.transactions.sort_by_key(|(update, _)| match &update.update_oneof {
+            Some(UpdateOneof::Transaction(tx)) => tx.transaction.as_ref().map_or(0, |info| info.index),
+            _ => 0,
+        });
+        self.entries.sort_by_key(|(update, _)| match &update.update_oneof {
+            Some(UpdateOneof::Entry(entry)) => entry.index,
+            _ => 0,
+        });
+        let mut ordered = self.accounts;
+        ordered.extend(self.transactions);
+        ordered.extend(self.entries);
+        ordered.extend(self.blocks);
+        ordered
+    }
+}
+
+/// What to do with an update given to [`ReorderBuffer::push`].
+pub enum Reordered {
+    /// Held until its slot's status update arrives
+    Held,
+    /// Handle the update now, after these previously held ones
+    Release(Vec<Held>),
+}
+
+/// Holds a slot's account, transaction, entry and block updates until the
+/// slot's status update arrives, then releases them grouped and in order,
+/// ahead of the status update itself.
+pub struct ReorderBuffer {
+    /// Slots held longer than this many slots behind the newest status
+    /// update are released without one
+    window: u64,
+    pending: BTreeMap<u64, SlotUpdates>,
+    /// Recently released slots, so late updates for them pass straight
+    /// through rather than waiting for a status that already came
+    released: BTreeSet<u64>,
+    newest_status: u64,
+    /// Slots released by the window rather than a status update
+    pub expired_slots: u64,
+    /// Updates that arrived after their slot was released
+    pub late_updates: u64,
+}
+
+impl ReorderBuffer {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            pending: BTreeMap::new(),
+            released: BTreeSet::new(),
+            newest_status: 0,
+            expired_slots: 0,
+            late_updates: 0,
+        }
+    }
+
+    pub fn push(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> Reordered {
+        let (slot, kind) = match &update.update_oneof {
+            Some(UpdateOneof::Slot(slot_update)) => return self.status(slot_update.slot, slot_update.status),
+            Some(UpdateOneof::Account(account)) => (account.slot, Kind::Account),
+            Some(UpdateOneof::Transaction(tx)) => (tx.slot, Kind::Transaction),
+            Some(UpdateOneof::Entry(entry)) => (entry.slot, Kind::Entry),
+            Some(UpdateOneof::Block(block)) => (block.slot, Kind::Block),
+            Some(UpdateOneof::BlockMeta(meta)) => (meta.slot, Kind::Block),
+            _ => return Reordered::Release(Vec::new()),
+        };
+        if self.released.contains(&slot) || slot + self.window < self.newest_status {
+            self.late_updates += 1;
+            return Reordered::Release(Vec::new());
+        }
+        let updates = self.pending.entry(slot).or_default();
+        let held = (update.clone(), received);
+        match kind {
+            Kind::Account => updates.accounts.push(held),
+            Kind::Transaction => updates.transactions.push(held),
+            Kind::Entry => updates.entries.push(held),
+            Kind::Block => updates.blocks.push(held),
+        }
+        Reordered::Held
+    }
+
+    /// Releases `slot` on a processed, confirmed or finalized status, along
+    /// with any slot that fell out of the window. Intra-slot statuses
+    /// (first shred, completed, ...) release nothing.
+    fn status(&mut self, slot: u64, status: i32) -> Reordered {
+        let commitment = [SlotStatus::SlotProcessed, SlotStatus::SlotConfirmed, SlotStatus::SlotFinalized]
+            .iter()
+            .any(|level| *level as i32 == status);
+        if !commitment {
+            return Reordered::Release(Vec::new());
+        }
+        self.newest_status = self.newest_status.max(slot);
+        let oldest_kept = self.newest_status.saturating_sub(self.window);
+
+        let mut released = Vec::new();
+        let kept = self.pending.split_off(&oldest_kept);
+        for (_, updates) in std::mem::replace(&mut self.pending, kept) {
+            self.expired_slots += 1;
+            released.extend(updates.into_ordered());
+        }
+        if let Some(updates) = self.pending.remove(&slot) {
+            released.extend(updates.into_ordered());
+        }
+        self.released.insert(slot);
+        self.released = self.released.split_off(&oldest_kept);
+        Reordered::Release(released)
+    }
+
+    /// Takes everything still held, oldest slot first, e.g. on shutdown.
+    pub fn release_all(&mut self) -> Vec<Held> {
+        std::mem::take(&mut self.pending)
+            .into_values()
+            .flat_map(SlotUpdates::into_ordered)
+            .collect()
+    }
+}
@@ -0,0 +1,369 @@
+use anyhow::{Context, Result, bail};
+use std::collections::{BTreeMap, HashMap};
+use tracing::{info, warn};
+use yellowstone_grpc_proto::prelude::*;
+use yellowstone_grpc_proto::prost::Message;
+
+use crate::account_state;
+use crate::config::{self, Commitment, Config};
+
+/// Builds the subscription from the configuration.
+///
+/// `commitment` is a top-level field of `SubscribeRequest`, so filter types
+/// configured with different commitments can't share a request. Filters are
+/// grouped into one request per commitment level, each sent on its own
+/// stream; with no per-type overrides this is a single request.
+pub fn build_subscribe_requests(config: &Config) -> Result<Vec<SubscribeRequest>> {
+    if config.interslot_updates {
+        // Providers that don't support the flag simply keep sending
+        // commitment-level updates, so there is nothing else to handle
+        info!("Interslot slot updates enabled");
+    } else {
+        info!("Interslot slot updates disabled");
+    }
+
+    if let (Some(from), Some(to)) = (config.from_slot, config.to_slot)
+        && from > to
+    {
+        bail!("--from-slot {from} is after --to-slot {to}");
+    }
+
+    if config.no_slots {
+        // Both only act on slot updates
+        if config.to_slot.is_some() {
+            bail!("--to-slot stops on the first slot update past it, which --no-slots leaves out");
+        }
+        if config.reorder {
+            bail!("--reorder releases a slot's updates on its slot update, which --no-slots leaves out");
+        }
+    }
+    if config.no_transactions && config.stop_on_match {
+        bail!("--stop-on-match waits for transactions, which --no-transactions leaves out");
+    }
+
+    if let Some(json) = &config.request_json {
+        // Validated when the option was parsed
+        let mut request = json.to_request().map_err(anyhow::Error::msg)?;
+        info!("Subscribing with the request from --request-json; the filter options are ignored");
+        request.from_slot = config.from_slot.or(request.from_slot);
+        if config.no_blocks && (!request.blocks.is_empty() || !request.blocks_meta.is_empty()) {
+            info!("Not subscribing to blocks or block meta (--no-blocks)");
+            request.blocks.clear();
+            request.blocks_meta.clear();
+            let others = [
+                request.slots.len(),
+                request.accounts.len(),
+                request.transactions.len(),
+                request.transactions_status.len(),
+                request.entry.len(),
+            ];
+            if others.iter().all(|count| *count == 0) {
+                bail!("nothing left to subscribe to: the --request-json only has block filters, which --no-blocks leaves out");
+            }
+        }
+        return finish(vec![request], config);
+    }
+
+    let mut requests = BTreeMap::new();
+
+    if config.no_slots {
+        info!("Not subscribing to slot updates (--no-slots)");
+    } else {
+        let slots_commitment = config.slots_commitment.unwrap_or(config.commitment);
+        request_for(&mut requests, slots_commitment).slots = HashMap::from([
+            ("client".to_string(), SubscribeRequestFilterSlots {
+                filter_by_commitment: Some(true),
+                interslot_updates: Some(config.interslot_updates),
+            })
+        ]);
+    }
+
+    if config.no_accounts {
+        if config.accounts_file.is_some() {
+            info!("Not subscribing to the accounts in ACCOUNTS_FILE (--no-accounts)");
+        }
+    } else if let Some(path) = &config.accounts_file {
+        let account = config::load_accounts_file(path)?;
+        info!("Loaded {} accounts from {}", account.len(), path.display());
+        if config.nonempty_txn_signature {
+            info!("Filtering account updates without a transaction signature server-side");
+        }
+        let commitment = config.accounts_commitment.unwrap_or(config.commitment);
+        let filter = SubscribeRequestFilterAccounts {
+            account,
+            // Supported by the linked proto, so no client-side fallback
+            nonempty_txn_signature: config.nonempty_txn_signature.then_some(true),
+            ..Default::default()
+        };
+        // Extra streams for the per-level account cache, told apart by name
+        if config.account_cache {
+            for extra in config.account_cache_commitments.iter().filter(|extra| **extra != commitment) {
+                request_for(&mut requests, *extra)
+                    .accounts
+                    .insert(account_state::filter_name(extra.level()), filter.clone());
+            }
+        }
+        request_for(&mut requests, commitment).accounts.insert("accounts_file".to_string(), filter);
+    } else if config.nonempty_txn_signature {
+        warn!("--nonempty-txn-signature has no effect without an accounts filter");
+    }
+
+    let mut transactions = HashMap::new();
+    if config.transactions {
+        transactions.insert("transactions".to_string(), SubscribeRequestFilterTransactions {
+            vote: (!config.decode_votes).then_some(false),
+            ..Default::default()
+        });
+        if config.decode_votes {
+            info!("Including vote transactions, logged as vote summaries");
+        }
+    } else if config.decode_votes {
+        warn!("--decode-votes has no effect without --transactions");
+    }
+
+    // The proto filters by a single signature per named filter, so the
+    // server only sends the transactions we are watching for
+    for (index, signature) in config.tx_signatures.iter().enumerate() {
+        transactions.insert(format!("signature_{index}"), SubscribeRequestFilterTransactions {
+            signature: Some(signature.clone()),
+            ..Default::default()
+        });
+    }
+
+    if config.no_transactions {
+        if !transactions.is_empty() {
+            info!("Not subscribing to transactions (--no-transactions)");
+        }
+    } else if !transactions.is_empty() {
+        let commitment = config.transactions_commitment.unwrap_or(config.commitment);
+        request_for(&mut requests, commitment).transactions = transactions;
+    }
+
+    if requests.is_empty() {
+        bail!(
+            "nothing left to subscribe to: --no-slots, --no-accounts and --no-transactions \
+             disable every update type that is configured"
+        );
+    }
+
+    let mut requests: Vec<SubscribeRequest> = requests.into_values().collect();
+    for request in &mut requests {
+        request.from_slot = config.from_slot;
+    }
+    finish(requests, config)
+}
+
+/// Fits the requests under the size limit and logs what each stream
+/// carries.
+fn finish(requests: Vec<SubscribeRequest>, config: &Config) -> Result<Vec<SubscribeRequest>> {
+    let requests = fit_request_size(requests, config)?;
+    for request in &requests {
+        info!(
+            "Stream at {:?} commitment: slots={}, accounts={}, transactions={}",
+            request.commitment(),
+            !request.slots.is_empty(),
+            !request.accounts.is_empty(),
+            !request.transactions.is_empty()
+        );
+    }
+    Ok(requests)
+}
+
+/// Checks every request against `--max-request-bytes`, splitting the ones
+/// over it with `--split-large-requests` and failing with an explanation
+/// otherwise, rather than letting the server reject the stream.
+fn fit_request_size(requests: Vec<SubscribeRequest>, config: &Config) -> Result<Vec<SubscribeRequest>> {
+    let max = config.max_request_bytes;
+    let mut fitted = Vec::with_capacity(requests.len());
+    for request in requests {
+        let size = request.encoded_len();
+        if size <= max {
+            fitted.push(request);
+            continue;
+        }
+        let (accounts, signatures) = (account_count(&request), signature_count(&request));
+        if !config.split_large_requests {
+            bail!(
+                "the subscription at {:?} commitment is {} bytes, over the {}-byte limit (MAX_REQUEST_BYTES), \
+                 and would likely be rejected by the server. It lists {} accounts and {} watched signatures; \
+                 set SPLIT_LARGE_REQUESTS to spread them over several streams, or raise MAX_REQUEST_BYTES \
+                 if your provider accepts larger requests",
+                request.commitment(),
+                size,
+                max,
+                accounts,
+                signatures
+            );
+        }
+        let parts = split_request(&request, max).with_context(|| {
+            format!("the subscription at {:?} commitment can't be split under {} bytes", request.commitment(), max)
+        })?;
+        warn!(
+            "Subscription at {:?} commitment is {} bytes, over the {}-byte limit: split {} accounts and {} signatures over {} streams",
+            request.commitment(),
+            size,
+            max,
+            accounts,
+            signatures,
+            parts.len()
+        );
+        fitted.extend(parts);
+    }
+    Ok(fitted)
+}
+
+fn account_count(request: &SubscribeRequest) -> usize {
+    request.accounts.values().map(|filter| filter.account.len()).sum()
+}
+
+fn signature_count(request: &SubscribeRequest) -> usize {
+    request.transactions.values().filter(|filter| filter.signature.is_some()).count()
+}
+
+/// Splits the account lists and single-signature transaction filters of
+/// `request` evenly over as few requests as fit in `max` bytes. Everything
+/// else, including the slot filter, stays on the first request so no update
+/// is delivered twice.
+fn split_request(request: &SubscribeRequest, max: usize) -> Result<Vec<SubscribeRequest>> {
+    let splittable = account_count(request) + signature_count(request);
+    let mut count = request.encoded_len().div_ceil(max).max(2);
+    while count <= splittable {
+        let mut parts: Vec<SubscribeRequest> = (0..count)
+            .map(|index| SubscribeRequest {
+                commitment: request.commitment,
+                from_slot: request.from_slot,
+                ..if index == 0 { request.clone() } else { Default::default() }
+            })
+            .collect();
+        for part in &mut parts {
+            part.transactions.retain(|_, filter| filter.signature.is_none());
+            part.accounts.clear();
+        }
+        for (name, filter) in &request.accounts {
+            if filter.account.is_empty() {
+                parts[0].accounts.insert(name.clone(), filter.clone());
+                continue;
+            }
+            for (part, accounts) in parts.iter_mut().zip(even_chunks(&filter.account, count)) {
+                part.accounts.insert(name.clone(), SubscribeRequestFilterAccounts {
+                    account: accounts.to_vec(),
+                    ..filter.clone()
+                });
+            }
+        }
+        let signature_filters = request.transactions.iter().filter(|(_, filter)| filter.signature.is_some());
+        for (index, (name, filter)) in signature_filters.enumerate() {
+            parts[index % count].transactions.insert(name.clone(), filter.clone());
+        }
+        if parts.iter().all(|part| part.encoded_len() <= max) {
+            // A part can end up empty when there are fewer accounts and
+            // signatures than parts in some filter; an empty request would
+            // subscribe to nothing and just hold a stream open.
+            let mut index = 0;
+            parts.retain(|part| {
+                index += 1;
+                index == 1 || !part.accounts.is_empty() || !part.transactions.is_empty()
+            });
+            return Ok(parts);
+        }
+        count *= 2;
+    }
+    bail!("its filters other than accounts and signatures are too large on their own")
+}
+
+/// Splits `items` into `count` runs whose lengths differ by at most one,
+/// leaving out the empty runs when there are fewer items than `count`.
+fn even_chunks<T>(items: &[T], count: usize) -> impl Iterator<Item = &[T]> {
+    let (size, extra) = (items.len() / count, items.len() % count);
+    (0..count).scan(0, move |start, index| {
+        let end = *start + size + usize::from(index < extra);
+        let chunk = &items[*start..end];
+        *start = end;
+        Some(chunk)
+    })
+    .filter(|chunk| !chunk.is_empty())
+}
+
+fn request_for(
+    requests: &mut BTreeMap<i32, SubscribeRequest>,
+    commitment: Commitment,
+) -> &mut SubscribeRequest {
+    let level = commitment.level() as i32;
+    requests.entry(level).or_insert_with(|| SubscribeRequest {
+        commitment: Some(level),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    fn accounts_request(count: usize) -> SubscribeRequest {
+        let account = (0..count).map(|index| format!("{index:0>44}")).collect();
+        SubscribeRequest {
+            accounts: HashMap::from([("accounts".to_owned(), SubscribeRequestFilterAccounts {
+                account,
+                ..Default::default()
+            })]),
+            ..Default::default()
+        }
+    }
+
+    fn part_sizes(parts: &[SubscribeRequest]) -> Vec<usize> {
+        parts.iter().map(account_count).collect()
+    }
+
+    #[test]
+    fn chunks_are_even_and_never_empty() {
+        let items = [1, 2, 3, 4, 5];
+        let lengths = |count| even_chunks(&items, count).map(<[i32]>::len).collect::<Vec<_>>();
+        assert_eq!(lengths(2), [3, 2]);
+        assert_eq!(lengths(4), [2, 1, 1, 1]);
+        assert_eq!(lengths(8), [1, 1, 1, 1, 1]);
+        assert_eq!(even_chunks(&items, 4).flatten().copied().collect::<Vec<_>>(), items);
+    }
+
+    #[test]
+    fn five_accounts_split_four_ways_fill_every_part() {
+        let request = accounts_request(5);
+        // Half the request doesn't fit three accounts, so the first attempt at
+        // two parts fails and the split goes to four.
+        let max = request.encoded_len() / 2;
+        let parts = split_request(&request, max).unwrap();
+        assert_eq!(part_sizes(&parts), [2, 1, 1, 1]);
+        assert!(parts.iter().all(|part| part.encoded_len() <= max));
+    }
+
+    #[test]
+    fn parts_left_without_filters_are_dropped() {
+        let mut request = accounts_request(2);
+        request.accounts.insert("pair".to_owned(), SubscribeRequestFilterAccounts {
+            account: vec!["p".repeat(44), "q".repeat(44), "r".repeat(44)],
+            ..Default::default()
+        });
+        // Four parts, but neither filter has an account left for the last one
+        let parts = split_request(&request, request.encoded_len() / 2).unwrap();
+        assert_eq!(part_sizes(&parts), [2, 2, 1]);
+    }
+
+    fn requests_for(args: &[&str]) -> Result<Vec<SubscribeRequest>> {
+        let config = Config::try_parse_from(["svs-rust-grpc-example"].iter().chain(args))?;
+        build_subscribe_requests(&config)
+    }
+
+    #[test]
+    fn no_blocks_drops_the_block_filters_of_a_request_json() {
+        let json = r#"{"slots": {"client": {}}, "blocks": {"blocks": {}}, "blocks_meta": {"meta": {}}}"#;
+        let requests = requests_for(&["--request-json", json, "--no-blocks"]).unwrap();
+        assert!(requests[0].blocks.is_empty() && requests[0].blocks_meta.is_empty());
+        assert!(requests[0].slots.contains_key("client"));
+
+        let requests = requests_for(&["--request-json", json]).unwrap();
+        assert_eq!(requests[0].blocks.len(), 1);
+
+        let error = requests_for(&["--request-json", r#"{"blocks_meta": {"meta": {}}}"#, "--no-blocks"]).unwrap_err();
+        assert!(error.to_string().starts_with("nothing left to subscribe to"), "{error}");
+    }
+}
@@ -0,0 +1,358 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter::Filter as AccountsFilter;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter_lamports::Cmp;
+use yellowstone_grpc_proto::prelude::subscribe_request_filter_accounts_filter_memcmp::Data;
+use yellowstone_grpc_proto::prelude::*;
+
+use crate::config;
+
+/// JSON form of a `SubscribeRequest`: proto field names, the commitment by
+/// name and filters sorted by name, so dumps diff cleanly. Unset fields
+/// are left out.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RequestJson {
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub accounts: BTreeMap<String, AccountsJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub slots: BTreeMap<String, SlotsJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub transactions: BTreeMap<String, TransactionsJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub transactions_status: BTreeMap<String, TransactionsJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub blocks: BTreeMap<String, BlocksJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub blocks_meta: BTreeMap<String, EmptyJson>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub entry: BTreeMap<String, EmptyJson>,
+    /// `processed`, `confirmed` or `finalized`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub commitment: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub accounts_data_slice: Vec<DataSliceJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_slot: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccountsJson {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub account: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub owner: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub filters: Vec<AccountsFilterJson>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nonempty_txn_signature: Option<bool>,
+}
+
+/// One account filter, e.g. `{"datasize": 165}` or
+/// `{"memcmp": {"offset": 0, "base58": "..."}}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum AccountsFilterJson {
+    Memcmp(MemcmpJson),
+    Datasize(u64),
+    TokenAccountState(bool),
+    Lamports(LamportsJson),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MemcmpJson {
+    pub offset: u64,
+    #[serde(flatten)]
+    pub data: MemcmpDataJson,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemcmpDataJson {
+    Bytes(Vec<u8>),
+    Base58(String),
+    Base64(String),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum LamportsJson {
+    Eq(u64),
+    Ne(u64),
+    Lt(u64),
+    Gt(u64),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SlotsJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub filter_by_commitment: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub interslot_updates: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TransactionsJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vote: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub account_include: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub account_exclude: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub account_required: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlocksJson {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub account_include: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_transactions: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_accounts: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_entries: Option<bool>,
+}
+
+/// Filters without options, like `blocks_meta` and `entry`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmptyJson {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DataSliceJson {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Writes `requests` to `path` as pretty-printed JSON: a single request
+/// as an object, one per stream as an array.
+pub fn dump(path: &Path, requests: &[SubscribeRequest]) -> Result<()> {
+    let requests: Vec<RequestJson> = requests.iter().map(RequestJson::from).collect();
+    let json = match requests.as_slice() {
+        [request] => serde_json::to_string_pretty(request)?,
+        requests => serde_json::to_string_pretty(requests)?,
+    };
+    fs::write(path, json + "\n").with_context(|| format!("failed to write the request to {}", path.display()))
+}
+
+/// Parses and validates an inline `--request-json` request.
+pub fn parse(json: &str) -> Result<RequestJson, String> {
+    let request: RequestJson = serde_json::from_str(json).map_err(|e| format!("invalid request JSON: {e}"))?;
+    request.to_request()?;
+    Ok(request)
+}
+
+impl RequestJson {
+    /// The request to send. Every invalid pubkey, signature or commitment
+    /// is reported, not just the first.
+    pub fn to_request(&self) -> Result<SubscribeRequest, String> {
+        let mut errors = Vec::new();
+        let mut request = SubscribeRequest { from_slot: self.from_slot, ..Default::default() };
+        for (name, filter) in &self.accounts {
+            let account = checked_pubkeys(&mut errors, format!("accounts.{name}.account"), &filter.account);
+            let owner = checked_pubkeys(&mut errors, format!("accounts.{name}.owner"), &filter.owner);
+            let filters = filter.filters.iter().map(|filter| SubscribeRequestFilterAccountsFilter {
+                filter: Some(match filter {
+                    AccountsFilterJson::Memcmp(memcmp) => AccountsFilter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                        offset: memcmp.offset,
+                        data: Some(match &memcmp.data {
+                            MemcmpDataJson::Bytes(bytes) => Data::Bytes(bytes.clone()),
+                            MemcmpDataJson::Base58(data) => Data::Base58(data.clone()),
+                            MemcmpDataJson::Base64(data) => Data::Base64(data.clone()),
+                        }),
+                    }),
+                    AccountsFilterJson::Datasize(size) => AccountsFilter::Datasize(*size),
+                    AccountsFilterJson::TokenAccountState(state) => AccountsFilter::TokenAccountState(*state),
+                    AccountsFilterJson::Lamports(lamports) => {
+                        AccountsFilter::Lamports(SubscribeRequestFilterAccountsFilterLamports {
+                            cmp: Some(match *lamports {
+                                LamportsJson::Eq(value) => Cmp::Eq(value),
+                                LamportsJson::Ne(value) => Cmp::Ne(value),
+                                LamportsJson::Lt(value) => Cmp::Lt(value),
+                                LamportsJson::Gt(value) => Cmp::Gt(value),
+                            }),
+                        })
+                    }
+                }),
+            });
+            let filters = filters.collect();
+            request.accounts.insert(name.clone(), SubscribeRequestFilterAccounts {
+                account,
+                owner,
+                filters,
+                nonempty_txn_signature: filter.nonempty_txn_signature,
+            });
+        }
+        for (name, filter) in &self.slots {
+            request.slots.insert(name.clone(), SubscribeRequestFilterSlots {
+                filter_by_commitment: filter.filter_by_commitment,
+                interslot_updates: filter.interslot_updates,
+            });
+        }
+        for (field, filters, target) in [
+            ("transactions", &self.transactions, &mut request.transactions),
+            ("transactions_status", &self.transactions_status, &mut request.transactions_status),
+        ] {
+            for (name, filter) in filters {
+                if let Some(signature) = &filter.signature
+                    && bs58::decode(signature).into_vec().map_or(true, |bytes| bytes.len() != 64)
+                {
+                    errors.push(format!("{field}.{name}.signature: `{signature}` is not a base58 signature"));
+                }
+                target.insert(name.clone(), SubscribeRequestFilterTransactions {
+                    vote: filter.vote,
+                    failed: filter.failed,
+                    signature: filter.signature.clone(),
+                    account_include: checked_pubkeys(&mut errors, format!("{field}.{name}.account_include"), &filter.account_include),
+                    account_exclude: checked_pubkeys(&mut errors, format!("{field}.{name}.account_exclude"), &filter.account_exclude),
+                    account_required: checked_pubkeys(&mut errors, format!("{field}.{name}.account_required"), &filter.account_required),
+                });
+            }
+        }
+        for (name, filter) in &self.blocks {
+            request.blocks.insert(name.clone(), SubscribeRequestFilterBlocks {
+                account_include: checked_pubkeys(&mut errors, format!("blocks.{name}.account_include"), &filter.account_include),
+                include_transactions: filter.include_transactions,
+                include_accounts: filter.include_accounts,
+                include_entries: filter.include_entries,
+            });
+        }
+        for name in self.blocks_meta.keys() {
+            request.blocks_meta.insert(name.clone(), SubscribeRequestFilterBlocksMeta {});
+        }
+        for name in self.entry.keys() {
+            request.entry.insert(name.clone(), SubscribeRequestFilterEntry {});
+        }
+        request.accounts_data_slice = self
+            .accounts_data_slice
+            .iter()
+            .map(|slice| SubscribeRequestAccountsDataSlice { offset: slice.offset, length: slice.length })
+            .collect();
+        if let Some(commitment) = &self.commitment {
+            match CommitmentLevel::from_str_name(&commitment.to_uppercase()) {
+                Some(level) => request.commitment = Some(level as i32),
+                None => errors.push(format!(
+                    "commitment: `{commitment}` is not one of processed, confirmed or finalized"
+                )),
+            }
+        }
+
+        if !errors.is_empty() {
+            return Err(errors.join("; "));
+        }
+        Ok(request)
+    }
+}
+
+fn checked_pubkeys(errors: &mut Vec<String>, field: String, keys: &[String]) -> Vec<String> {
+    keys.iter()
+        .map(|key| match config::parse_pubkey(key) {
+            Ok(key) => bs58::encode(key).into_string(),
+            Err(e) => {
+                errors.push(format!("{field}: {e}"));
+                key.clone()
+            }
+        })
+        .collect()
+}
+
+fn sorted<T, J>(filters: &std::collections::HashMap<String, T>, convert: impl Fn(&T) -> J) -> BTreeMap<String, J> {
+    filters.iter().map(|(name, filter)| (name.clone(), convert(filter))).collect()
+}
+
+impl From<&SubscribeRequest> for RequestJson {
+    fn from(request: &SubscribeRequest) -> Self {
+        Self {
+            accounts: sorted(&request.accounts, |filter| AccountsJson::from(filter)),
+            slots: sorted(&request.slots, |filter| SlotsJson {
+                filter_by_commitment: filter.filter_by_commitment,
+                interslot_updates: filter.interslot_updates,
+            }),
+            transactions: sorted(&request.transactions, |filter| TransactionsJson::from(filter)),
+            transactions_status: sorted(&request.transactions_status, |filter| TransactionsJson::from(filter)),
+            blocks: sorted(&request.blocks, |filter| BlocksJson {
+                account_include: filter.account_include.clone(),
+                include_transactions: filter.include_transactions,
+                include_accounts: filter.include_accounts,
+                include_entries: filter.include_entries,
+            }),
+            blocks_meta: sorted(&request.blocks_meta, |_| EmptyJson {}),
+            entry: sorted(&request.entry, |_| EmptyJson {}),
+            commitment: request.commitment.map(|level| match CommitmentLevel::try_from(level) {
+                Ok(level) => level.as_str_name().to_lowercase(),
+                // Kept as the raw value; unknown to this proto version
+                Err(_) => level.to_string(),
+            }),
+            accounts_data_slice: request
+                .accounts_data_slice
+                .iter()
+                .map(|slice| DataSliceJson { offset: slice.offset, length: slice.length })
+                .collect(),
+            from_slot: request.from_slot,
+        }
+    }
+}
+
+impl From<&SubscribeRequestFilterAccounts> for AccountsJson {
+    fn from(filter: &SubscribeRequestFilterAccounts) -> Self {
+        Self {
+            account: filter.account.clone(),
+            owner: filter.owner.clone(),
+            filters: filter
+                .filters
+                .iter()
+                .filter_map(|filter| filter.filter.as_ref())
+                // An empty oneof carries nothing to record
+                .filter_map(|filter| {
+                    Some(match filter {
+                        AccountsFilter::Memcmp(memcmp) => AccountsFilterJson::Memcmp(MemcmpJson {
+                            offset: memcmp.offset,
+                            data: match memcmp.data.as_ref()? {
+                                Data::Bytes(bytes) => MemcmpDataJson::Bytes(bytes.clone()),
+                                Data::Base58(data) => MemcmpDataJson::Base58(data.clone()),
+                                Data::Base64(data) => MemcmpDataJson::Base64(data.clone()),
+                            },
+                        }),
+                        AccountsFilter::Datasize(size) => AccountsFilterJson::Datasize(*size),
+                        AccountsFilter::TokenAccountState(state) => AccountsFilterJson::TokenAccountState(*state),
+                        AccountsFilter::Lamports(lamports) => AccountsFilterJson::Lamports(match lamports.cmp? {
+                            Cmp::Eq(value) => LamportsJson::Eq(value),
+                            Cmp::Ne(value) => LamportsJson::Ne(value),
+                            Cmp::Lt(value) => LamportsJson::Lt(value),
+                            Cmp::Gt(value) => LamportsJson::Gt(value),
+                        }),
+                    })
+                })
+                .collect(),
+            nonempty_txn_signature: filter.nonempty_txn_signature,
+        }
+    }
+}
+
+impl From<&SubscribeRequestFilterTransactions> for TransactionsJson {
+    fn from(filter: &SubscribeRequestFilterTransactions) -> Self {
+        Self {
+            vote: filter.vote,
+            failed: filter.failed,
+            signature: filter.signature.clone(),
+            account_include: filter.account_include.clone(),
+            account_exclude: filter.account_exclude.clone(),
+            account_required: filter.account_required.clone(),
+        }
+    }
+}
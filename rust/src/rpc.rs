@@ -0,0 +1,142 @@
+use anyhow::{Context, Result, anyhow, bail};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper::header::{CONTENT_TYPE, HOST, RETRY_AFTER};
+use hyper::{Request, StatusCode};
+use hyper_util::rt::TokioIo;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tokio_rustls::rustls::ClientConfig;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tonic::codegen::http::Uri;
+
+use crate::client;
+
+/// Time allowed for one JSON-RPC call, connect included.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Why a JSON-RPC call failed.
+#[derive(Debug)]
+pub enum RpcError {
+    /// HTTP 429, with the server's `Retry-After` if it sent one
+    RateLimited(Option<Duration>),
+    /// A JSON-RPC error object
+    Rpc { code: i64, message: String },
+    /// Transport failures and malformed responses
+    Other(anyhow::Error),
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RateLimited(_) => write!(f, "rate limited"),
+            Self::Rpc { code, message } => write!(f, "RPC error {code}: {message}"),
+            Self::Other(e) => write!(f, "{e:#}"),
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::Other(e)
+    }
+}
+
+/// A minimal Solana JSON-RPC client over HTTP/1.1, opening a connection per
+/// call.
+pub struct RpcClient {
+    uri: Uri,
+    host: String,
+    port: u16,
+    tls: Option<TlsConnector>,
+}
+
+impl RpcClient {
+    pub fn new(url: &str) -> Result<Self> {
+        let uri: Uri = url.parse().with_context(|| format!("invalid RPC URL {url}"))?;
+        let host = uri.host().with_context(|| format!("RPC URL {url} has no host"))?.to_string();
+        let (tls, default_port) = match uri.scheme_str() {
+            Some("https") => {
+                let mut config = ClientConfig::builder()
+                    .with_root_certificates(client::native_roots())
+                    .with_no_client_auth();
+                config.alpn_protocols = vec![b"http/1.1".to_vec()];
+                (Some(TlsConnector::from(Arc::new(config))), 443)
+            }
+            Some("http") => (None, 80),
+            _ => bail!("RPC URL {url} must start with http:// or https://"),
+        };
+        let port = uri.port_u16().unwrap_or(default_port);
+        Ok(Self { uri, host, port, tls })
+    }
+
+    /// Calls `method` and returns its `result`.
+    pub async fn call(&self, method: &str, params: Value) -> Result<Value, RpcError> {
+        let body = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params}).to_string();
+        let request = Request::post(self.uri.path_and_query().map_or("/", |path| path.as_str()))
+            .header(HOST, self.uri.authority().map_or(self.host.as_str(), |authority| authority.as_str()))
+            .header(CONTENT_TYPE, "application/json")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| anyhow!("failed to build RPC request: {e}"))?;
+
+        let response = tokio::time::timeout(CALL_TIMEOUT, self.send(request))
+            .await
+            .map_err(|_| anyhow!("RPC call {method} timed out after {CALL_TIMEOUT:?}"))??;
+        match response {
+            (StatusCode::TOO_MANY_REQUESTS, retry_after, _) => Err(RpcError::RateLimited(retry_after)),
+            (status, _, body) => {
+                let body: Value = serde_json::from_slice(&body)
+                    .with_context(|| format!("RPC call {method} returned HTTP {status} without a JSON body"))?;
+                if let Some(error) = body.get("error") {
+                    return Err(RpcError::Rpc {
+                        code: error["code"].as_i64().unwrap_or_default(),
+                        message: error["message"].as_str().unwrap_or_default().to_string(),
+                    });
+                }
+                Ok(body.get("result").cloned().unwrap_or(Value::Null))
+            }
+        }
+    }
+
+    async fn send(&self, request: Request<Full<Bytes>>) -> Result<(StatusCode, Option<Duration>, Bytes)> {
+        let tcp = TcpStream::connect((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("failed to connect to RPC at {}:{}", self.host, self.port))?;
+        match &self.tls {
+            Some(tls) => {
+                let server_name = ServerName::try_from(self.host.clone())
+                    .with_context(|| format!("invalid TLS server name `{}`", self.host))?;
+                let stream = tls
+                    .connect(server_name, tcp)
+                    .await
+                    .with_context(|| format!("TLS handshake with RPC at {} failed", self.host))?;
+                exchange(stream, request).await
+            }
+            None => exchange(tcp, request).await,
+        }
+    }
+}
+
+async fn exchange<S>(stream: S, request: Request<Full<Bytes>>) -> Result<(StatusCode, Option<Duration>, Bytes)>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .context("HTTP handshake with RPC failed")?;
+    tokio::spawn(connection);
+    let response = sender.send_request(request).await.context("RPC request failed")?;
+    let status = response.status();
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .map(Duration::from_secs);
+    let body = response.into_body().collect().await.context("failed to read RPC response")?.to_bytes();
+    Ok((status, retry_after, body))
+}
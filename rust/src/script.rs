@@ -0,0 +1,126 @@
+use anyhow::{Context, Result};
+use rhai::{AST, Array, Dynamic, Engine, Map, Scope};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+use yellowstone_grpc_proto::prelude::*;
+
+/// A user-supplied rhai script that decides whether an update is processed.
+///
+/// The script sees the update as a map named `update` and must evaluate to
+/// a boolean. Errors and timeouts let the update through so a broken script
+/// never silently drops data.
+pub struct FilterScript {
+    engine: Engine,
+    ast: AST,
+    budget: Duration,
+    deadline: Arc<Mutex<Instant>>,
+}
+
+impl FilterScript {
+    /// Compiles the script once at startup.
+    pub fn load(path: &Path, budget: Duration) -> Result<Self> {
+        let mut engine = Engine::new();
+        let ast = engine
+            .compile_file(path.to_path_buf())
+            .with_context(|| format!("failed to compile filter script {}", path.display()))?;
+
+        let deadline = Arc::new(Mutex::new(Instant::now()));
+        let progress_deadline = deadline.clone();
+        engine.on_progress(move |_| {
+            if Instant::now() >= *progress_deadline.lock().unwrap() {
+                Some("time budget exceeded".into())
+            } else {
+                None
+            }
+        });
+
+        Ok(Self { engine, ast, budget, deadline })
+    }
+
+    /// Returns whether `update` should be processed. Pings and pongs always
+    /// pass since the keepalive depends on them.
+    pub fn allows(&self, update: &SubscribeUpdate) -> bool {
+        let Some(object) = update_object(update) else {
+            return true;
+        };
+
+        *self.deadline.lock().unwrap() = Instant::now() + self.budget;
+        let mut scope = Scope::new();
+        scope.push("update", object);
+        match self.engine.eval_ast_with_scope::<bool>(&mut scope, &self.ast) {
+            Ok(allowed) => allowed,
+            Err(e) => {
+                warn!("Filter script failed, passing update through: {}", e);
+                true
+            }
+        }
+    }
+}
+
+/// Flattens an update into the simplified map handed to scripts.
+fn update_object(update: &SubscribeUpdate) -> Option<Map> {
+    use subscribe_update::UpdateOneof;
+
+    let mut object = Map::new();
+    let filters: Array = update.filters.iter().cloned().map(Dynamic::from).collect();
+    object.insert("filters".into(), filters.into());
+
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Slot(slot) => {
+            object.insert("type".into(), "slot".into());
+            object.insert("slot".into(), (slot.slot as i64).into());
+            object.insert("parent".into(), (slot.parent.unwrap_or(0) as i64).into());
+            object.insert("status".into(), crate::slot_status::slot_status_name(slot.status).into_owned().into());
+        }
+        UpdateOneof::Account(account) => {
+            object.insert("type".into(), "account".into());
+            object.insert("slot".into(), (account.slot as i64).into());
+            object.insert("is_startup".into(), account.is_startup.into());
+            if let Some(info) = &account.account {
+                object.insert("pubkey".into(), bs58::encode(&info.pubkey).into_string().into());
+                object.insert("owner".into(), bs58::encode(&info.owner).into_string().into());
+                object.insert("lamports".into(), (info.lamports as i64).into());
+                object.insert("data_len".into(), (info.data.len() as i64).into());
+                object.insert("write_version".into(), (info.write_version as i64).into());
+            }
+        }
+        UpdateOneof::Transaction(tx) => {
+            object.insert("type".into(), "transaction".into());
+            object.insert("slot".into(), (tx.slot as i64).into());
+            if let Some(info) = &tx.transaction {
+                object.insert("signature".into(), bs58::encode(&info.signature).into_string().into());
+                object.insert("is_vote".into(), info.is_vote.into());
+                let failed = info.meta.as_ref().is_some_and(|meta| meta.err.is_some());
+                object.insert("failed".into(), failed.into());
+            }
+        }
+        UpdateOneof::Block(block) => {
+            object.insert("type".into(), "block".into());
+            object.insert("slot".into(), (block.slot as i64).into());
+            object.insert("blockhash".into(), block.blockhash.clone().into());
+            object.insert(
+                "executed_transaction_count".into(),
+                (block.executed_transaction_count as i64).into(),
+            );
+        }
+        UpdateOneof::BlockMeta(meta) => {
+            object.insert("type".into(), "block_meta".into());
+            object.insert("slot".into(), (meta.slot as i64).into());
+            object.insert("blockhash".into(), meta.blockhash.clone().into());
+        }
+        UpdateOneof::TransactionStatus(status) => {
+            object.insert("type".into(), "transaction_status".into());
+            object.insert("slot".into(), (status.slot as i64).into());
+            object.insert("signature".into(), bs58::encode(&status.signature).into_string().into());
+            object.insert("is_vote".into(), status.is_vote.into());
+        }
+        UpdateOneof::Entry(entry) => {
+            object.insert("type".into(), "entry".into());
+            object.insert("slot".into(), (entry.slot as i64).into());
+        }
+        UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => return None,
+    }
+    Some(object)
+}
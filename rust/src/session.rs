@@ -0,0 +1,335 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::error;
+use yellowstone_grpc_proto::prelude::SubscribeUpdate;
+
+use crate::account_fields::AccountFields;
+use crate::account_hashes::DataHashTracker;
+use crate::account_state::AccountState;
+use crate::active_slots::ActiveSlots;
+use crate::backfill::RpcBackfill;
+use crate::baseline::Baseline;
+use crate::checkpoint::Checkpoint;
+use crate::client::HmacSigner;
+use crate::coalesce::AccountCoalescer;
+use crate::connection_state::ConnectionHealth;
+use crate::burst::BurstLogger;
+use crate::handler::{PanicPolicy, UnknownUpdatePolicy};
+use crate::logging::{LogFilterHandle, OutputFormat};
+use crate::pipeline::Pipeline;
+use crate::pubkey_filter::{ProgramFilter, PubkeyFilter};
+use crate::recent_errors::RecentErrors;
+use crate::reorder::ReorderBuffer;
+use crate::shard::Shard;
+use crate::signatures::SignatureWatch;
+use crate::sinks::{ReceivedAt, SinkSet};
+use crate::slot_history::SlotHistory;
+use crate::slot_range::SlotRange;
+use crate::slot_rate::SlotRateEstimator;
+use crate::stats::{BandwidthTracker, BusiestSlot, FilterCounter, MessageCounter, RunStats};
+use crate::tokens::TokenPool;
+use crate::tx_counts::SlotTxCounter;
+use crate::warmup::SnapshotMode;
+
+#[cfg(feature = "anchor")]
+use crate::anchor::AnchorIdl;
+#[cfg(feature = "scripting")]
+use crate::script::FilterScript;
+
+/// State that outlives a single connection, shared by every reconnect.
+#[derive(Default)]
+pub struct Session {
+    pub stats_interval: Duration,
+    pub output_format: OutputFormat,
+    pub messages: Mutex<MessageCounter>,
+    pub bandwidth: Mutex<BandwidthTracker>,
+    /// Updates and bytes per filter name, for the metrics endpoint
+    pub filter_counts: Mutex<FilterCounter>,
+    pub tx_counts: Mutex<SlotTxCounter>,
+    pub signatures: Mutex<SignatureWatch>,
+    pub slot_history: Mutex<SlotHistory>,
+    pub account_hashes: Mutex<DataHashTracker>,
+    /// Only log account updates whose data changed
+    pub only_changed: bool,
+    /// Holds account updates until their slot is over, with
+    /// `--coalesce-accounts`
+    pub coalescer: Option<Mutex<AccountCoalescer>>,
+    /// Holds data updates until their slot's status update, with
+    /// `--reorder`
+    pub reorder: Option<Mutex<ReorderBuffer>>,
+    /// Access tokens rotated on auth and rate-limit errors
+    pub tokens: Option<Mutex<TokenPool>>,
+    /// `--pipeline` stages around the handler and the sinks
+    pub pipeline: Option<Mutex<Pipeline>>,
+    /// What account update log lines show
+    pub account_fields: AccountFields,
+    /// Drop account updates below this balance, except closures
+    pub min_lamports: Option<u64>,
+    /// `--include-pubkey` / `--exclude-pubkey`
+    pub pubkey_filter: Option<PubkeyFilter>,
+    /// `--program`
+    pub program_filter: Option<ProgramFilter>,
+    /// Only process updates for slots in this shard
+    pub shard: Option<Shard>,
+    /// Log the first 8 bytes of account data and known account types
+    pub show_discriminator: bool,
+    /// Log inner instructions down to this stack height, with `--show-inner`
+    pub inner_max_depth: Option<u32>,
+    /// Log vote transactions as a compact vote summary
+    pub decode_votes: bool,
+    /// Saves the last processed slot for resuming
+    pub checkpoint: Option<Mutex<Checkpoint>>,
+    /// Only log accounts that differ from this baseline
+    pub baseline: Option<Mutex<Baseline>>,
+    pub panic_policy: PanicPolicy,
+    /// Leave server pings unanswered, with `--no-ping-reply`
+    pub no_ping_reply: bool,
+    pub unknown_update_policy: UnknownUpdatePolicy,
+    /// Shares its clock offset with the connect options' signer
+    pub hmac: Option<HmacSigner>,
+    /// Read throttle, applied per subscription
+    pub max_bytes_per_sec: Option<u64>,
+    /// `--log-unknown-raw`
+    pub log_unknown_raw: bool,
+    /// `--check-invariants`
+    pub check_invariants: bool,
+    /// Recycle each subscription after this long
+    pub max_lifetime: Option<Duration>,
+    /// Open the next subscription before the lifetime one closes
+    pub warm_standby: bool,
+    /// Reconnect when a new subscription stays silent this long
+    pub first_message_timeout: Option<Duration>,
+    /// Close the subscription after this long without data updates
+    pub idle_unsubscribe: Option<Duration>,
+    /// How long an idle subscription stays closed
+    pub idle_resubscribe: Duration,
+    /// Whether snapshot or live account updates are dropped
+    pub snapshot_mode: SnapshotMode,
+    /// Stop instead of reconnecting once every watched signature is seen
+    pub stop_on_match: bool,
+    /// Successful connections, including the first one
+    pub connections: AtomicU64,
+    /// Subscriptions closed for reaching `--subscription-max-lifetime-secs`
+    pub recycles: AtomicU64,
+    /// State of the reconnect loop, for the metrics endpoint
+    pub connection: Mutex<ConnectionHealth>,
+    /// The last errors of the run, for `/errors` and the run summary
+    pub recent_errors: Mutex<RecentErrors>,
+    /// Updates dropped by client-side filters
+    pub filtered: AtomicU64,
+    /// Account updates suppressed by `--only-changed` or `--baseline`
+    pub unchanged_accounts: AtomicU64,
+    /// Updates whose handling panicked and was skipped
+    pub handler_panics: AtomicU64,
+    /// Messages that didn't match the linked proto
+    pub schema_errors: AtomicU64,
+    /// Updates of a type the client doesn't know or doesn't handle
+    pub unknown_updates: AtomicU64,
+    /// Snapshot account updates dropped by `--snapshot-mode exclude`
+    pub snapshot_skipped: AtomicU64,
+    /// Exact repeats of a (slot, status) notification
+    pub duplicate_slot_updates: AtomicU64,
+    /// Slot statuses contradicting the slot's history, e.g. processed
+    /// after confirmed
+    pub commitment_anomalies: AtomicU64,
+    /// New slots whose parent skips slots already seen
+    pub forks: AtomicU64,
+    /// Updates breaking an ordering `--check-invariants` checks
+    pub invariant_violations: AtomicU64,
+    /// Transactions kept by `--program`
+    pub program_matches: AtomicU64,
+    /// Transactions `--program` dropped, also counted as filtered
+    pub program_misses: AtomicU64,
+    /// Highest slot seen in a slot update, 0 until the first one arrives
+    pub last_slot: AtomicU64,
+    /// Set when `--from-slot` or `--to-slot` bound the run
+    pub slot_range: Option<Mutex<SlotRange>>,
+    /// Estimates slots per second with `--slot-rate`
+    pub slot_rate: Option<Mutex<SlotRateEstimator>>,
+    /// Holds slot log lines until the slot has data, with `--active-slots-only`
+    pub active_slots: Option<Mutex<ActiveSlots>>,
+    /// Fills gaps after a resume from RPC, with `--rpc-backfill`
+    pub rpc_backfill: Option<RpcBackfill>,
+    /// Coalesces log lines during bursts when `--follow-rate` is set
+    pub follow_rate: Option<Mutex<BurstLogger>>,
+    /// Latest state of the subscribed accounts, with `--account-cache`
+    pub account_state: Option<AccountState>,
+    /// Changes the log filter for `PUT /loglevel`
+    pub log_filter: Option<LogFilterHandle>,
+    /// Every configured sink, each fed on its own thread
+    pub sinks: SinkSet,
+    #[cfg(feature = "scripting")]
+    pub filter_script: Option<FilterScript>,
+    #[cfg(feature = "anchor")]
+    pub idl: Option<AnchorIdl>,
+}
+
+impl Session {
+    /// Clears the poison a contained handler panic left on the session's
+    /// locks, which would otherwise fail every later update taking them.
+    pub fn clear_poison(&self) {
+        self.messages.clear_poison();
+        self.bandwidth.clear_poison();
+        self.filter_counts.clear_poison();
+        self.tx_counts.clear_poison();
+        self.signatures.clear_poison();
+        self.slot_history.clear_poison();
+        self.account_hashes.clear_poison();
+        self.connection.clear_poison();
+        self.recent_errors.clear_poison();
+        if let Some(lock) = &self.coalescer {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.reorder {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.tokens {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.pipeline {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.checkpoint {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.baseline {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.slot_range {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.slot_rate {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.active_slots {
+            lock.clear_poison();
+        }
+        if let Some(lock) = &self.follow_rate {
+            lock.clear_poison();
+        }
+        if let Some(account_state) = &self.account_state {
+            account_state.clear_poison();
+        }
+    }
+
+    /// Counts a received update towards the message and bandwidth stats.
+    pub fn record_received(&self, update: &SubscribeUpdate) {
+        self.messages.lock().unwrap().record(update);
+        if let Some(slot_rate) = &self.slot_rate {
+            slot_rate.lock().unwrap().message_received();
+        }
+        self.filter_counts.lock().unwrap().record(update);
+        let mut bandwidth = self.bandwidth.lock().unwrap();
+        bandwidth.record(update);
+        if bandwidth.maybe_report(self.stats_interval) {
+            // Also reached by updates the client-side filters drop
+            self.tx_counts.lock().unwrap().flush_stale();
+            if let Some(slot_rate) = &self.slot_rate {
+                slot_rate.lock().unwrap().log();
+            }
+        }
+    }
+
+    /// Hands an update to every configured sink. Write errors are logged
+    /// and counted rather than interrupting the stream.
+    pub fn write_sink(&self, update: &SubscribeUpdate, received: ReceivedAt) {
+        self.sinks.write(update, received);
+    }
+
+    /// Releases held slot log lines once the slot has matching data.
+    pub fn mark_active_slot(&self, slot: u64) {
+        if let Some(active_slots) = &self.active_slots {
+            active_slots.lock().unwrap().data_update(slot);
+        }
+    }
+
+    /// Writes out everything queued for the sinks and stops them, e.g. on
+    /// shutdown.
+    pub fn flush_sink(&self) {
+        self.sinks.finish();
+    }
+
+    /// Records a processed slot in the checkpoint, if one is configured.
+    pub fn checkpoint_slot(&self, slot: u64) {
+        if let Some(checkpoint) = &self.checkpoint
+            && let Err(e) = checkpoint.lock().unwrap().observe(slot)
+        {
+            error!("{:#}", e);
+        }
+    }
+
+    /// Writes the latest checkpoint, e.g. on shutdown.
+    pub fn flush_checkpoint(&self) {
+        if let Some(checkpoint) = &self.checkpoint
+            && let Err(e) = checkpoint.lock().unwrap().flush()
+        {
+            error!("{:#}", e);
+        }
+    }
+
+    /// Writes the rolled-forward account baseline, e.g. on shutdown.
+    pub fn save_baseline(&self) {
+        if let Some(baseline) = &self.baseline
+            && let Err(e) = baseline.lock().unwrap().save()
+        {
+            error!("{:#}", e);
+        }
+    }
+
+    /// Collects the stats of every tracker into a single report.
+    pub fn run_stats(&self) -> RunStats {
+        let bandwidth = self.bandwidth.lock().unwrap();
+        let mut stats = RunStats::new(&self.messages.lock().unwrap(), bandwidth.total_bytes());
+        stats.bytes_by_type = bandwidth.bytes_by_kind().clone();
+        drop(bandwidth);
+        stats.connections = self.connections.load(Ordering::Relaxed);
+        stats.reconnects = stats.connections.saturating_sub(1);
+        stats.recycles = self.recycles.load(Ordering::Relaxed);
+        stats.filtered = self.filtered.load(Ordering::Relaxed);
+        stats.sink_errors = self.sinks.errors();
+        stats.sink_dropped = self.sinks.dropped();
+        stats.unchanged_accounts = self.unchanged_accounts.load(Ordering::Relaxed);
+        stats.handler_panics = self.handler_panics.load(Ordering::Relaxed);
+        stats.schema_errors = self.schema_errors.load(Ordering::Relaxed);
+        stats.unknown_updates = self.unknown_updates.load(Ordering::Relaxed);
+        stats.snapshot_skipped = self.snapshot_skipped.load(Ordering::Relaxed);
+        if let Some(coalescer) = &self.coalescer {
+            stats.coalesced_account_updates = coalescer.lock().unwrap().coalesced();
+        }
+        if let Some(reorder) = &self.reorder {
+            let reorder = reorder.lock().unwrap();
+            stats.reorder_expired_slots = reorder.expired_slots;
+            stats.reorder_late_updates = reorder.late_updates;
+        }
+        if let Some(backfill) = &self.rpc_backfill {
+            stats.rpc_backfilled_slots = backfill.filled.load(Ordering::Relaxed);
+            stats.rpc_missing_slots = backfill.missing.load(Ordering::Relaxed);
+        }
+        if let Some(active_slots) = &self.active_slots {
+            stats.inactive_slots_not_logged = active_slots.lock().unwrap().suppressed();
+        }
+        let recent_errors = self.recent_errors.lock().unwrap();
+        stats.errors_total = recent_errors.total();
+        stats.recent_errors = recent_errors.entries();
+        drop(recent_errors);
+        stats.duplicate_slot_updates = self.duplicate_slot_updates.load(Ordering::Relaxed);
+        stats.commitment_anomalies = self.commitment_anomalies.load(Ordering::Relaxed);
+        stats.forks = self.forks.load(Ordering::Relaxed);
+        stats.invariant_violations = self.invariant_violations.load(Ordering::Relaxed);
+        if let Some(slot_rate) = &self.slot_rate {
+            let stalls = slot_rate.lock().unwrap().stall_counts();
+            stats.slot_stalls = stalls.slot_stalls;
+            stats.no_data_stalls = stalls.no_data;
+        }
+        stats.program_matches = self.program_matches.load(Ordering::Relaxed);
+        stats.program_misses = self.program_misses.load(Ordering::Relaxed);
+        let last_slot = self.last_slot.load(Ordering::Relaxed);
+        stats.last_slot = (last_slot > 0).then_some(last_slot);
+        let tx_counts = self.tx_counts.lock().unwrap();
+        stats.busiest_slot = tx_counts.busiest().map(|(slot, transactions)| BusiestSlot { slot, transactions });
+        stats.late_slot_transactions = tx_counts.late();
+        drop(tx_counts);
+        stats
+    }
+}
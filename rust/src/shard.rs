@@ -0,0 +1,84 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// A slice of slots, `slot % total == index`, for splitting processing
+/// across instances without coordination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shard {
+    pub index: u64,
+    pub total: u64,
+}
+
+impl Shard {
+    pub fn owns(&self, slot: u64) -> bool {
+        slot % self.total == self.index
+    }
+}
+
+impl FromStr for Shard {
+    type Err = String;
+
+    /// Parses `<index>/<total>`, e.g. `0/4`.
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let (index, total) = value
+            .split_once('/')
+            .ok_or_else(|| format!("expected <index>/<total>, got `{value}`"))?;
+        let index: u64 = index.trim().parse().map_err(|_| format!("invalid shard index `{index}`"))?;
+        let total: u64 = total.trim().parse().map_err(|_| format!("invalid shard count `{total}`"))?;
+        if total == 0 || index >= total {
+            return Err(format!("shard index must be below the shard count, got {index}/{total}"));
+        }
+        Ok(Self { index, total })
+    }
+}
+
+impl fmt::Display for Shard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
+impl Serialize for Shard {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_slot_has_exactly_one_owner() {
+        for total in 1..=8 {
+            let shards: Vec<Shard> = (0..total).map(|index| Shard { index, total }).collect();
+            for slot in 276_389_600..276_389_700 {
+                let owners = shards.iter().filter(|shard| shard.owns(slot)).count();
+                assert_eq!(owners, 1, "slot {slot} with {total} shards");
+            }
+        }
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("1/4".parse::<Shard>(), Ok(Shard { index: 1, total: 4 }));
+        assert_eq!(" 0 / 1 ".parse::<Shard>(), Ok(Shard { index: 0, total: 1 }));
+        assert_eq!("3/4".parse::<Shard>().unwrap().to_string(), "3/4");
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range() {
+        assert_eq!(
+            "4/4".parse::<Shard>(),
+            Err("shard index must be below the shard count, got 4/4".to_string())
+        );
+        assert_eq!(
+            "0/0".parse::<Shard>(),
+            Err("shard index must be below the shard count, got 0/0".to_string())
+        );
+        assert_eq!("1".parse::<Shard>(), Err("expected <index>/<total>, got `1`".to_string()));
+        assert_eq!("a/4".parse::<Shard>(), Err("invalid shard index `a`".to_string()));
+        assert_eq!("1/-4".parse::<Shard>(), Err("invalid shard count `-4`".to_string()));
+    }
+}
@@ -0,0 +1,49 @@
+use anyhow::{Result, bail};
+use std::collections::HashSet;
+use tracing::info;
+
+/// Tracks a set of transaction signatures until each has been seen.
+#[derive(Debug, Default)]
+pub struct SignatureWatch {
+    watched: HashSet<String>,
+    pending: HashSet<String>,
+}
+
+impl SignatureWatch {
+    /// Validates the base58 signatures to watch for.
+    pub fn new(signatures: &[String]) -> Result<Self> {
+        for signature in signatures {
+            let valid = bs58::decode(signature)
+                .into_vec()
+                .is_ok_and(|bytes| bytes.len() == 64);
+            if !valid {
+                bail!("invalid transaction signature `{signature}`");
+            }
+        }
+        let watched: HashSet<String> = signatures.iter().cloned().collect();
+        Ok(Self { pending: watched.clone(), watched })
+    }
+
+    pub fn is_active(&self) -> bool {
+        !self.watched.is_empty()
+    }
+
+    /// Returns true if `signature` is watched, logging the first time each
+    /// one lands.
+    pub fn observe(&mut self, signature: &str, slot: u64) -> bool {
+        if self.pending.remove(signature) {
+            info!(
+                "Watched transaction landed: signature={}, slot={} ({} of {} seen)",
+                signature,
+                slot,
+                self.watched.len() - self.pending.len(),
+                self.watched.len()
+            );
+        }
+        self.watched.contains(signature)
+    }
+
+    pub fn all_seen(&self) -> bool {
+        self.is_active() && self.pending.is_empty()
+    }
+}
@@ -0,0 +1,285 @@
+use anyhow::{Context, Result};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tracing::{error, info, warn};
+use yellowstone_grpc_proto::prelude::SubscribeUpdate;
+
+use super::{ReceivedAt, Sink};
+
+/// Updates queued per sink before further ones are dropped for it.
+const QUEUE_CAPACITY: usize = 10_000;
+
+/// How long a sink waits for an update before it is ticked.
+const IDLE_TICK: Duration = Duration::from_millis(250);
+
+enum Message {
+    Update(Arc<SubscribeUpdate>, ReceivedAt),
+    /// Flush and stop
+    Finish,
+}
+
+/// A sink running on its own thread behind a bounded queue.
+struct Worker {
+    name: String,
+    queue: SyncSender<Message>,
+    /// Set while the queue is full, so falling behind is logged once
+    lagging: AtomicBool,
+    dropped: AtomicU64,
+}
+
+/// Fans every update out to all configured sinks.
+///
+/// Each sink gets its updates in processing order on its own thread, so a
+/// slow sink never holds up the stream or the other sinks: once its queue
+/// is full, updates are dropped for that sink only and counted. With
+/// `--strict-order` the stream waits for room instead, so every sink gets
+/// every update, in stream order, at the pace of the slowest sink.
+#[derive(Default)]
+pub struct SinkSet {
+    workers: Vec<Worker>,
+    threads: Mutex<Vec<JoinHandle<()>>>,
+    errors: Arc<AtomicU64>,
+    strict_order: bool,
+}
+
+impl SinkSet {
+    pub fn start(sinks: Vec<Box<dyn Sink>>, strict_order: bool) -> Result<Self> {
+        let errors = Arc::new(AtomicU64::new(0));
+        let mut workers = Vec::with_capacity(sinks.len());
+        let mut threads = Vec::with_capacity(sinks.len());
+        for mut sink in sinks {
+            let name = sink.name().to_string();
+            sink.prepare().with_context(|| format!("the {name} sink is not ready"))?;
+            let (queue, receiver) = mpsc::sync_channel(QUEUE_CAPACITY);
+            let errors = errors.clone();
+            let thread = std::thread::Builder::new()
+                .name(format!("sink-{name}"))
+                .spawn(move || {
+                    loop {
+                        match receiver.recv_timeout(IDLE_TICK) {
+                            Ok(Message::Update(update, received)) => {
+                                if let Err(e) = sink.write(&update, received) {
+                                    error!("{} sink write failed: {:#}", sink.name(), e);
+                                    errors.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                            Err(RecvTimeoutError::Timeout) => {
+                                if let Err(e) = sink.tick() {
+                                    error!("{} sink write failed: {:#}", sink.name(), e);
+                                    errors.fetch_add(1, Ordering::Relaxed);
+                                }
+                            }
+                            Ok(Message::Finish) | Err(RecvTimeoutError::Disconnected) => break,
+                        }
+                    }
+                    if let Err(e) = sink.flush() {
+                        error!("{} sink flush failed: {:#}", sink.name(), e);
+                        errors.fetch_add(1, Ordering::Relaxed);
+                    }
+                })
+                .with_context(|| format!("failed to start the {name} sink thread"))?;
+            workers.push(Worker { name, queue, lagging: AtomicBool::new(false), dropped: AtomicU64::new(0) });
+            threads.push(thread);
+        }
+        Ok(Self { workers, threads: Mutex::new(threads), errors, strict_order })
+    }
+
+    /// Queues an update for every sink without waiting on any of them, or,
+    /// with `strict_order`, waiting until each has room.
+    pub fn write(&self, update: &SubscribeUpdate, received: ReceivedAt) {
+        if self.workers.is_empty() {
+            return;
+        }
+        let update = Arc::new(update.clone());
+        for worker in &self.workers {
+            if self.strict_order {
+                // Fails only once the sink has finished
+                let _ = worker.queue.send(Message::Update(update.clone(), received));
+                continue;
+            }
+            match worker.queue.try_send(Message::Update(update.clone(), received)) {
+                Ok(()) => {
+                    if worker.lagging.swap(false, Ordering::Relaxed) {
+                        info!("{} sink caught up", worker.name);
+                    }
+                }
+                Err(TrySendError::Full(_)) => {
+                    worker.dropped.fetch_add(1, Ordering::Relaxed);
+                    if !worker.lagging.swap(true, Ordering::Relaxed) {
+                        warn!(
+                            "{} sink is falling behind, dropping updates for it until its queue drains",
+                            worker.name
+                        );
+                    }
+                }
+                // Already finished
+                Err(TrySendError::Disconnected(_)) => {}
+            }
+        }
+    }
+
+    /// Writes out everything queued and stops the sinks, e.g. on shutdown.
+    pub fn finish(&self) {
+        for worker in &self.workers {
+            // Blocks until the sink has room, so nothing queued is lost
+            let _ = worker.queue.send(Message::Finish);
+        }
+        for thread in self.threads.lock().unwrap().drain(..) {
+            if thread.join().is_err() {
+                self.errors.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        for worker in &self.workers {
+            let dropped = worker.dropped.load(Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("{} sink dropped {} updates while falling behind", worker.name, dropped);
+            }
+        }
+    }
+
+    /// Updates a sink failed to write, plus failed flushes.
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Updates dropped for sinks that fell behind, summed over sinks.
+    pub fn dropped(&self) -> u64 {
+        self.workers.iter().map(|worker| worker.dropped.load(Ordering::Relaxed)).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use yellowstone_grpc_proto::prelude::{SubscribeUpdateSlot, subscribe_update::UpdateOneof};
+
+    /// Records the slots it is given. While `gate` is set, the first write
+    /// waits for a message on it, holding the sink's queue full.
+    struct SlowSink {
+        slots: Arc<Mutex<Vec<u64>>>,
+        gate: Option<(mpsc::Sender<()>, mpsc::Receiver<()>)>,
+    }
+
+    impl Sink for SlowSink {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        fn prepare(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn write(&mut self, update: &SubscribeUpdate, _: ReceivedAt) -> Result<()> {
+            if let Some((entered, release)) = self.gate.take() {
+                entered.send(()).unwrap();
+                release.recv().unwrap();
+            }
+            if let Some(UpdateOneof::Slot(slot)) = &update.update_oneof {
+                self.slots.lock().unwrap().push(slot.slot);
+            }
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn slot_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Slot(SubscribeUpdateSlot { slot, ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    /// Writes `count` slot updates while the sink is stalled on the first,
+    /// and returns what it received and how many were dropped.
+    fn run(strict_order: bool, count: u64) -> (Vec<u64>, u64) {
+        let slots = Arc::new(Mutex::new(Vec::new()));
+        let (entered, on_entered) = mpsc::channel();
+        let (release, on_release) = mpsc::channel();
+        let sink = SlowSink { slots: slots.clone(), gate: Some((entered, on_release)) };
+        let sinks = SinkSet::start(vec![Box::new(sink)], strict_order).unwrap();
+        sinks.write(&slot_update(0), ReceivedAt::now());
+        on_entered.recv().unwrap();
+        // In strict order the writer blocks once the queue is full, so the
+        // sink is released from another thread meanwhile
+        let releaser = strict_order.then(|| {
+            let release = release.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(50));
+                release.send(()).unwrap();
+            })
+        });
+        for slot in 1..count {
+            sinks.write(&slot_update(slot), ReceivedAt::now());
+        }
+        match releaser {
+            Some(releaser) => releaser.join().unwrap(),
+            None => release.send(()).unwrap(),
+        }
+        sinks.finish();
+        let received = slots.lock().unwrap().clone();
+        (received, sinks.dropped())
+    }
+
+    #[test]
+    fn a_stalled_sink_drops_updates_by_default() {
+        let count = QUEUE_CAPACITY as u64 + 10;
+        let (received, dropped) = run(false, count);
+        // One update is being written and the queue holds the next ones
+        assert_eq!(dropped, 9);
+        assert_eq!(received, (0..=QUEUE_CAPACITY as u64).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn strict_order_delivers_every_update_in_order() {
+        let count = QUEUE_CAPACITY as u64 + 10;
+        let (received, dropped) = run(true, count);
+        assert_eq!(dropped, 0);
+        assert_eq!(received, (0..count).collect::<Vec<_>>());
+    }
+
+    /// Counts the ticks and flushes it gets.
+    struct TickSink(Arc<Mutex<(u32, u32)>>);
+
+    impl Sink for TickSink {
+        fn name(&self) -> &str {
+            "tick"
+        }
+
+        fn prepare(&mut self) -> Result<()> {
+            Ok(())
+        }
+
+        fn write(&mut self, _: &SubscribeUpdate, _: ReceivedAt) -> Result<()> {
+            Ok(())
+        }
+
+        fn tick(&mut self) -> Result<()> {
+            self.0.lock().unwrap().0 += 1;
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            self.0.lock().unwrap().1 += 1;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn idle_sinks_are_ticked_without_being_flushed() {
+        let counts = Arc::new(Mutex::new((0, 0)));
+        let sinks = SinkSet::start(vec![Box::new(TickSink(counts.clone()))], false).unwrap();
+        sinks.write(&slot_update(1), ReceivedAt::now());
+        std::thread::sleep(IDLE_TICK * 3);
+        let (ticks, flushes) = *counts.lock().unwrap();
+        assert!(ticks >= 1, "{ticks} ticks");
+        assert_eq!(flushes, 0);
+        sinks.finish();
+        assert_eq!(counts.lock().unwrap().1, 1);
+    }
+}
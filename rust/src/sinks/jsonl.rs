@@ -0,0 +1,378 @@
+use anyhow::{Context, Result, bail};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use serde_json::{Value, json};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::info;
+use yellowstone_grpc_proto::prelude::*;
+
+use super::{ReceivedAt, SCHEMA_VERSION, Sink};
+use crate::account_fields::AccountFields;
+
+/// Tokens a JSONL path may contain, filled in when the file is closed.
+const SLOT_TOKENS: [&str; 2] = ["{first_slot}", "{last_slot}"];
+
+/// Writes slots, accounts and transactions as one JSON object per line,
+/// with the same fields as the SQLite tables.
+pub struct JsonlSink {
+    writer: Output,
+    naming: Option<SlotNaming>,
+    account_fields: AccountFields,
+    /// `--checksum`: records written so far and the CRC32 of their lines
+    checksum: Option<(u64, crc32fast::Hasher)>,
+}
+
+/// Where the lines go: straight to the buffered writer, or through gzip.
+enum Output {
+    Plain(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+}
+
+impl Output {
+    fn new(writer: impl Write + Send + 'static, compress: bool) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(BufWriter::new(writer));
+        match compress {
+            true => Output::Gzip(GzEncoder::new(writer, Compression::default())),
+            false => Output::Plain(writer),
+        }
+    }
+
+    /// Writes out everything buffered, ending the gzip stream so the file
+    /// decodes completely. Nothing can be written afterwards.
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut writer) => writer.flush(),
+            Output::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(writer) => writer.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(writer) => writer.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// The path the JSONL output is written to: with `compress`, `.gz` is
+/// appended unless the path already ends in it. Stdout stays `-`.
+pub fn output_path(path: &Path, compress: bool) -> PathBuf {
+    if !compress || path == Path::new("-") || path.extension().is_some_and(|extension| extension == "gz") {
+        return path.to_path_buf();
+    }
+    let mut compressed = path.as_os_str().to_owned();
+    compressed.push(".gz");
+    compressed.into()
+}
+
+/// A path template with slot tokens. The file is written under a
+/// `.partial` name and renamed once the slots it holds are known.
+struct SlotNaming {
+    template: String,
+    partial: PathBuf,
+    first_slot: Option<u64>,
+    last_slot: Option<u64>,
+}
+
+impl SlotNaming {
+    fn path(&self, first: &str, last: &str) -> PathBuf {
+        self.template.replace(SLOT_TOKENS[0], first).replace(SLOT_TOKENS[1], last).into()
+    }
+}
+
+impl JsonlSink {
+    /// Creates (or truncates) the file; `-` writes to stdout. A path with
+    /// `{first_slot}` or `{last_slot}` gets them filled in on close. With
+    /// `checksum`, closing appends a footer. With `compress`, the output is
+    /// gzipped and the file name ends in `.gz`.
+    pub fn open(path: &Path, account_fields: AccountFields, checksum: bool, compress: bool) -> Result<Self> {
+        let checksum = checksum.then(|| (0, crc32fast::Hasher::new()));
+        let path = output_path(path, compress);
+        let path = path.as_path();
+        if path == Path::new("-") {
+            info!("Writing updates to JSONL on stdout (schema version {})", SCHEMA_VERSION);
+            return Ok(Self {
+                writer: Output::new(std::io::stdout(), compress),
+                naming: None,
+                account_fields,
+                checksum,
+            });
+        }
+        let naming = slot_naming(path)?;
+        let create = naming.as_ref().map_or(path, |naming| naming.partial.as_path());
+        let file = File::create(create)
+            .with_context(|| format!("failed to create JSONL file {}", create.display()))?;
+        match &naming {
+            Some(naming) => info!(
+                "Writing updates to JSONL {}, renamed to {} on close (schema version {})",
+                create.display(),
+                naming.template,
+                SCHEMA_VERSION
+            ),
+            None => info!("Writing updates to JSONL {} (schema version {})", path.display(), SCHEMA_VERSION),
+        }
+        Ok(Self { writer: Output::new(file, compress), naming, account_fields, checksum })
+    }
+}
+
+/// The slot naming for `path`, if it has slot tokens. Other `{...}`
+/// placeholders are rejected rather than written literally.
+fn slot_naming(path: &Path) -> Result<Option<SlotNaming>> {
+    let template = path.to_str().unwrap_or_default().to_string();
+    let mut rest = template.clone();
+    for token in SLOT_TOKENS {
+        rest = rest.replace(token, "");
+    }
+    if let Some(start) = rest.find('{') {
+        let placeholder = rest[start..].split_inclusive('}').next().unwrap_or_default();
+        bail!(
+            "unknown placeholder `{}` in JSONL path {}, expected {} or {}",
+            placeholder,
+            path.display(),
+            SLOT_TOKENS[0],
+            SLOT_TOKENS[1]
+        );
+    }
+    if rest == template {
+        return Ok(None);
+    }
+    let mut naming = SlotNaming { template, partial: PathBuf::new(), first_slot: None, last_slot: None };
+    let mut partial = naming.path("pending", "pending").into_os_string();
+    partial.push(".partial");
+    naming.partial = partial.into();
+    Ok(Some(naming))
+}
+
+/// The JSON object written for `update`, or `None` for update types that
+/// aren't written. Shared with the WebSocket sink.
+pub fn to_json(update: &SubscribeUpdate, received: ReceivedAt, account_fields: &AccountFields) -> Option<Value> {
+    let mut record = record(update, account_fields)?;
+    record["tags"] = json!(super::tags(update));
+    record["schema_version"] = json!(SCHEMA_VERSION);
+    record["received_at_unix_ns"] = json!(received.unix_ns);
+    record["received_monotonic_ns"] = json!(received.monotonic_ns);
+    Some(record)
+}
+
+fn record(update: &SubscribeUpdate, account_fields: &AccountFields) -> Option<Value> {
+    use subscribe_update::UpdateOneof;
+
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Slot(slot) => Some(json!({
+            "type": "slot",
+            "slot": slot.slot,
+            "parent": slot.parent,
+            "status": crate::slot_status::slot_status_name(slot.status),
+        })),
+        UpdateOneof::Account(account) => {
+            let info = account.account.as_ref()?;
+            let mut record = account_fields.json(account, info);
+            record.insert("type".to_string(), json!("account"));
+            Some(Value::Object(record))
+        }
+        UpdateOneof::Transaction(tx) => {
+            let info = tx.transaction.as_ref()?;
+            Some(json!({
+                "type": "transaction",
+                "slot": tx.slot,
+                "signature": bs58::encode(&info.signature).into_string(),
+                "is_vote": info.is_vote,
+                "failed": info.meta.as_ref().is_some_and(|meta| meta.err.is_some()),
+                "tx_index": info.index,
+                "fee": info.meta.as_ref().map(|meta| meta.fee),
+            }))
+        }
+        UpdateOneof::Block(block) => Some(json!({
+            "type": "block",
+            "slot": block.slot,
+            "blockhash": block.blockhash,
+            "parent_slot": block.parent_slot,
+            "parent_blockhash": block.parent_blockhash,
+            "block_height": block.block_height.as_ref().map(|height| height.block_height),
+            "block_time": block.block_time.as_ref().map(|time| time.timestamp),
+            "executed_transaction_count": block.executed_transaction_count,
+            "entries_count": block.entries_count,
+        })),
+        _ => None,
+    }
+}
+
+impl Sink for JsonlSink {
+    fn name(&self) -> &str {
+        "jsonl"
+    }
+
+    fn prepare(&mut self) -> Result<()> {
+        // Creating the file already proved it writable; this surfaces a
+        // closed stdout
+        self.writer.flush().context("JSONL output is not writable")
+    }
+
+    fn write(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> Result<()> {
+        let Some(record) = to_json(update, received, &self.account_fields) else {
+            return Ok(());
+        };
+        if let Some(naming) = &mut self.naming
+            && let Some(slot) = crate::stats::update_slot(update)
+        {
+            // Updates can arrive out of slot order
+            naming.first_slot = Some(naming.first_slot.map_or(slot, |first| first.min(slot)));
+            naming.last_slot = Some(naming.last_slot.map_or(slot, |last| last.max(slot)));
+        }
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        if let Some((records, hasher)) = &mut self.checksum {
+            *records += 1;
+            hasher.update(&line);
+        }
+        self.writer.write_all(&line)?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Only a clean shutdown gets here, so a file without the footer
+        // was cut short
+        if let Some((records, hasher)) = self.checksum.take() {
+            let crc32 = format!("{:08x}", hasher.finalize());
+            let footer = json!({
+                "type": "footer",
+                "schema_version": SCHEMA_VERSION,
+                "records": records,
+                "crc32": crc32,
+            });
+            serde_json::to_writer(&mut self.writer, &footer)?;
+            self.writer.write_all(b"\n")?;
+            info!("Wrote the JSONL footer: {} records, crc32 {}", records, crc32);
+        }
+        // Closed before renaming, which some platforms require
+        std::mem::replace(&mut self.writer, Output::Plain(Box::new(io::sink())))
+            .finish()
+            .context("failed to flush JSONL output")?;
+        let Some(naming) = self.naming.take() else {
+            return Ok(());
+        };
+        let (Some(first), Some(last)) = (naming.first_slot, naming.last_slot) else {
+            info!("No updates were written, removing {}", naming.partial.display());
+            return fs::remove_file(&naming.partial)
+                .with_context(|| format!("failed to remove {}", naming.partial.display()));
+        };
+        let path = naming.path(&first.to_string(), &last.to_string());
+        fs::rename(&naming.partial, &path)
+            .with_context(|| format!("failed to rename {} to {}", naming.partial.display(), path.display()))?;
+        info!("Renamed JSONL output to {}", path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn slot_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot { slot, ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    /// Writes slot updates for `slots`, in that order, and closes the sink.
+    fn capture(path: &Path, checksum: bool, compress: bool, slots: &[u64]) {
+        let mut sink = JsonlSink::open(path, AccountFields::default(), checksum, compress).unwrap();
+        sink.prepare().unwrap();
+        for slot in slots {
+            sink.write(&slot_update(*slot), ReceivedAt::now()).unwrap();
+        }
+        sink.flush().unwrap();
+    }
+
+    fn lines(contents: &str) -> Vec<Value> {
+        contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    #[test]
+    fn compressed_output_roundtrips() {
+        let dir = tempfile::tempdir().unwrap();
+        capture(&dir.path().join("capture-{first_slot}-{last_slot}.jsonl"), true, true, &[7, 8, 9]);
+
+        let path = dir.path().join("capture-7-9.jsonl.gz");
+        let mut contents = String::new();
+        flate2::read::GzDecoder::new(File::open(&path).unwrap()).read_to_string(&mut contents).unwrap();
+        let records = lines(&contents);
+        let slots: Vec<_> = records[..3].iter().map(|record| record["slot"].as_u64().unwrap()).collect();
+        assert_eq!(slots, [7, 8, 9]);
+        // The footer made it into the gzip stream before it was finished
+        assert_eq!(records[3]["type"], "footer");
+        assert_eq!(records[3]["records"], 3);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn compressed_paths_end_in_gz() {
+        assert_eq!(output_path(Path::new("updates.jsonl"), true), Path::new("updates.jsonl.gz"));
+        assert_eq!(output_path(Path::new("updates.jsonl.gz"), true), Path::new("updates.jsonl.gz"));
+        assert_eq!(output_path(Path::new("updates.jsonl"), false), Path::new("updates.jsonl"));
+        assert_eq!(output_path(Path::new("-"), true), Path::new("-"));
+    }
+
+    #[test]
+    fn slot_names_cover_updates_that_arrive_out_of_order() {
+        let dir = tempfile::tempdir().unwrap();
+        capture(&dir.path().join("capture-{first_slot}-{last_slot}.jsonl"), false, false, &[12, 9, 15, 10]);
+
+        let names: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(names, ["capture-9-15.jsonl"]);
+        let contents = fs::read_to_string(dir.path().join("capture-9-15.jsonl")).unwrap();
+        let slots: Vec<_> = lines(&contents).iter().map(|record| record["slot"].as_u64().unwrap()).collect();
+        assert_eq!(slots, [12, 9, 15, 10]);
+    }
+
+    #[test]
+    fn a_capture_without_updates_leaves_no_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("capture-{first_slot}-{last_slot}.jsonl");
+        let mut sink = JsonlSink::open(&path, AccountFields::default(), false, false).unwrap();
+        assert!(dir.path().join("capture-pending-pending.jsonl.partial").exists());
+        sink.flush().unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn the_footer_counts_and_checksums_the_lines_before_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("updates.jsonl");
+        capture(&path, true, false, &[3, 4, 5, 6]);
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let (body, footer) = contents.trim_end().rsplit_once('\n').unwrap();
+        let footer: Value = serde_json::from_str(footer).unwrap();
+        assert_eq!(footer["type"], "footer");
+        assert_eq!(footer["schema_version"], SCHEMA_VERSION);
+        assert_eq!(footer["records"], 4);
+        // Every record line, newline included, as a reader verifying the file would
+        let expected = format!("{:08x}", crc32fast::hash(format!("{body}\n").as_bytes()));
+        assert_eq!(footer["crc32"], expected);
+    }
+
+    #[test]
+    fn no_footer_without_checksum() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("updates.jsonl");
+        capture(&path, false, false, &[3, 4]);
+
+        let records = lines(&fs::read_to_string(&path).unwrap());
+        assert_eq!(records.len(), 2);
+        assert!(records.iter().all(|record| record["type"] == "slot"));
+    }
+}
@@ -0,0 +1,78 @@
+use anyhow::Result;
+use std::sync::OnceLock;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use yellowstone_grpc_proto::prelude::SubscribeUpdate;
+
+mod fanout;
+pub mod jsonl;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+#[cfg(feature = "ws")]
+pub mod websocket;
+
+pub use fanout::SinkSet;
+
+/// Version of the layout sinks write, recorded in their output and in the
+/// run manifest. Bump it whenever a table or record changes and list the
+/// change under "Output Schema Versions" in the README.
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// The names of the subscription filters an update matched, sorted, as
+/// written in the sinks' `tags`.
+pub fn tags(update: &SubscribeUpdate) -> Vec<&str> {
+    let mut tags: Vec<&str> = update.filters.iter().map(String::as_str).collect();
+    tags.sort_unstable();
+    tags.dedup();
+    tags
+}
+
+/// When an update was received, on the wall clock and on a monotonic clock
+/// that clock adjustments can't move.
+#[derive(Debug, Clone, Copy)]
+pub struct ReceivedAt {
+    /// Nanoseconds since the Unix epoch
+    pub unix_ns: u64,
+    /// Nanoseconds since the first update of the run; only comparable
+    /// within one run
+    pub monotonic_ns: u64,
+}
+
+impl ReceivedAt {
+    pub fn now() -> Self {
+        static RUN_START: OnceLock<Instant> = OnceLock::new();
+        let start = *RUN_START.get_or_init(Instant::now);
+        Self {
+            unix_ns: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_nanos() as u64)
+                .unwrap_or_default(),
+            monotonic_ns: start.elapsed().as_nanos() as u64,
+        }
+    }
+}
+
+/// A destination that receives every processed update. Each sink runs on
+/// its own thread; see [`SinkSet`].
+pub trait Sink: Send {
+    /// Short name used in logs.
+    fn name(&self) -> &str;
+
+    /// Checks the sink can actually be written to, e.g. that a database
+    /// isn't read-only or locked. Called once at startup, before the
+    /// subscription starts, so a broken sink fails the run instead of
+    /// dropping data.
+    fn prepare(&mut self) -> Result<()>;
+
+    /// Accepts one update. Sinks may buffer internally.
+    fn write(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> Result<()>;
+
+    /// Called while no update has arrived for a moment, so a sink that
+    /// buffers can write out what has waited too long. Unlike `flush`, the
+    /// sink stays open. Does nothing by default.
+    fn tick(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Writes out anything still buffered. Called on shutdown.
+    fn flush(&mut self) -> Result<()>;
+}
@@ -0,0 +1,358 @@
+use anyhow::{Context, Result, bail};
+use rusqlite::{Connection, params};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::info;
+use yellowstone_grpc_proto::prelude::*;
+
+use super::{ReceivedAt, SCHEMA_VERSION, Sink};
+
+/// Rows are committed in one transaction once this many are buffered...
+const BATCH_SIZE: usize = 1000;
+/// ...or once the oldest buffered row is this old.
+const BATCH_MAX_AGE: Duration = Duration::from_secs(1);
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS slots (
+        slot INTEGER NOT NULL,
+        parent INTEGER,
+        status TEXT NOT NULL,
+        received_at_unix_ns INTEGER,
+        received_monotonic_ns INTEGER,
+        tags TEXT
+    );
+    CREATE INDEX IF NOT EXISTS slots_slot ON slots (slot);
+
+    CREATE TABLE IF NOT EXISTS accounts (
+        slot INTEGER NOT NULL,
+        pubkey TEXT NOT NULL,
+        owner TEXT NOT NULL,
+        lamports INTEGER NOT NULL,
+        data BLOB NOT NULL,
+        write_version INTEGER NOT NULL,
+        txn_signature TEXT,
+        is_startup INTEGER NOT NULL,
+        received_at_unix_ns INTEGER,
+        received_monotonic_ns INTEGER,
+        tags TEXT
+    );
+    CREATE INDEX IF NOT EXISTS accounts_slot ON accounts (slot);
+
+    CREATE TABLE IF NOT EXISTS transactions (
+        slot INTEGER NOT NULL,
+        signature TEXT NOT NULL,
+        is_vote INTEGER NOT NULL,
+        failed INTEGER NOT NULL,
+        tx_index INTEGER NOT NULL,
+        fee INTEGER,
+        received_at_unix_ns INTEGER,
+        received_monotonic_ns INTEGER,
+        tags TEXT
+    );
+    CREATE INDEX IF NOT EXISTS transactions_slot ON transactions (slot);
+";
+
+enum Row {
+    Slot {
+        slot: u64,
+        parent: Option<u64>,
+        status: String,
+    },
+    Account {
+        slot: u64,
+        pubkey: String,
+        owner: String,
+        lamports: u64,
+        data: Vec<u8>,
+        write_version: u64,
+        txn_signature: Option<String>,
+        is_startup: bool,
+    },
+    Transaction {
+        slot: u64,
+        signature: String,
+        is_vote: bool,
+        failed: bool,
+        tx_index: u64,
+        fee: Option<u64>,
+    },
+}
+
+impl Row {
+    fn from_update(update: &SubscribeUpdate) -> Option<Self> {
+        use subscribe_update::UpdateOneof;
+
+        match update.update_oneof.as_ref()? {
+            UpdateOneof::Slot(slot) => Some(Row::Slot {
+                slot: slot.slot,
+                parent: slot.parent,
+                status: crate::slot_status::slot_status_name(slot.status).into_owned(),
+            }),
+            UpdateOneof::Account(account) => {
+                let info = account.account.as_ref()?;
+                Some(Row::Account {
+                    slot: account.slot,
+                    pubkey: bs58::encode(&info.pubkey).into_string(),
+                    owner: bs58::encode(&info.owner).into_string(),
+                    lamports: info.lamports,
+                    data: info.data.clone(),
+                    write_version: info.write_version,
+                    txn_signature: info.txn_signature.as_ref().map(|sig| bs58::encode(sig).into_string()),
+                    is_startup: account.is_startup,
+                })
+            }
+            UpdateOneof::Transaction(tx) => {
+                let info = tx.transaction.as_ref()?;
+                Some(Row::Transaction {
+                    slot: tx.slot,
+                    signature: bs58::encode(&info.signature).into_string(),
+                    is_vote: info.is_vote,
+                    failed: info.meta.as_ref().is_some_and(|meta| meta.err.is_some()),
+                    tx_index: info.index,
+                    fee: info.meta.as_ref().map(|meta| meta.fee),
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Brings a database written by an older version up to `SCHEMA_VERSION`,
+/// tracked in SQLite's `user_version`, before `SCHEMA` fills in anything
+/// missing. Databases from before versioning report 0 and already have the
+/// version 1 layout.
+fn migrate(connection: &Connection, path: &Path) -> Result<()> {
+    let version: u32 = connection.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    let existing: u32 = connection.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'slots'",
+        [],
+        |row| row.get(0),
+    )?;
+    if existing == 0 {
+        // New database, created at the current version by `SCHEMA`
+    } else if version > SCHEMA_VERSION {
+        bail!(
+            "SQLite database {} uses schema version {}, newer than the {} this build writes",
+            path.display(),
+            version,
+            SCHEMA_VERSION
+        );
+    } else {
+        if version < 2 {
+            // Version 2 added receipt timestamps; older rows keep them NULL
+            connection
+                .execute_batch(
+                    "ALTER TABLE slots ADD COLUMN received_at_unix_ns INTEGER;
+                     ALTER TABLE slots ADD COLUMN received_monotonic_ns INTEGER;
+                     ALTER TABLE accounts ADD COLUMN received_at_unix_ns INTEGER;
+                     ALTER TABLE accounts ADD COLUMN received_monotonic_ns INTEGER;
+                     ALTER TABLE transactions ADD COLUMN received_at_unix_ns INTEGER;
+                     ALTER TABLE transactions ADD COLUMN received_monotonic_ns INTEGER;",
+                )
+                .context("failed to migrate SQLite database to schema version 2")?;
+            info!("Migrated SQLite database {} from schema version {} to 2", path.display(), version);
+        }
+        // Version 3 only added JSONL records
+        if version < 4 {
+            // Version 4 added the matched filter names; older rows keep NULL
+            connection
+                .execute_batch(
+                    "ALTER TABLE slots ADD COLUMN tags TEXT;
+                     ALTER TABLE accounts ADD COLUMN tags TEXT;
+                     ALTER TABLE transactions ADD COLUMN tags TEXT;",
+                )
+                .context("failed to migrate SQLite database to schema version 4")?;
+            info!("Migrated SQLite database {} to schema version 4", path.display());
+        }
+    }
+    connection
+        .execute_batch(&format!("PRAGMA user_version = {SCHEMA_VERSION}"))
+        .context("failed to record SQLite schema version")?;
+    Ok(())
+}
+
+/// Stores slots, accounts and transactions in a local SQLite database.
+pub struct SqliteSink {
+    connection: Connection,
+    /// Rows with their tags as a JSON array
+    batch: Vec<(Row, String, ReceivedAt)>,
+    batch_started: Instant,
+}
+
+impl SqliteSink {
+    /// Opens (or creates) the database and its schema.
+    pub fn open(path: &Path) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("failed to open SQLite database {}", path.display()))?;
+        migrate(&connection, path)?;
+        connection
+            .execute_batch(SCHEMA)
+            .context("failed to create SQLite schema")?;
+        info!("Writing updates to SQLite database {} (schema version {})", path.display(), SCHEMA_VERSION);
+        Ok(Self {
+            connection,
+            batch: Vec::with_capacity(BATCH_SIZE),
+            batch_started: Instant::now(),
+        })
+    }
+
+    fn commit_batch(&mut self) -> Result<()> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        // A failed batch is dropped rather than retried forever, so one bad
+        // write can't wedge the sink
+        let result = self.insert_batch().context("failed to write SQLite batch");
+        self.batch.clear();
+        result
+    }
+
+    fn insert_batch(&mut self) -> Result<()> {
+        let tx = self.connection.transaction()?;
+        {
+            let mut slots = tx.prepare_cached("INSERT INTO slots VALUES (?1, ?2, ?3, ?4, ?5, ?6)")?;
+            let mut accounts =
+                tx.prepare_cached("INSERT INTO accounts VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)")?;
+            let mut transactions =
+                tx.prepare_cached("INSERT INTO transactions VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)")?;
+
+            // SQLite integers are signed; Solana values and nanosecond
+            // timestamps fit comfortably
+            for (row, tags, received) in &self.batch {
+                let unix_ns = received.unix_ns as i64;
+                let monotonic_ns = received.monotonic_ns as i64;
+                match row {
+                    Row::Slot { slot, parent, status } => {
+                        slots.execute(params![
+                            *slot as i64,
+                            parent.map(|p| p as i64),
+                            status,
+                            unix_ns,
+                            monotonic_ns,
+                            tags
+                        ])?;
+                    }
+                    Row::Account {
+                        slot,
+                        pubkey,
+                        owner,
+                        lamports,
+                        data,
+                        write_version,
+                        txn_signature,
+                        is_startup,
+                    } => {
+                        accounts.execute(params![
+                            *slot as i64,
+                            pubkey,
+                            owner,
+                            *lamports as i64,
+                            data,
+                            *write_version as i64,
+                            txn_signature,
+                            is_startup,
+                            unix_ns,
+                            monotonic_ns,
+                            tags
+                        ])?;
+                    }
+                    Row::Transaction {
+                        slot,
+                        signature,
+                        is_vote,
+                        failed,
+                        tx_index,
+                        fee,
+                    } => {
+                        transactions.execute(params![
+                            *slot as i64,
+                            signature,
+                            is_vote,
+                            failed,
+                            *tx_index as i64,
+                            fee.map(|fee| fee as i64),
+                            unix_ns,
+                            monotonic_ns,
+                            tags
+                        ])?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+impl Sink for SqliteSink {
+    fn name(&self) -> &str {
+        "sqlite"
+    }
+
+    fn prepare(&mut self) -> Result<()> {
+        // Takes the write lock without writing anything, which fails on
+        // read-only files and databases another process is writing to
+        self.connection
+            .execute_batch("BEGIN IMMEDIATE; ROLLBACK;")
+            .context("SQLite database is not writable")
+    }
+
+    fn write(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> Result<()> {
+        let Some(row) = Row::from_update(update) else {
+            return Ok(());
+        };
+        if self.batch.is_empty() {
+            self.batch_started = Instant::now();
+        }
+        self.batch.push((row, serde_json::to_string(&super::tags(update))?, received));
+
+        if self.batch.len() >= BATCH_SIZE || self.batch_started.elapsed() >= BATCH_MAX_AGE {
+            self.commit_batch()?;
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        // Without it, the last rows before a quiet spell would wait for the
+        // next update
+        if !self.batch.is_empty() && self.batch_started.elapsed() >= BATCH_MAX_AGE {
+            self.commit_batch()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.commit_batch()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn slot_update(slot: u64) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot { slot, ..Default::default() })),
+            ..Default::default()
+        }
+    }
+
+    fn slot_rows(sink: &SqliteSink) -> i64 {
+        sink.connection.query_row("SELECT COUNT(*) FROM slots", [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn an_idle_tick_commits_a_batch_once_it_is_old_enough() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut sink = SqliteSink::open(&dir.path().join("updates.db")).unwrap();
+        sink.prepare().unwrap();
+        sink.write(&slot_update(7), ReceivedAt::now()).unwrap();
+        sink.tick().unwrap();
+        assert_eq!(slot_rows(&sink), 0);
+
+        sink.batch_started -= BATCH_MAX_AGE;
+        sink.tick().unwrap();
+        assert_eq!(slot_rows(&sink), 1);
+        assert!(sink.batch.is_empty());
+    }
+}
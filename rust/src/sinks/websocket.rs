@@ -0,0 +1,333 @@
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, info, warn};
+use yellowstone_grpc_proto::prelude::SubscribeUpdate;
+
+use super::{ReceivedAt, Sink, jsonl};
+use crate::http::{self, Response};
+
+/// Messages queued per client before further ones are dropped for it.
+const CLIENT_QUEUE: usize = 1_000;
+
+/// Client frames larger than this close the connection; clients only send
+/// control frames.
+const MAX_CLIENT_FRAME: u64 = 4 * 1024;
+
+/// Appended to the client's key to compute `Sec-WebSocket-Accept` (RFC 6455).
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+const OPCODE_TEXT: u8 = 0x1;
+const OPCODE_CLOSE: u8 = 0x8;
+const OPCODE_PING: u8 = 0x9;
+const OPCODE_PONG: u8 = 0xA;
+
+/// Streams every update to connected WebSocket clients as the JSON objects
+/// of the JSONL sink, one per text message.
+pub struct WebSocketSink {
+    clients: Arc<Mutex<Vec<Client>>>,
+}
+
+/// A connected client, fed through its own bounded queue.
+struct Client {
+    peer: SocketAddr,
+    filter: ClientFilter,
+    queue: mpsc::Sender<Arc<str>>,
+    /// Set while the queue is full, so falling behind is logged once
+    lagging: bool,
+    dropped: u64,
+}
+
+/// What a client asked for in its query string; unset fields match
+/// everything.
+#[derive(Default)]
+struct ClientFilter {
+    /// `type=slot,account,transaction`
+    types: Option<HashSet<String>>,
+    /// `pubkey=...`, for account updates
+    pubkeys: Option<HashSet<String>>,
+    /// `owner=...`, for account updates
+    owners: Option<HashSet<String>>,
+}
+
+impl ClientFilter {
+    fn parse(query: &str) -> Result<Self> {
+        let mut filter = Self::default();
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let values = value.split(',').filter(|value| !value.is_empty()).map(str::to_string);
+            let field = match key {
+                "type" => &mut filter.types,
+                "pubkey" => &mut filter.pubkeys,
+                "owner" => &mut filter.owners,
+                _ => bail!("unknown parameter `{key}`, expected type, pubkey or owner"),
+            };
+            field.get_or_insert_default().extend(values);
+        }
+        Ok(filter)
+    }
+
+    fn matches(&self, record: &Value) -> bool {
+        let allows = |set: &Option<HashSet<String>>, field: &str| {
+            set.as_ref().is_none_or(|set| record[field].as_str().is_some_and(|value| set.contains(value)))
+        };
+        if !allows(&self.types, "type") {
+            return false;
+        }
+        record["type"] != "account" || (allows(&self.pubkeys, "pubkey") && allows(&self.owners, "owner"))
+    }
+}
+
+impl WebSocketSink {
+    /// Binds `addr` and starts accepting clients on the current runtime.
+    pub fn open(addr: SocketAddr) -> Result<Self> {
+        let listener = std::net::TcpListener::bind(addr)
+            .with_context(|| format!("failed to bind WebSocket endpoint {addr}"))?;
+        listener.set_nonblocking(true)?;
+        let listener = TcpListener::from_std(listener)?;
+        info!("Streaming updates to WebSocket clients on ws://{}/", addr);
+        let clients = Arc::new(Mutex::new(Vec::new()));
+        tokio::spawn(accept(listener, clients.clone()));
+        Ok(Self { clients })
+    }
+}
+
+impl Sink for WebSocketSink {
+    fn name(&self) -> &str {
+        "websocket"
+    }
+
+    fn prepare(&mut self) -> Result<()> {
+        // Bound in `open`; clients come and go
+        Ok(())
+    }
+
+    fn write(&mut self, update: &SubscribeUpdate, received: ReceivedAt) -> Result<()> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.is_empty() {
+            return Ok(());
+        }
+        let Some(record) = jsonl::to_json(update, received, &Default::default()) else {
+            return Ok(());
+        };
+        let message: Arc<str> = record.to_string().into();
+        clients.retain_mut(|client| {
+            let sent = match client.filter.matches(&record) {
+                true => client.queue.try_send(message.clone()),
+                // Still noticed if it went away
+                false if client.queue.is_closed() => Err(TrySendError::Closed(message.clone())),
+                false => return true,
+            };
+            match sent {
+                Ok(()) => {
+                    if client.lagging {
+                        client.lagging = false;
+                        info!("WebSocket client {} caught up", client.peer);
+                    }
+                    true
+                }
+                Err(TrySendError::Full(_)) => {
+                    if !client.lagging {
+                        client.lagging = true;
+                        warn!("WebSocket client {} is falling behind, dropping updates for it", client.peer);
+                    }
+                    client.dropped += 1;
+                    true
+                }
+                Err(TrySendError::Closed(_)) => {
+                    match client.dropped {
+                        0 => info!("WebSocket client {} disconnected", client.peer),
+                        dropped => info!(
+                            "WebSocket client {} disconnected, {} updates were dropped for it",
+                            client.peer, dropped
+                        ),
+                    }
+                    false
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        // Dropping the queues closes every client with "going away"
+        self.clients.lock().unwrap().clear();
+        Ok(())
+    }
+}
+
+async fn accept(listener: TcpListener, clients: Arc<Mutex<Vec<Client>>>) {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                debug!("WebSocket accept failed: {}", e);
+                continue;
+            }
+        };
+        let clients = clients.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_client(stream, peer, &clients).await {
+                debug!("WebSocket connection from {} failed: {:#}", peer, e);
+            }
+        });
+    }
+}
+
+async fn handle_client(mut stream: TcpStream, peer: SocketAddr, clients: &Mutex<Vec<Client>>) -> Result<()> {
+    let Some(request) = http::read_request(&mut stream).await? else {
+        return http::write_response(&mut stream, &Response::text(400, "malformed request\n")).await;
+    };
+    let upgrade = request.header("upgrade").is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    let (true, Some(key)) = (request.method == "GET" && upgrade, request.header("sec-websocket-key")) else {
+        let response = Response::text(426, "expected a WebSocket upgrade\n");
+        return http::write_response(&mut stream, &response).await;
+    };
+    let filter = match ClientFilter::parse(&request.query) {
+        Ok(filter) => filter,
+        Err(e) => return http::write_response(&mut stream, &Response::text(400, format!("{e}\n"))).await,
+    };
+
+    let digest = ring::digest::digest(
+        &ring::digest::SHA1_FOR_LEGACY_USE_ONLY,
+        format!("{key}{ACCEPT_GUID}").as_bytes(),
+    );
+    let accept = base64::engine::general_purpose::STANDARD.encode(digest.as_ref());
+    let head = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {accept}\r\n\r\n"
+    );
+    stream.write_all(head.as_bytes()).await?;
+
+    let (queue, updates) = mpsc::channel(CLIENT_QUEUE);
+    clients.lock().unwrap().push(Client { peer, filter, queue, lagging: false, dropped: 0 });
+    match request.query.as_str() {
+        "" => info!("WebSocket client {} connected", peer),
+        query => info!("WebSocket client {} connected with {}", peer, query),
+    }
+
+    let (reader, writer) = stream.into_split();
+    let (control, controls) = mpsc::channel(8);
+    tokio::spawn(read_frames(reader, control));
+    write_frames(writer, updates, controls).await
+}
+
+/// Control frames the writer sends on behalf of the reader.
+enum Control {
+    Pong(Vec<u8>),
+    Close(u16),
+}
+
+/// Answers pings and close frames; data frames from clients are ignored.
+async fn read_frames(mut reader: OwnedReadHalf, control: mpsc::Sender<Control>) {
+    loop {
+        let frame = match read_frame(&mut reader).await {
+            Ok(frame) => frame,
+            Err(e) => {
+                debug!("Closing WebSocket client: {:#}", e);
+                // 1002: protocol error
+                let _ = control.send(Control::Close(1002)).await;
+                return;
+            }
+        };
+        match frame {
+            Some((OPCODE_PING, payload)) => {
+                if control.send(Control::Pong(payload)).await.is_err() {
+                    return;
+                }
+            }
+            Some((OPCODE_CLOSE, _)) | None => {
+                // 1000: normal closure
+                let _ = control.send(Control::Close(1000)).await;
+                return;
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+async fn write_frames(
+    mut writer: OwnedWriteHalf,
+    mut updates: mpsc::Receiver<Arc<str>>,
+    mut controls: mpsc::Receiver<Control>,
+) -> Result<()> {
+    loop {
+        tokio::select! {
+            update = updates.recv() => match update {
+                Some(update) => write_frame(&mut writer, OPCODE_TEXT, update.as_bytes()).await?,
+                None => {
+                    // 1001: going away, the client is shutting down
+                    write_frame(&mut writer, OPCODE_CLOSE, &1001u16.to_be_bytes()).await?;
+                    return Ok(());
+                }
+            },
+            control = controls.recv() => match control {
+                Some(Control::Pong(payload)) => write_frame(&mut writer, OPCODE_PONG, &payload).await?,
+                Some(Control::Close(code)) => {
+                    write_frame(&mut writer, OPCODE_CLOSE, &code.to_be_bytes()).await?;
+                    return Ok(());
+                }
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Writes one unmasked, unfragmented frame, as servers send them.
+async fn write_frame(writer: &mut OwnedWriteHalf, opcode: u8, payload: &[u8]) -> Result<()> {
+    let mut frame = Vec::with_capacity(payload.len() + 10);
+    frame.push(0x80 | opcode);
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend_from_slice(&(len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend_from_slice(&(len as u64).to_be_bytes());
+        }
+    }
+    frame.extend_from_slice(payload);
+    writer.write_all(&frame).await?;
+    Ok(())
+}
+
+/// Reads one frame as `(opcode, unmasked payload)`, or `None` once the
+/// client has closed the connection.
+async fn read_frame(reader: &mut OwnedReadHalf) -> Result<Option<(u8, Vec<u8>)>> {
+    let mut head = [0; 2];
+    if let Err(e) = reader.read_exact(&mut head).await {
+        return match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => Ok(None),
+            _ => Err(e.into()),
+        };
+    }
+    let opcode = head[0] & 0x0F;
+    if head[1] & 0x80 == 0 {
+        bail!("client frames must be masked");
+    }
+    let len = match head[1] & 0x7F {
+        126 => u64::from(reader.read_u16().await?),
+        127 => reader.read_u64().await?,
+        len => u64::from(len),
+    };
+    if len > MAX_CLIENT_FRAME {
+        bail!("client frame of {len} bytes is too large");
+    }
+    let mut mask = [0; 4];
+    reader.read_exact(&mut mask).await?;
+    let mut payload = vec![0; len as usize];
+    reader.read_exact(&mut payload).await?;
+    for (index, byte) in payload.iter_mut().enumerate() {
+        *byte ^= mask[index % 4];
+    }
+    Ok(Some((opcode, payload)))
+}
@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+use yellowstone_grpc_proto::prelude::*;
+
+/// How many recent slots are remembered.
+const CAPACITY: usize = 1024;
+
+/// Statuses seen for one slot, as a bitmask indexed by `SlotStatus`, and
+/// its parent once known.
+#[derive(Debug, Default, Clone, Copy)]
+struct SlotEntry {
+    statuses: u8,
+    parent: Option<u64>,
+}
+
+impl SlotEntry {
+    fn has(&self, status: SlotStatus) -> bool {
+        self.statuses & bit(status as i32) != 0
+    }
+
+    /// The highest of confirmed and finalized seen, if any.
+    fn commitment(&self) -> Option<SlotStatus> {
+        [SlotStatus::SlotFinalized, SlotStatus::SlotConfirmed]
+            .into_iter()
+            .find(|status| self.has(*status))
+    }
+}
+
+fn bit(status: i32) -> u8 {
+    1u8 << (status.clamp(0, 7) as u8)
+}
+
+/// A slot status history that a consistent provider can't produce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anomaly {
+    /// A lower commitment status arrived after a higher one, e.g.
+    /// processed after confirmed
+    Downgrade { seen: SlotStatus },
+    /// A confirmed or finalized slot was reported dead
+    DeadAfter { seen: SlotStatus },
+    /// A new slot's parent is older than an already finalized slot, so it
+    /// forks off below finalization
+    ForkBelowFinalized { parent: u64, finalized: u64 },
+}
+
+/// A slot built on a parent that skips slots already seen, so those are
+/// on a competing fork that the new slot abandons.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fork {
+    pub parent: u64,
+    /// Seen slots between `parent` and the new slot, lowest first
+    pub abandoned: Vec<u64>,
+    /// The highest commitment any abandoned slot reached; a confirmed one
+    /// being replaced is rare
+    pub abandoned_commitment: Option<SlotStatus>,
+}
+
+/// What [`SlotHistory::record`] found out about a slot update.
+#[derive(Debug, Default)]
+pub struct Recorded {
+    /// The exact same (slot, status) pair was already seen
+    pub duplicate: bool,
+    pub anomaly: Option<Anomaly>,
+    pub fork: Option<Fork>,
+}
+
+/// Bounded history of slot status notifications for recent slots.
+#[derive(Debug, Default)]
+pub struct SlotHistory {
+    slots: BTreeMap<u64, SlotEntry>,
+    /// The highest slot seen finalized
+    finalized: u64,
+}
+
+impl SlotHistory {
+    /// Records a slot update, noting exact repeats and statuses that
+    /// contradict the slot's history. Status progression such as
+    /// processed -> confirmed -> finalized is neither.
+    pub fn record(&mut self, update: &SubscribeUpdateSlot) -> Recorded {
+        let bit = bit(update.status);
+        let entry = self.slots.entry(update.slot).or_default();
+        let new_slot = entry.statuses == 0;
+        let new_parent = entry.parent.is_none() && update.parent.is_some();
+        entry.parent = entry.parent.or(update.parent);
+        let duplicate = entry.statuses & bit != 0;
+        let anomaly = match (SlotStatus::try_from(update.status), entry.commitment()) {
+            // Repeats are already reported as duplicates
+            _ if duplicate => None,
+            (Ok(SlotStatus::SlotProcessed), Some(seen)) => Some(Anomaly::Downgrade { seen }),
+            (Ok(SlotStatus::SlotConfirmed), Some(seen @ SlotStatus::SlotFinalized)) => {
+                Some(Anomaly::Downgrade { seen })
+            }
+            (Ok(SlotStatus::SlotDead), Some(seen)) => Some(Anomaly::DeadAfter { seen }),
+            _ => None,
+        };
+        entry.statuses |= bit;
+
+        // Replays of old slots after a reconnect sit below finalization
+        // legitimately; only new slots above it must descend from it
+        let fork = match update.parent {
+            Some(parent) if new_slot && update.slot > self.finalized && parent < self.finalized => {
+                Some(Anomaly::ForkBelowFinalized { parent, finalized: self.finalized })
+            }
+            _ => None,
+        };
+        let anomaly = anomaly.or(fork);
+
+        let fork = match update.parent {
+            Some(parent) if new_parent => self.competing(parent, update.slot),
+            _ => None,
+        };
+        if update.status == SlotStatus::SlotFinalized as i32 {
+            self.finalized = self.finalized.max(update.slot);
+        }
+
+        while self.slots.len() > CAPACITY {
+            self.slots.pop_first();
+        }
+        Recorded { duplicate, anomaly, fork }
+    }
+
+    /// The fork `slot` creates by building on `parent`, if slots between
+    /// the two were seen. Dead slots are left out: they already ended
+    /// their fork, and are reported as dead.
+    fn competing(&self, parent: u64, slot: u64) -> Option<Fork> {
+        // `range` panics on an inverted range, and a slot can't be its own
+        // ancestor anyway
+        if parent >= slot {
+            return None;
+        }
+        let abandoned: Vec<(u64, &SlotEntry)> = self
+            .slots
+            .range(parent.saturating_add(1)..slot)
+            .filter(|(_, entry)| !entry.has(SlotStatus::SlotDead))
+            .map(|(slot, entry)| (*slot, entry))
+            .collect();
+        if abandoned.is_empty() {
+            return None;
+        }
+        let abandoned_commitment = abandoned
+            .iter()
+            .filter_map(|(_, entry)| entry.commitment())
+            .max_by_key(|status| *status as i32);
+        Some(Fork {
+            parent,
+            abandoned: abandoned.into_iter().map(|(slot, _)| slot).collect(),
+            abandoned_commitment,
+        })
+    }
+}
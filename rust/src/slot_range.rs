@@ -0,0 +1,53 @@
+use tracing::info;
+
+/// Where an update falls relative to the configured slot range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeCheck {
+    Inside,
+    /// Outside the range, e.g. streamed slightly past `to`; drop it
+    Outside,
+    /// A slot after `to` was announced, so the range is complete
+    Done,
+}
+
+/// Bounds processing to `from..=to` and remembers which slots were seen.
+#[derive(Debug, Default)]
+pub struct SlotRange {
+    from: Option<u64>,
+    to: Option<u64>,
+    first_seen: Option<u64>,
+    last_seen: Option<u64>,
+}
+
+impl SlotRange {
+    pub fn new(from: Option<u64>, to: Option<u64>) -> Self {
+        Self { from, to, ..Default::default() }
+    }
+
+    /// Checks the slot of an update. `is_slot_update` marks slot status
+    /// notifications, the only updates that prove a later slot has started.
+    pub fn check(&mut self, slot: u64, is_slot_update: bool) -> RangeCheck {
+        if self.to.is_some_and(|to| slot > to) {
+            return if is_slot_update { RangeCheck::Done } else { RangeCheck::Outside };
+        }
+        if self.from.is_some_and(|from| slot < from) {
+            return RangeCheck::Outside;
+        }
+        self.first_seen = Some(self.first_seen.map_or(slot, |first| first.min(slot)));
+        self.last_seen = Some(self.last_seen.map_or(slot, |last| last.max(slot)));
+        RangeCheck::Inside
+    }
+
+    /// Where a new subscription should resume so a reconnect doesn't replay
+    /// the slots already processed.
+    pub fn resume_slot(&self) -> Option<u64> {
+        self.last_seen.or(self.from)
+    }
+
+    pub fn log_processed(&self) {
+        match (self.first_seen, self.last_seen) {
+            (Some(first), Some(last)) => info!("Processed slots {}-{}", first, last),
+            _ => info!("No updates were processed within the slot range"),
+        }
+    }
+}
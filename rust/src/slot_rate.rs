@@ -0,0 +1,199 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::connection_state::ConnectionState;
+use crate::session::Session;
+
+/// How often [`watch`] checks whether slots are still advancing.
+const PROGRESS_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Whether slots are advancing, as far as the stream shows.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Progress {
+    #[default]
+    Advancing,
+    /// Pings or other updates still arrive, but no new slot does: most
+    /// likely the cluster has stalled
+    SlotStall,
+    /// Nothing arrives at all, not even pings: most likely the connection
+    NoData,
+}
+
+/// Periods of each kind of stall since the start, for the metrics.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StallCounts {
+    pub progress: Progress,
+    pub slot_stalls: u64,
+    pub no_data: u64,
+}
+
+/// Estimates how many slots the cluster produces per second from the
+/// arrival times of new slots over a sliding window. Mainnet targets about
+/// 2.5 slots per second (400ms slots).
+pub struct SlotRateEstimator {
+    window: Duration,
+    /// Warn when the rate falls below this many slots per second
+    min_rate: f64,
+    /// Arrival time of each new highest slot within the window
+    samples: VecDeque<(Instant, u64)>,
+    started: Instant,
+    /// Set while below `min_rate`, so a dip warns once
+    below: bool,
+    /// Slots stalled once no new slot arrived for this long
+    stall_after: Duration,
+    last_message: Option<Instant>,
+    /// Nothing before this counts towards a stall, e.g. a reconnect
+    quiet_since: Instant,
+    /// When the current `stalls.progress` began
+    progress_since: Instant,
+    stalls: StallCounts,
+}
+
+impl SlotRateEstimator {
+    pub fn new(window: Duration, min_rate: f64, stall_after: Duration) -> Self {
+        let now = Instant::now();
+        Self {
+            window,
+            min_rate,
+            samples: VecDeque::new(),
+            started: now,
+            below: false,
+            stall_after,
+            last_message: None,
+            quiet_since: now,
+            progress_since: now,
+            stalls: StallCounts::default(),
+        }
+    }
+
+    /// Records that a message of any type, pings included, arrived.
+    pub fn message_received(&mut self) {
+        self.last_message = Some(Instant::now());
+    }
+
+    /// Tells whether slots stopped advancing while other messages keep
+    /// arriving, or nothing arrives at all, and logs when that changes.
+    pub fn check_progress(&mut self) {
+        let now = Instant::now();
+        let last_slot = self.samples.back().map_or(self.quiet_since, |(at, _)| (*at).max(self.quiet_since));
+        let last_message = self.last_message.map_or(self.quiet_since, |at| at.max(self.quiet_since));
+        let progress = if now.duration_since(last_slot) < self.stall_after {
+            Progress::Advancing
+        } else if now.duration_since(last_message) < self.stall_after {
+            Progress::SlotStall
+        } else {
+            Progress::NoData
+        };
+        if progress == self.stalls.progress {
+            return;
+        }
+        match progress {
+            Progress::SlotStall => {
+                self.stalls.slot_stalls += 1;
+                warn!(
+                    "No new slot for {:?} while other messages still arrive: slots have stopped advancing, \
+                     likely a cluster stall",
+                    now.duration_since(last_slot)
+                );
+            }
+            Progress::NoData => {
+                self.stalls.no_data += 1;
+                warn!(
+                    "Nothing received for {:?}, not even pings: likely a connection problem rather than the cluster",
+                    now.duration_since(last_message)
+                );
+            }
+            Progress::Advancing => {
+                info!("Slots are advancing again after {:?}", now.duration_since(self.progress_since));
+            }
+        }
+        self.stalls.progress = progress;
+        self.progress_since = now;
+    }
+
+    /// Starts the stall checks over, for while there is no stream to
+    /// expect messages from; the reconnect loop reports that itself.
+    pub fn pause_progress(&mut self) {
+        let now = Instant::now();
+        self.quiet_since = now;
+        self.progress_since = now;
+        self.stalls.progress = Progress::Advancing;
+    }
+
+    pub fn stall_counts(&self) -> StallCounts {
+        self.stalls
+    }
+
+    /// Records a slot update. Only slots newer than any seen so far count,
+    /// so repeated statuses for the same slot don't inflate the rate.
+    pub fn observe(&mut self, slot: u64) {
+        if self.samples.back().is_some_and(|(_, last)| slot <= *last) {
+            return;
+        }
+        let now = Instant::now();
+        self.samples.push_back((now, slot));
+        while self
+            .samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > self.window)
+        {
+            self.samples.pop_front();
+        }
+        self.check();
+        self.check_progress();
+    }
+
+    /// Slots per second over the window, once a full window has passed.
+    /// Skipped slots count, since the leader schedule still spent their
+    /// time.
+    pub fn rate(&self) -> Option<f64> {
+        if self.started.elapsed() < self.window {
+            return None;
+        }
+        let (first_at, first) = self.samples.front()?;
+        let (last_at, last) = self.samples.back()?;
+        let elapsed = last_at.duration_since(*first_at).as_secs_f64();
+        (elapsed > 0.0).then(|| (last - first) as f64 / elapsed)
+    }
+
+    /// Logs the current rate, for the periodic stats.
+    pub fn log(&self) {
+        match self.rate() {
+            Some(rate) => info!("Slot rate: {:.2} slots/s over the last {:?}", rate, self.window),
+            None => info!("Slot rate: not enough data yet (window {:?})", self.window),
+        }
+    }
+
+    fn check(&mut self) {
+        let Some(rate) = self.rate() else { return };
+        if rate < self.min_rate && !self.below {
+            warn!(
+                "Slot rate dropped to {:.2} slots/s over the last {:?}, below {:.2}: the cluster may be struggling",
+                rate, self.window, self.min_rate
+            );
+        } else if rate >= self.min_rate && self.below {
+            info!("Slot rate recovered to {:.2} slots/s", rate);
+        }
+        self.below = rate < self.min_rate;
+    }
+}
+
+/// Checks every second whether slots are still advancing, while a stream
+/// is open.
+pub async fn watch(session: Arc<Session>) {
+    let Some(slot_rate) = &session.slot_rate else {
+        return;
+    };
+    let mut ticker = tokio::time::interval(PROGRESS_CHECK_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let connected = session.connection.lock().unwrap().state == ConnectionState::Connected;
+        let mut slot_rate = slot_rate.lock().unwrap();
+        match connected {
+            true => slot_rate.check_progress(),
+            false => slot_rate.pause_progress(),
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use std::borrow::Cow;
+use yellowstone_grpc_proto::prelude::SlotStatus;
+
+/// Friendly name for a raw `SlotStatus` value.
+///
+/// Every known variant is listed explicitly so a proto upgrade that adds one
+/// shows up as a compile error here, and values newer than the linked proto
+/// are shown as `unknown(<n>)` instead of silently defaulting to processed.
+pub fn slot_status_name(status: i32) -> Cow<'static, str> {
+    match SlotStatus::try_from(status) {
+        Ok(SlotStatus::SlotProcessed) => "processed".into(),
+        Ok(SlotStatus::SlotConfirmed) => "confirmed".into(),
+        Ok(SlotStatus::SlotFinalized) => "finalized".into(),
+        Ok(SlotStatus::SlotFirstShredReceived) => "first_shred_received".into(),
+        Ok(SlotStatus::SlotCompleted) => "completed".into(),
+        Ok(SlotStatus::SlotCreatedBank) => "created_bank".into(),
+        Ok(SlotStatus::SlotDead) => "dead".into(),
+        Err(_) => format!("unknown({status})").into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAMES: [(SlotStatus, &str); 7] = [
+        (SlotStatus::SlotProcessed, "processed"),
+        (SlotStatus::SlotConfirmed, "confirmed"),
+        (SlotStatus::SlotFinalized, "finalized"),
+        (SlotStatus::SlotFirstShredReceived, "first_shred_received"),
+        (SlotStatus::SlotCompleted, "completed"),
+        (SlotStatus::SlotCreatedBank, "created_bank"),
+        (SlotStatus::SlotDead, "dead"),
+    ];
+
+    #[test]
+    fn every_variant_has_its_name() {
+        // Every value the linked proto knows, so a new variant fails here
+        let known: Vec<SlotStatus> = (0..64).filter_map(|value| SlotStatus::try_from(value).ok()).collect();
+        assert_eq!(known.len(), NAMES.len());
+        for status in known {
+            let (_, name) = NAMES.iter().find(|(variant, _)| *variant == status).unwrap();
+            assert_eq!(slot_status_name(status as i32), *name);
+        }
+    }
+
+    #[test]
+    fn unknown_value_is_numeric() {
+        assert_eq!(slot_status_name(99), "unknown(99)");
+        assert_eq!(slot_status_name(-1), "unknown(-1)");
+    }
+}
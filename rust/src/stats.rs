@@ -0,0 +1,423 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+use yellowstone_grpc_proto::prelude::*;
+use yellowstone_grpc_proto::prost::Message;
+
+use crate::recent_errors::RecordedError;
+
+/// Short name of an update's type, used in logs and stats.
+pub fn update_kind(update: &SubscribeUpdate) -> &'static str {
+    use subscribe_update::UpdateOneof;
+
+    match &update.update_oneof {
+        Some(UpdateOneof::Account(_)) => "account",
+        Some(UpdateOneof::Slot(_)) => "slot",
+        Some(UpdateOneof::Transaction(_)) => "transaction",
+        Some(UpdateOneof::TransactionStatus(_)) => "transaction_status",
+        Some(UpdateOneof::Block(_)) => "block",
+        Some(UpdateOneof::BlockMeta(_)) => "block_meta",
+        Some(UpdateOneof::Entry(_)) => "entry",
+        Some(UpdateOneof::Ping(_)) => "ping",
+        Some(UpdateOneof::Pong(_)) => "pong",
+        None => "empty",
+    }
+}
+
+/// The slot an update belongs to, for update types that carry one.
+pub fn update_slot(update: &SubscribeUpdate) -> Option<u64> {
+    use subscribe_update::UpdateOneof;
+
+    match &update.update_oneof {
+        Some(UpdateOneof::Account(account)) => Some(account.slot),
+        Some(UpdateOneof::Slot(slot)) => Some(slot.slot),
+        Some(UpdateOneof::Transaction(tx)) => Some(tx.slot),
+        Some(UpdateOneof::TransactionStatus(status)) => Some(status.slot),
+        Some(UpdateOneof::Block(block)) => Some(block.slot),
+        Some(UpdateOneof::BlockMeta(meta)) => Some(meta.slot),
+        Some(UpdateOneof::Entry(entry)) => Some(entry.slot),
+        Some(UpdateOneof::Ping(_)) | Some(UpdateOneof::Pong(_)) | None => None,
+    }
+}
+
+/// Counts received messages per type and the peak one-second rate.
+pub struct MessageCounter {
+    started: Instant,
+    started_at_unix: u64,
+    by_kind: BTreeMap<&'static str, u64>,
+    total: u64,
+    window_start: Instant,
+    window_count: u64,
+    peak_per_sec: u64,
+}
+
+impl Default for MessageCounter {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            started_at_unix: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_secs())
+                .unwrap_or_default(),
+            by_kind: BTreeMap::new(),
+            total: 0,
+            window_start: now,
+            window_count: 0,
+            peak_per_sec: 0,
+        }
+    }
+}
+
+impl MessageCounter {
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    pub fn record(&mut self, update: &SubscribeUpdate) {
+        *self.by_kind.entry(update_kind(update)).or_default() += 1;
+        self.total += 1;
+
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.window_count = 0;
+        }
+        self.window_count += 1;
+        self.peak_per_sec = self.peak_per_sec.max(self.window_count);
+    }
+}
+
+/// Counted in place of a filter name the subscription doesn't have, so a
+/// provider echoing unexpected names can't grow the metrics without bound.
+pub const OTHER_FILTER: &str = "other";
+
+/// Updates and their encoded bytes under one filter name and type.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FilterCount {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Counts updates per filter name, from each update's `filters`, and type.
+/// An update several filters matched counts towards each of them, so the
+/// filters of one type can add up to more than that type's total.
+#[derive(Debug, Default)]
+pub struct FilterCounter {
+    /// Every filter name in the subscription
+    names: BTreeSet<String>,
+    counts: BTreeMap<(String, &'static str), FilterCount>,
+}
+
+impl FilterCounter {
+    pub fn new(requests: &[SubscribeRequest]) -> Self {
+        let mut names = BTreeSet::new();
+        for request in requests {
+            names.extend(request.slots.keys().cloned());
+            names.extend(request.accounts.keys().cloned());
+            names.extend(request.transactions.keys().cloned());
+            names.extend(request.transactions_status.keys().cloned());
+            names.extend(request.blocks.keys().cloned());
+            names.extend(request.blocks_meta.keys().cloned());
+            names.extend(request.entry.keys().cloned());
+        }
+        Self { names, counts: BTreeMap::new() }
+    }
+
+    pub fn record(&mut self, update: &SubscribeUpdate) {
+        if update.filters.is_empty() {
+            return;
+        }
+        let kind = update_kind(update);
+        let size = update.encoded_len() as u64;
+        for filter in &update.filters {
+            let name = match self.names.contains(filter) {
+                true => filter.as_str(),
+                false => OTHER_FILTER,
+            };
+            let count = self.counts.entry((name.to_string(), kind)).or_default();
+            count.messages += 1;
+            count.bytes += size;
+        }
+    }
+
+    /// Counts by (filter name, update type), in name order.
+    pub fn counts(&self) -> impl Iterator<Item = (&str, &'static str, FilterCount)> {
+        self.counts.iter().map(|((name, kind), count)| (name.as_str(), *kind, *count))
+    }
+}
+
+/// Slot with the most matching transactions.
+#[derive(Debug, Serialize)]
+pub struct BusiestSlot {
+    pub slot: u64,
+    pub transactions: u64,
+}
+
+/// Final summary of a run, logged on shutdown.
+#[derive(Debug, Default, Serialize)]
+pub struct RunStats {
+    pub started_at_unix: u64,
+    pub duration_secs: f64,
+    pub messages_total: u64,
+    pub messages_by_type: BTreeMap<&'static str, u64>,
+    pub bytes_total: u64,
+    pub bytes_by_type: BTreeMap<&'static str, u64>,
+    pub average_messages_per_sec: f64,
+    pub peak_messages_per_sec: u64,
+    pub average_bytes_per_sec: f64,
+    pub connections: u64,
+    pub reconnects: u64,
+    pub recycles: u64,
+    pub filtered: u64,
+    pub unchanged_accounts: u64,
+    pub coalesced_account_updates: u64,
+    pub reorder_expired_slots: u64,
+    pub reorder_late_updates: u64,
+    pub snapshot_skipped: u64,
+    pub inactive_slots_not_logged: u64,
+    pub handler_panics: u64,
+    pub schema_errors: u64,
+    pub unknown_updates: u64,
+    pub sink_errors: u64,
+    pub sink_dropped: u64,
+    pub duplicate_slot_updates: u64,
+    pub commitment_anomalies: u64,
+    pub forks: u64,
+    pub invariant_violations: u64,
+    pub slot_stalls: u64,
+    pub no_data_stalls: u64,
+    pub program_matches: u64,
+    pub program_misses: u64,
+    pub rpc_backfilled_slots: u64,
+    pub rpc_missing_slots: u64,
+    pub last_slot: Option<u64>,
+    pub busiest_slot: Option<BusiestSlot>,
+    pub late_slot_transactions: u64,
+    pub errors_total: u64,
+    /// The last `--error-history` errors, oldest first
+    pub recent_errors: Vec<RecordedError>,
+}
+
+impl RunStats {
+    /// Starts a report from the message and byte totals; the remaining
+    /// counters are filled in by the caller.
+    pub fn new(messages: &MessageCounter, bytes_total: u64) -> Self {
+        let duration_secs = messages.started.elapsed().as_secs_f64();
+        let divisor = duration_secs.max(f64::EPSILON);
+        Self {
+            started_at_unix: messages.started_at_unix,
+            duration_secs,
+            messages_total: messages.total,
+            messages_by_type: messages.by_kind.clone(),
+            bytes_total,
+            average_messages_per_sec: messages.total as f64 / divisor,
+            peak_messages_per_sec: messages.peak_per_sec,
+            average_bytes_per_sec: bytes_total as f64 / divisor,
+            ..Default::default()
+        }
+    }
+
+    /// Logs the report as readable lines.
+    pub fn log(&self) {
+        info!("Run summary:");
+        info!("  duration: {:.1}s", self.duration_secs);
+        info!(
+            "  messages: {} ({:.1}/s average, {}/s peak)",
+            self.messages_total, self.average_messages_per_sec, self.peak_messages_per_sec
+        );
+        for (kind, count) in &self.messages_by_type {
+            info!("    {}: {}", kind, count);
+        }
+        info!(
+            "  bytes: {} ({}/s average)",
+            format_bytes(self.bytes_total as f64),
+            format_bytes(self.average_bytes_per_sec)
+        );
+        for (kind, bytes) in &self.bytes_by_type {
+            info!("    {}: {}", kind, format_bytes(*bytes as f64));
+        }
+        info!(
+            "  connections: {} ({} reconnects, {} planned recycles)",
+            self.connections, self.reconnects, self.recycles
+        );
+        info!("  filtered client-side: {}", self.filtered);
+        info!(
+            "  transactions matching --program: {} ({} dropped)",
+            self.program_matches, self.program_misses
+        );
+        info!("  unchanged account updates suppressed: {}", self.unchanged_accounts);
+        info!("  account updates coalesced within a slot: {}", self.coalesced_account_updates);
+        info!(
+            "  reordered slots released without a status: {} ({} late updates)",
+            self.reorder_expired_slots, self.reorder_late_updates
+        );
+        info!("  snapshot account updates skipped: {}", self.snapshot_skipped);
+        info!("  slot updates not logged for lack of data: {}", self.inactive_slots_not_logged);
+        info!("  duplicate slot updates: {}", self.duplicate_slot_updates);
+        info!("  commitment anomalies: {}", self.commitment_anomalies);
+        info!("  forks: {}", self.forks);
+        info!(
+            "  slot stalls: {} ({} without any data)",
+            self.slot_stalls, self.no_data_stalls
+        );
+        info!("  ordering invariant violations: {}", self.invariant_violations);
+        info!(
+            "  slots backfilled from RPC: {} ({} without a block)",
+            self.rpc_backfilled_slots, self.rpc_missing_slots
+        );
+        info!("  sink errors: {}", self.sink_errors);
+        info!("  updates dropped by lagging sinks: {}", self.sink_dropped);
+        info!("  handler panics: {}", self.handler_panics);
+        info!("  schema errors: {}", self.schema_errors);
+        info!("  unknown or unhandled updates: {}", self.unknown_updates);
+        match self.last_slot {
+            Some(slot) => info!("  last slot: {}", slot),
+            None => info!("  last slot: none"),
+        }
+        if let Some(busiest) = &self.busiest_slot {
+            info!(
+                "  busiest slot: {} ({} matching transactions)",
+                busiest.slot, busiest.transactions
+            );
+        }
+        if self.late_slot_transactions > 0 {
+            info!("  transactions after their slot was counted: {}", self.late_slot_transactions);
+        }
+        info!("  errors: {}", self.errors_total);
+        for error in &self.recent_errors {
+            let offset = error.at_unix_ms as f64 / 1000.0 - self.started_at_unix as f64;
+            let code = error.grpc_code.as_deref().map(|code| format!(" {code}")).unwrap_or_default();
+            info!("    +{:.1}s {}{}: {}", offset, error.class.as_str(), code, error.message);
+        }
+    }
+}
+
+/// Tracks received bytes, using each update's protobuf-encoded size as an
+/// estimate of what the provider meters.
+pub struct BandwidthTracker {
+    started: Instant,
+    total_bytes: u64,
+    bytes_by_kind: BTreeMap<&'static str, u64>,
+    last_report: Instant,
+    bytes_at_last_report: u64,
+}
+
+impl Default for BandwidthTracker {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            started: now,
+            total_bytes: 0,
+            bytes_by_kind: BTreeMap::new(),
+            last_report: now,
+            bytes_at_last_report: 0,
+        }
+    }
+}
+
+impl BandwidthTracker {
+    pub fn total_bytes(&self) -> u64 {
+        self.total_bytes
+    }
+
+    pub fn bytes_by_kind(&self) -> &BTreeMap<&'static str, u64> {
+        &self.bytes_by_kind
+    }
+
+    pub fn record(&mut self, update: &SubscribeUpdate) {
+        let size = update.encoded_len() as u64;
+        self.total_bytes += size;
+        *self.bytes_by_kind.entry(update_kind(update)).or_default() += size;
+    }
+
+    /// Logs a report if at least `interval` has passed since the last one,
+    /// returning whether it did.
+    pub fn maybe_report(&mut self, interval: Duration) -> bool {
+        let due = self.last_report.elapsed() >= interval;
+        if due {
+            self.report();
+        }
+        due
+    }
+
+    /// Logs cumulative totals and the rate since the previous report.
+    pub fn report(&mut self) {
+        let now = Instant::now();
+        let window = now.duration_since(self.last_report).as_secs_f64().max(f64::EPSILON);
+        let rate = (self.total_bytes - self.bytes_at_last_report) as f64 / window;
+        let average = self.total_bytes as f64
+            / now.duration_since(self.started).as_secs_f64().max(f64::EPSILON);
+
+        info!(
+            "Bandwidth: total={}, current={}/s, average={}/s",
+            format_bytes(self.total_bytes as f64),
+            format_bytes(rate),
+            format_bytes(average)
+        );
+        for (kind, bytes) in &self.bytes_by_kind {
+            let share = *bytes as f64 * 100.0 / self.total_bytes.max(1) as f64;
+            info!("  {}: {} ({:.1}%)", kind, format_bytes(*bytes as f64), share);
+        }
+
+        self.last_report = now;
+        self.bytes_at_last_report = self.total_bytes;
+    }
+}
+
+/// Formats a byte count with a binary unit suffix.
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn slot_update(filters: &[&str]) -> SubscribeUpdate {
+        SubscribeUpdate {
+            filters: filters.iter().map(|filter| filter.to_string()).collect(),
+            update_oneof: Some(subscribe_update::UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 1,
+                ..Default::default()
+            })),
+            ..Default::default()
+        }
+    }
+
+    fn counts(counter: &FilterCounter) -> Vec<(&str, &'static str, u64)> {
+        counter.counts().map(|(filter, kind, count)| (filter, kind, count.messages)).collect()
+    }
+
+    #[test]
+    fn counts_by_configured_filter_and_type() {
+        let request = SubscribeRequest {
+            slots: HashMap::from([
+                ("fast".to_string(), SubscribeRequestFilterSlots::default()),
+                ("slow".to_string(), SubscribeRequestFilterSlots::default()),
+            ]),
+            ..Default::default()
+        };
+        let mut counter = FilterCounter::new(&[request]);
+        counter.record(&slot_update(&["fast"]));
+        counter.record(&slot_update(&["fast", "slow"]));
+        // Names the subscription doesn't have share one series
+        counter.record(&slot_update(&["injected-1"]));
+        counter.record(&slot_update(&["injected-2"]));
+        // Pings carry no filter
+        counter.record(&slot_update(&[]));
+        assert_eq!(counts(&counter), vec![("fast", "slot", 2), ("other", "slot", 2), ("slow", "slot", 1)]);
+
+        let size = slot_update(&["fast"]).encoded_len() as u64;
+        let (_, _, fast) = counter.counts().next().unwrap();
+        assert!(fast.bytes >= 2 * size);
+    }
+}
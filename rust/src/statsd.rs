@@ -0,0 +1,138 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tracing::{info, warn};
+
+use crate::metrics::{self, Kind, Sample};
+use crate::session::Session;
+
+/// Lines are packed into datagrams up to this size, which fits the usual
+/// MTU with room for headers.
+const MAX_DATAGRAM: usize = 1432;
+
+/// How labels are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatsdFormat {
+    /// Plain StatsD, labels appended to the name: `geyser_messages_total.slot`
+    Statsd,
+    /// DogStatsD tags: `geyser_messages_total:3|c|#type:slot`
+    Dogstatsd,
+}
+
+/// Pushes the Prometheus metrics to a StatsD agent over UDP.
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    addr: SocketAddr,
+    format: StatsdFormat,
+    interval: Duration,
+    /// Counter totals last sent, as StatsD counters are increments
+    sent: HashMap<String, u64>,
+    /// Set while sends fail, so a missing agent is logged once
+    failing: bool,
+}
+
+impl StatsdExporter {
+    /// Resolves the agent address; UDP needs no handshake, so an agent that
+    /// isn't listening only shows up as failed sends.
+    pub async fn connect(addr: &str, format: StatsdFormat, interval: Duration) -> Result<Self> {
+        let addr = tokio::net::lookup_host(addr)
+            .await
+            .with_context(|| format!("failed to resolve StatsD address {addr}"))?
+            .next()
+            .with_context(|| format!("StatsD address {addr} resolved to nothing"))?;
+        let local: SocketAddr = match addr {
+            SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+            SocketAddr::V6(_) => (std::net::Ipv6Addr::UNSPECIFIED, 0).into(),
+        };
+        let socket = UdpSocket::bind(local).await.context("failed to open a UDP socket for StatsD")?;
+        socket.connect(addr).await.with_context(|| format!("failed to connect the StatsD socket to {addr}"))?;
+        info!("Sending {:?} metrics to {} every {:?}", format, addr, interval);
+        Ok(Self { socket, addr, format, interval, sent: HashMap::new(), failing: false })
+    }
+
+    /// Sends the metrics every interval until the process exits.
+    pub async fn run(mut self, session: Arc<Session>) {
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            let lines = self.lines(&metrics::collect(&session));
+            self.send(&lines).await;
+        }
+    }
+
+    fn lines(&mut self, samples: &[Sample]) -> Vec<String> {
+        let mut lines = Vec::with_capacity(samples.len());
+        for sample in samples {
+            let name = match self.format {
+                StatsdFormat::Statsd => {
+                    let mut name = sample.name.to_string();
+                    for (_, value) in &sample.labels {
+                        name.push('.');
+                        name.push_str(value);
+                    }
+                    name
+                }
+                StatsdFormat::Dogstatsd => sample.name.to_string(),
+            };
+            let tags = match (self.format, sample.labels.is_empty()) {
+                (StatsdFormat::Dogstatsd, false) => {
+                    let tags: Vec<String> = sample.labels.iter().map(|(key, value)| format!("{key}:{value}")).collect();
+                    format!("|#{}", tags.join(","))
+                }
+                _ => String::new(),
+            };
+            let line = match sample.kind {
+                Kind::Counter => {
+                    let previous = self.sent.insert(format!("{name}{tags}"), sample.value).unwrap_or_default();
+                    // A total that went down was reset; send it whole
+                    let delta = sample.value.checked_sub(previous).unwrap_or(sample.value);
+                    if delta == 0 {
+                        continue;
+                    }
+                    format!("{name}:{delta}|c{tags}")
+                }
+                Kind::Gauge => format!("{name}:{}|g{tags}", sample.value),
+            };
+            lines.push(line);
+        }
+        lines
+    }
+
+    async fn send(&mut self, lines: &[String]) {
+        let mut datagram = String::new();
+        for line in lines {
+            if !datagram.is_empty() && datagram.len() + 1 + line.len() > MAX_DATAGRAM {
+                self.send_datagram(&std::mem::take(&mut datagram)).await;
+            }
+            if !datagram.is_empty() {
+                datagram.push('\n');
+            }
+            datagram.push_str(line);
+        }
+        if !datagram.is_empty() {
+            self.send_datagram(&datagram).await;
+        }
+    }
+
+    async fn send_datagram(&mut self, datagram: &str) {
+        match self.socket.send(datagram.as_bytes()).await {
+            Ok(_) if self.failing => {
+                self.failing = false;
+                info!("Sending metrics to StatsD at {} again", self.addr);
+            }
+            Ok(_) => {}
+            Err(e) if !self.failing => {
+                self.failing = true;
+                warn!("Failed to send metrics to StatsD at {}: {}", self.addr, e);
+            }
+            Err(_) => {}
+        }
+    }
+}
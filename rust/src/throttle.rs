@@ -0,0 +1,89 @@
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// Paces how fast the stream is read to an average of `rate` bytes per
+/// second, with bursts of up to one second's worth. While the loop sleeps
+/// it doesn't pull, so HTTP/2 flow control pushes back on the server.
+pub struct Throttle {
+    rate: f64,
+    /// Bytes that may be read right now; negative after a message larger
+    /// than what was left
+    tokens: f64,
+    last_refill: Instant,
+    /// Set while reads are being held back, so it is logged once
+    active: bool,
+    waited: Duration,
+}
+
+impl Throttle {
+    pub fn new(bytes_per_sec: u64) -> Self {
+        Self {
+            rate: bytes_per_sec as f64,
+            tokens: bytes_per_sec as f64,
+            last_refill: Instant::now(),
+            active: false,
+            waited: Duration::ZERO,
+        }
+    }
+
+    /// Takes `bytes` from the bucket, sleeping until it is no longer in
+    /// debt.
+    pub async fn pace(&mut self, bytes: usize) {
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * self.rate).min(self.rate);
+        self.last_refill = now;
+        self.tokens -= bytes as f64;
+
+        if self.tokens >= 0.0 {
+            // Back to full means the stream is slower than the limit again
+            if self.active && self.tokens + bytes as f64 >= self.rate {
+                self.active = false;
+                info!("Throttling stopped after holding reads back for {:?} in total", self.waited);
+            }
+            return;
+        }
+        if !self.active {
+            self.active = true;
+            info!("Throttling reads to {} bytes/s (MAX_BYTES_PER_SEC)", self.rate);
+        }
+        let wait = Duration::from_secs_f64(-self.tokens / self.rate);
+        self.waited += wait;
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Caps connection attempts at `per_minute`, whatever the backoff says:
+/// a bucket of that many attempts, refilled evenly over a minute.
+pub struct ReconnectLimiter {
+    per_minute: u32,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl ReconnectLimiter {
+    pub fn new(per_minute: u32) -> Self {
+        Self { per_minute, tokens: f64::from(per_minute), last_refill: Instant::now() }
+    }
+
+    /// Takes one attempt from the bucket, first waiting for one to refill
+    /// if it is empty.
+    pub async fn acquire(&mut self) {
+        let rate = f64::from(self.per_minute) / 60.0;
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.last_refill).as_secs_f64() * rate)
+            .min(f64::from(self.per_minute));
+        self.last_refill = now;
+        if self.tokens < 1.0 {
+            let wait = Duration::from_secs_f64((1.0 - self.tokens) / rate);
+            warn!(
+                "Reconnect rate limit reached (MAX_RECONNECTS_PER_MINUTE={}), waiting {:?} before the next attempt",
+                self.per_minute, wait
+            );
+            tokio::time::sleep(wait).await;
+            self.tokens = 1.0;
+            self.last_refill = Instant::now();
+        }
+        self.tokens -= 1.0;
+    }
+}
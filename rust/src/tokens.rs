@@ -0,0 +1,74 @@
+use std::time::{Duration, Instant};
+use tonic::Code;
+use tracing::{info, warn};
+
+use crate::config;
+
+/// How long a token rests after the provider rate-limited it.
+const EXHAUSTED_COOLDOWN: Duration = Duration::from_secs(60);
+/// How long a rejected token rests; it may have been rotated or revoked.
+const UNAUTHENTICATED_COOLDOWN: Duration = Duration::from_secs(600);
+
+/// Access tokens to rotate through when the provider rejects or
+/// rate-limits the active one.
+pub struct TokenPool {
+    tokens: Vec<String>,
+    active: usize,
+    cooling_until: Vec<Option<Instant>>,
+}
+
+impl TokenPool {
+    pub fn new(tokens: Vec<String>) -> Self {
+        let cooling_until = vec![None; tokens.len()];
+        Self { tokens, active: 0, cooling_until }
+    }
+
+    pub fn current(&self) -> Option<String> {
+        self.tokens.get(self.active).cloned()
+    }
+
+    /// Puts the active token on cooldown if `code` blames it, and switches
+    /// to the next token that isn't cooling down. If all are, the one that
+    /// becomes available first is used.
+    pub fn on_error(&mut self, code: Code) {
+        let cooldown = match code {
+            Code::Unauthenticated => UNAUTHENTICATED_COOLDOWN,
+            Code::ResourceExhausted => EXHAUSTED_COOLDOWN,
+            _ => return,
+        };
+        let now = Instant::now();
+        self.cooling_until[self.active] = Some(now + cooldown);
+
+        let len = self.tokens.len();
+        let available = (1..len)
+            .map(|offset| (self.active + offset) % len)
+            .find(|index| self.cooling_until[*index].is_none_or(|until| until <= now));
+        let next = match available {
+            Some(index) => index,
+            None => {
+                warn!("All {} access tokens are cooling down, using the one that recovers first", len);
+                (0..len)
+                    .min_by_key(|index| self.cooling_until[*index].unwrap_or(now))
+                    .unwrap_or(self.active)
+            }
+        };
+        if next != self.active {
+            info!(
+                "Switching to access token {} ({} of {}) after {:?} from the provider",
+                config::redact(&self.tokens[next]),
+                next + 1,
+                len,
+                code
+            );
+            self.active = next;
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        self.tokens
+            .iter()
+            .map(|token| config::redact(token))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,170 @@
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Slots that stop receiving transactions for this long are logged even if
+/// no newer slot has been seen yet.
+const STALE_AFTER: Duration = Duration::from_secs(10);
+
+struct PendingSlot {
+    count: u64,
+    last_update: Instant,
+}
+
+/// Counts matching transaction updates per slot and logs each total once the
+/// stream has moved on to a newer slot.
+#[derive(Default)]
+pub struct SlotTxCounter {
+    pending: BTreeMap<u64, PendingSlot>,
+    busiest: Option<(u64, u64)>,
+    /// Every slot up to this one is closed, logged or not
+    closed_through: Option<u64>,
+    /// Transactions that arrived for a closed slot
+    late: u64,
+}
+
+impl SlotTxCounter {
+    /// Counts a transaction in its slot. One for a slot that is already
+    /// closed is only counted as late, so the slot isn't logged twice.
+    pub fn record_transaction(&mut self, slot: u64) {
+        if self.closed_through.is_some_and(|closed| slot <= closed) {
+            self.late += 1;
+            return;
+        }
+        let now = Instant::now();
+        let pending = self
+            .pending
+            .entry(slot)
+            .or_insert(PendingSlot { count: 0, last_update: now });
+        pending.count += 1;
+        pending.last_update = now;
+        self.observe_slot(slot);
+    }
+
+    /// Closes every pending slot older than `slot`.
+    pub fn observe_slot(&mut self, slot: u64) {
+        let newer = self.pending.split_off(&slot);
+        let closed = std::mem::replace(&mut self.pending, newer);
+        for (slot, pending) in closed {
+            self.close(slot, pending.count);
+        }
+        if let Some(older) = slot.checked_sub(1) {
+            self.close_through(older);
+        }
+    }
+
+    /// Transactions that arrived after their slot was closed.
+    pub fn late(&self) -> u64 {
+        self.late
+    }
+
+    /// Logs slots that never saw a newer slot, e.g. at the end of a burst.
+    pub fn flush_stale(&mut self) {
+        self.flush_stale_at(Instant::now());
+    }
+
+    fn flush_stale_at(&mut self, now: Instant) {
+        let stale: Vec<u64> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| now.duration_since(pending.last_update) >= STALE_AFTER)
+            .map(|(slot, _)| *slot)
+            .collect();
+        for slot in stale {
+            if let Some(pending) = self.pending.remove(&slot) {
+                self.close(slot, pending.count);
+            }
+        }
+    }
+
+    /// The slot with the most matching transactions seen so far, including
+    /// slots still pending, so the last slots of a run count at shutdown.
+    pub fn busiest(&self) -> Option<(u64, u64)> {
+        self.pending.iter().fold(self.busiest, |busiest, (slot, pending)| {
+            match busiest.is_none_or(|(_, max)| pending.count > max) {
+                true => Some((*slot, pending.count)),
+                false => busiest,
+            }
+        })
+    }
+
+    fn close(&mut self, slot: u64, count: u64) {
+        info!("Slot {} had {} matching transactions", slot, count);
+        if self.busiest.is_none_or(|(_, max)| count > max) {
+            self.busiest = Some((slot, count));
+        }
+        self.close_through(slot);
+    }
+
+    fn close_through(&mut self, slot: u64) {
+        self.closed_through = Some(self.closed_through.map_or(slot, |closed| closed.max(slot)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn busiest_includes_pending_slots() {
+        let mut counter = SlotTxCounter::default();
+        assert_eq!(counter.busiest(), None);
+        counter.record_transaction(10);
+        counter.record_transaction(10);
+        counter.record_transaction(11);
+        assert_eq!(counter.busiest(), Some((10, 2)));
+        // Slot 11 is still open when the run ends
+        counter.record_transaction(11);
+        counter.record_transaction(11);
+        assert_eq!(counter.busiest(), Some((11, 3)));
+        // A tie keeps the earlier slot
+        counter.record_transaction(12);
+        counter.record_transaction(12);
+        counter.record_transaction(12);
+        assert_eq!(counter.busiest(), Some((11, 3)));
+    }
+
+    #[test]
+    fn stale_slots_close_without_a_newer_slot() {
+        let mut counter = SlotTxCounter::default();
+        counter.record_transaction(10);
+        let recorded = Instant::now();
+        counter.flush_stale_at(recorded);
+        assert!(counter.pending.contains_key(&10));
+        counter.flush_stale_at(recorded + STALE_AFTER);
+        assert!(counter.pending.is_empty());
+        assert_eq!(counter.busiest(), Some((10, 1)));
+    }
+
+    #[test]
+    fn late_transactions_do_not_reopen_a_closed_slot() {
+        let mut counter = SlotTxCounter::default();
+        counter.record_transaction(10);
+        counter.record_transaction(10);
+        counter.record_transaction(11);
+        // Slot 10 was logged when 11 arrived
+        counter.record_transaction(10);
+        assert!(!counter.pending.contains_key(&10));
+        assert_eq!(counter.late(), 1);
+        assert_eq!(counter.busiest(), Some((10, 2)));
+
+        // A slot update closes the slots before it, even ones without transactions
+        counter.observe_slot(20);
+        counter.record_transaction(15);
+        counter.record_transaction(19);
+        assert_eq!(counter.late(), 3);
+        assert!(counter.pending.is_empty());
+        counter.record_transaction(20);
+        assert_eq!(counter.pending[&20].count, 1);
+    }
+
+    #[test]
+    fn stale_slots_stay_closed() {
+        let mut counter = SlotTxCounter::default();
+        counter.record_transaction(10);
+        counter.flush_stale_at(Instant::now() + STALE_AFTER);
+        counter.record_transaction(10);
+        assert!(counter.pending.is_empty());
+        assert_eq!(counter.late(), 1);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::discriminators::hex;
+
+/// `TransactionError` variants, in bincode variant order.
+const TRANSACTION_ERRORS: [&str; 39] = [
+    "AccountInUse",
+    "AccountLoadedTwice",
+    "AccountNotFound",
+    "ProgramAccountNotFound",
+    "InsufficientFundsForFee",
+    "InvalidAccountForFee",
+    "AlreadyProcessed",
+    "BlockhashNotFound",
+    "InstructionError",
+    "CallChainTooDeep",
+    "MissingSignatureForFee",
+    "InvalidAccountIndex",
+    "SignatureFailure",
+    "InvalidProgramForExecution",
+    "SanitizeFailure",
+    "ClusterMaintenance",
+    "AccountBorrowOutstanding",
+    "WouldExceedMaxBlockCostLimit",
+    "UnsupportedVersion",
+    "InvalidWritableAccount",
+    "WouldExceedMaxAccountCostLimit",
+    "WouldExceedAccountDataBlockLimit",
+    "TooManyAccountLocks",
+    "AddressLookupTableNotFound",
+    "InvalidAddressLookupTableOwner",
+    "InvalidAddressLookupTableData",
+    "InvalidAddressLookupTableIndex",
+    "InvalidRentPayingAccount",
+    "WouldExceedMaxVoteCostLimit",
+    "WouldExceedAccountDataTotalLimit",
+    "DuplicateInstruction",
+    "InsufficientFundsForRent",
+    "MaxLoadedAccountsDataSizeExceeded",
+    "InvalidLoadedAccountsDataSizeLimit",
+    "ResanitizationNeeded",
+    "ProgramExecutionTemporarilyRestricted",
+    "UnbalancedTransaction",
+    "ProgramCacheHitMaxLimit",
+    "CommitCancelled",
+];
+
+const INSTRUCTION_ERROR: u32 = 8;
+const DUPLICATE_INSTRUCTION: u32 = 30;
+const INSUFFICIENT_FUNDS_FOR_RENT: u32 = 31;
+const PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED: u32 = 35;
+
+/// `InstructionError` variants, in bincode variant order.
+const INSTRUCTION_ERRORS: [&str; 54] = [
+    "GenericError",
+    "InvalidArgument",
+    "InvalidInstructionData",
+    "InvalidAccountData",
+    "AccountDataTooSmall",
+    "InsufficientFunds",
+    "IncorrectProgramId",
+    "MissingRequiredSignature",
+    "AccountAlreadyInitialized",
+    "UninitializedAccount",
+    "UnbalancedInstruction",
+    "ModifiedProgramId",
+    "ExternalAccountLamportSpend",
+    "ExternalAccountDataModified",
+    "ReadonlyLamportChange",
+    "ReadonlyDataModified",
+    "DuplicateAccountIndex",
+    "ExecutableModified",
+    "RentEpochModified",
+    "NotEnoughAccountKeys",
+    "AccountDataSizeChanged",
+    "AccountNotExecutable",
+    "AccountBorrowFailed",
+    "AccountBorrowOutstanding",
+    "DuplicateAccountOutOfSync",
+    "Custom",
+    "InvalidError",
+    "ExecutableDataModified",
+    "ExecutableLamportChange",
+    "ExecutableAccountNotRentExempt",
+    "UnsupportedProgramId",
+    "CallDepth",
+    "MissingAccount",
+    "ReentrancyNotAllowed",
+    "MaxSeedLengthExceeded",
+    "InvalidSeeds",
+    "InvalidRealloc",
+    "ComputationalBudgetExceeded",
+    "PrivilegeEscalation",
+    "ProgramEnvironmentSetupFailure",
+    "ProgramFailedToComplete",
+    "ProgramFailedToCompile",
+    "Immutable",
+    "IncorrectAuthority",
+    "BorshIoError",
+    "AccountNotRentExempt",
+    "InvalidAccountOwner",
+    "ArithmeticOverflow",
+    "UnsupportedSysvar",
+    "IllegalOwner",
+    "MaxAccountsDataAllocationsExceeded",
+    "MaxAccountsResizeExceeded",
+    "MaxInstructionTraceLengthExceeded",
+    "BuiltinProgramsMustConsumeComputeUnits",
+];
+
+const CUSTOM: u32 = 25;
+const BORSH_IO_ERROR: u32 = 44;
+
+/// Describes a bincode-encoded `TransactionError` from `meta.err`, e.g.
+/// `instruction 2 failed: Custom(6001)`. Errors this table doesn't know,
+/// such as variants added by newer validators, are shown as raw bytes.
+pub fn describe(err: &[u8]) -> String {
+    decode(err).unwrap_or_else(|| format!("undecoded error 0x{}", hex(err)))
+}
+
+fn decode(err: &[u8]) -> Option<String> {
+    let (variant, rest) = read_u32(err)?;
+    let name = TRANSACTION_ERRORS.get(variant as usize)?;
+    Some(match variant {
+        INSTRUCTION_ERROR => {
+            let (&index, rest) = rest.split_first()?;
+            format!("instruction {index} failed: {}", decode_instruction_error(rest)?)
+        }
+        DUPLICATE_INSTRUCTION => format!("{name}({})", rest.first()?),
+        INSUFFICIENT_FUNDS_FOR_RENT | PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED => {
+            format!("{name} {{ account_index: {} }}", rest.first()?)
+        }
+        _ => name.to_string(),
+    })
+}
+
+fn decode_instruction_error(err: &[u8]) -> Option<String> {
+    let (variant, rest) = read_u32(err)?;
+    let name = INSTRUCTION_ERRORS.get(variant as usize)?;
+    Some(match variant {
+        CUSTOM => format!("{name}({})", read_u32(rest)?.0),
+        BORSH_IO_ERROR => {
+            // bincode strings are prefixed with a u64 length
+            let (len, message) = rest.split_first_chunk::<8>()?;
+            let message = message.get(..usize::try_from(u64::from_le_bytes(*len)).ok()?)?;
+            format!("{name}({:?})", String::from_utf8_lossy(message))
+        }
+        _ => name.to_string(),
+    })
+}
+
+fn read_u32(bytes: &[u8]) -> Option<(u32, &[u8])> {
+    let (value, rest) = bytes.split_first_chunk::<4>()?;
+    Some((u32::from_le_bytes(*value), rest))
+}
+
+/// Encodes a `TransactionError` in the JSON form RPC returns it in, e.g.
+/// `{"InstructionError": [2, {"Custom": 6001}]}`, as the bincode bytes the
+/// stream carries. Returns `None` for variants this build doesn't know.
+pub fn encode_json(err: &serde_json::Value) -> Option<Vec<u8>> {
+    let (name, payload) = variant(err)?;
+    let index = TRANSACTION_ERRORS.iter().position(|known| *known == name)? as u32;
+    let mut bytes = index.to_le_bytes().to_vec();
+    match index {
+        INSTRUCTION_ERROR => {
+            let payload = payload?.as_array()?;
+            bytes.push(u8::try_from(payload.first()?.as_u64()?).ok()?);
+            let (name, payload) = variant(payload.get(1)?)?;
+            let index = INSTRUCTION_ERRORS.iter().position(|known| *known == name)? as u32;
+            bytes.extend_from_slice(&index.to_le_bytes());
+            match index {
+                CUSTOM => bytes.extend_from_slice(&u32::try_from(payload?.as_u64()?).ok()?.to_le_bytes()),
+                BORSH_IO_ERROR => {
+                    let message = payload?.as_str()?;
+                    bytes.extend_from_slice(&(message.len() as u64).to_le_bytes());
+                    bytes.extend_from_slice(message.as_bytes());
+                }
+                _ => {}
+            }
+        }
+        DUPLICATE_INSTRUCTION => bytes.push(u8::try_from(payload?.as_u64()?).ok()?),
+        INSUFFICIENT_FUNDS_FOR_RENT | PROGRAM_EXECUTION_TEMPORARILY_RESTRICTED => {
+            bytes.push(u8::try_from(payload?["account_index"].as_u64()?).ok()?);
+        }
+        _ => {}
+    }
+    Some(bytes)
+}
+
+/// Splits serde's externally tagged enum form: `"Name"` or `{"Name": payload}`.
+fn variant(value: &serde_json::Value) -> Option<(&str, Option<&serde_json::Value>)> {
+    match value {
+        serde_json::Value::String(name) => Some((name, None)),
+        serde_json::Value::Object(object) if object.len() == 1 => {
+            object.iter().next().map(|(name, payload)| (name.as_str(), Some(payload)))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,166 @@
+use yellowstone_grpc_proto::prelude::Message;
+
+/// `Vote111111111111111111111111111111111111111`
+pub const VOTE_PROGRAM_ID: [u8; 32] = [
+    7, 97, 72, 29, 53, 116, 116, 187, 124, 77, 118, 36, 235, 211, 189, 179, 216, 53, 94, 115, 209,
+    16, 67, 252, 13, 163, 83, 128, 0, 0, 0, 0,
+];
+
+// Vote instruction discriminants (bincode, u32 little-endian)
+const VOTE: u32 = 2;
+const VOTE_SWITCH: u32 = 6;
+const UPDATE_VOTE_STATE: u32 = 8;
+const UPDATE_VOTE_STATE_SWITCH: u32 = 9;
+const COMPACT_UPDATE_VOTE_STATE: u32 = 12;
+const COMPACT_UPDATE_VOTE_STATE_SWITCH: u32 = 13;
+const TOWER_SYNC: u32 = 14;
+const TOWER_SYNC_SWITCH: u32 = 15;
+
+/// What a vote transaction voted for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoteSummary {
+    /// The fee payer, which for votes is the validator's identity
+    pub validator: String,
+    /// The vote account the vote was cast for
+    pub vote_account: Option<String>,
+    /// Slots the vote covers, oldest first; `None` if the instruction
+    /// couldn't be decoded
+    pub slots: Option<Vec<u64>>,
+    /// The validator's root slot, for instructions that carry one
+    pub root: Option<u64>,
+}
+
+impl VoteSummary {
+    /// Decodes the first vote instruction of `message`, or returns `None`
+    /// if it has none.
+    pub fn from_message(message: &Message) -> Option<Self> {
+        let instruction = message.instructions.iter().find(|instruction| {
+            message
+                .account_keys
+                .get(instruction.program_id_index as usize)
+                .is_some_and(|key| key.as_slice() == VOTE_PROGRAM_ID)
+        })?;
+        let key = |index: Option<&u8>| {
+            index
+                .and_then(|&index| message.account_keys.get(index as usize))
+                .map(|key| bs58::encode(key).into_string())
+        };
+        let decoded = decode_instruction(&instruction.data);
+        Some(Self {
+            validator: key(Some(&0)).unwrap_or_else(|| "<unknown>".to_string()),
+            vote_account: key(instruction.accounts.first()),
+            root: decoded.as_ref().and_then(|(_, root)| *root),
+            slots: decoded.map(|(slots, _)| slots),
+        })
+    }
+
+    /// `voted=<last slot> (<n> slots), root=<root>` or `voted=<undecoded>`,
+    /// for the vote log line.
+    pub fn describe_slots(&self) -> String {
+        let mut line = match self.slots.as_deref() {
+            Some(slots @ [.., last]) => format!("voted={last} ({} slots)", slots.len()),
+            Some([]) => "voted=none".to_string(),
+            None => "voted=<undecoded>".to_string(),
+        };
+        if let Some(root) = self.root {
+            line.push_str(&format!(", root={root}"));
+        }
+        line
+    }
+}
+
+/// Voted slots and root of a vote instruction, or `None` if it is of an
+/// unknown kind or malformed.
+fn decode_instruction(data: &[u8]) -> Option<(Vec<u64>, Option<u64>)> {
+    let mut reader = Reader { data };
+    match reader.u32()? {
+        VOTE | VOTE_SWITCH => {
+            let len = reader.u64()?;
+            let slots = (0..len).map(|_| reader.u64()).collect::<Option<_>>()?;
+            Some((slots, None))
+        }
+        UPDATE_VOTE_STATE | UPDATE_VOTE_STATE_SWITCH => {
+            let len = reader.u64()?;
+            let slots = (0..len)
+                .map(|_| {
+                    let slot = reader.u64()?;
+                    reader.take(4)?; // confirmation count
+                    Some(slot)
+                })
+                .collect::<Option<_>>()?;
+            let root = match reader.u8()? {
+                0 => None,
+                _ => Some(reader.u64()?),
+            };
+            Some((slots, root))
+        }
+        COMPACT_UPDATE_VOTE_STATE | COMPACT_UPDATE_VOTE_STATE_SWITCH | TOWER_SYNC | TOWER_SYNC_SWITCH => {
+            // Lockouts are offsets from the root, each from the previous one
+            let root = reader.u64()?;
+            let len = reader.short_vec_len()?;
+            let mut slot = root;
+            let mut slots = Vec::with_capacity(len);
+            for _ in 0..len {
+                slot = slot.checked_add(reader.varint()?)?;
+                reader.take(1)?; // confirmation count
+                slots.push(slot);
+            }
+            Some((slots, (root != u64::MAX).then_some(root)))
+        }
+        _ => None,
+    }
+}
+
+/// Cursor over bincode-encoded instruction data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> Option<&[u8]> {
+        if self.data.len() < len {
+            return None;
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Some(bytes)
+    }
+
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+    }
+
+    /// LEB128, as written by `serde_varint`.
+    fn varint(&mut self) -> Option<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.u8()?;
+            value |= u64::from(byte & 0x7f).checked_shl(shift)?;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    /// Solana's compact-u16 length prefix.
+    fn short_vec_len(&mut self) -> Option<usize> {
+        let mut len = 0usize;
+        for shift in [0, 7, 14] {
+            let byte = self.u8()?;
+            len |= usize::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Some(len);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,76 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use tracing::info;
+use yellowstone_grpc_proto::prelude::{SubscribeUpdate, subscribe_update::UpdateOneof};
+
+/// Which account updates to process with respect to the startup snapshot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SnapshotMode {
+    /// Snapshot and live updates alike
+    #[default]
+    Include,
+    /// Only live updates, dropping the snapshot
+    Exclude,
+    /// Only the snapshot, dropping live account updates
+    Only,
+}
+
+/// Whether the server flagged `update` as part of the account snapshot it
+/// streams while starting up (`is_startup`).
+pub fn is_startup(update: &SubscribeUpdate) -> bool {
+    matches!(&update.update_oneof, Some(UpdateOneof::Account(account)) if account.is_startup)
+}
+
+/// Detects the switch from the initial account snapshot some providers send
+/// after subscribing to the live stream of changes.
+///
+/// Snapshot entries carry no transaction signature, so the stream is
+/// considered live at the first account update caused by a transaction, or
+/// once any update reports a slot newer than the first one seen.
+#[derive(Default)]
+pub struct Warmup {
+    live: bool,
+    first_slot: Option<u64>,
+    snapshot_updates: u64,
+}
+
+impl Warmup {
+    /// Returns true if `update` belongs to the snapshot phase.
+    pub fn observe(&mut self, update: &SubscribeUpdate) -> bool {
+        if self.live {
+            return false;
+        }
+        let (slot, is_account, caused_by_tx) = match &update.update_oneof {
+            Some(UpdateOneof::Account(account)) => {
+                let caused_by_tx = account
+                    .account
+                    .as_ref()
+                    .is_some_and(|info| info.txn_signature.is_some());
+                (account.slot, true, caused_by_tx)
+            }
+            Some(UpdateOneof::Slot(slot)) => (slot.slot, false, false),
+            Some(UpdateOneof::Transaction(tx)) => (tx.slot, false, false),
+            Some(UpdateOneof::Block(block)) => (block.slot, false, false),
+            _ => return false,
+        };
+
+        let first_slot = *self.first_slot.get_or_insert(slot);
+        if caused_by_tx || slot > first_slot {
+            self.enter_live(slot);
+            return false;
+        }
+        if is_account {
+            self.snapshot_updates += 1;
+        }
+        is_account
+    }
+
+    fn enter_live(&mut self, slot: u64) {
+        self.live = true;
+        info!(
+            "Entering live mode at slot {} after {} snapshot account updates",
+            slot, self.snapshot_updates
+        );
+    }
+}